@@ -0,0 +1,302 @@
+//! API key authentication with HMAC request signing.
+//!
+//! Every request has to carry three headers:
+//! - `X-API-KEY`: the public identifier of the key
+//! - `X-TIMESTAMP`: milliseconds since the unix epoch at signing time
+//! - `X-SIGNATURE`: hex encoded HMAC-SHA256 of `{timestamp}{METHOD}{path?query}{body}`
+//!
+//! Clients sign with their secret. Secrets are never stored in the clear:
+//! the `api_keys` table keeps them encrypted with AES-256-GCM under the
+//! gateway's `authentication.encryption_key`, see [`SecretCipher`], so the
+//! table alone is not enough to sign requests.
+use crate::configuration::AuthenticationSettings;
+use crate::error::ErrorBody;
+use actix_web::{
+    Error, HttpMessage, HttpResponse, ResponseError,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    middleware::Next,
+    web,
+};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+
+pub const API_KEY_HEADER: &str = "X-API-KEY";
+pub const TIMESTAMP_HEADER: &str = "X-TIMESTAMP";
+pub const SIGNATURE_HEADER: &str = "X-SIGNATURE";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The caller behind a correctly signed request, inserted into the request
+/// extensions by [`require_signature`].
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: u64,
+    pub api_key: String,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    MissingHeader(&'static str),
+    InvalidTimestamp,
+    OutsideRecvWindow,
+    UnknownApiKey,
+    InvalidSignature,
+    Database(sqlx::Error),
+    /// The secret was not encrypted under the configured key.
+    Undecryptable,
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::MissingHeader(name) => write!(f, "missing or malformed {} header", name),
+            AuthError::InvalidTimestamp => write!(f, "timestamp is not a valid unix time in ms"),
+            AuthError::OutsideRecvWindow => write!(f, "timestamp is outside the recv window"),
+            AuthError::UnknownApiKey => write!(f, "unknown or revoked api key"),
+            AuthError::InvalidSignature => write!(f, "signature does not match"),
+            AuthError::Database(_) => write!(f, "failed to look up api key"),
+            AuthError::Undecryptable => write!(f, "failed to decrypt the api secret"),
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Database(_) | AuthError::Undecryptable => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let code = match self {
+            AuthError::MissingHeader(_) => "missing_header",
+            AuthError::InvalidTimestamp => "invalid_timestamp",
+            AuthError::OutsideRecvWindow => "outside_recv_window",
+            AuthError::UnknownApiKey => "unknown_api_key",
+            AuthError::InvalidSignature => "invalid_signature",
+            AuthError::Database(_) | AuthError::Undecryptable => "internal_error",
+        };
+        ErrorBody::new(code, self.to_string()).respond(self.status_code())
+    }
+}
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug)]
+pub struct InvalidEncryptionKey;
+
+impl std::fmt::Display for InvalidEncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the encryption key must be 64 hex characters")
+    }
+}
+
+impl std::error::Error for InvalidEncryptionKey {}
+
+/// Encrypts api secrets at rest. Each secret is sealed with a random nonce
+/// and bound to its api key, so rows can't be swapped between keys.
+pub struct SecretCipher {
+    cipher: Aes256Gcm,
+}
+
+impl SecretCipher {
+    /// `key` is the hex encoded 256 bit key.
+    pub fn new(key: &str) -> Result<Self, InvalidEncryptionKey> {
+        let key = hex::decode(key).map_err(|_| InvalidEncryptionKey)?;
+        let cipher = Aes256Gcm::new_from_slice(&key).map_err(|_| InvalidEncryptionKey)?;
+        Ok(SecretCipher { cipher })
+    }
+
+    /// Hex encoded nonce followed by the ciphertext.
+    fn seal(&self, api_key: &str, secret: &str) -> String {
+        let nonce = rand::rng().random::<[u8; NONCE_LEN]>();
+        let payload = Payload {
+            msg: secret.as_bytes(),
+            aad: api_key.as_bytes(),
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .expect("AES-GCM encrypts messages of any reasonable length");
+        hex::encode([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// None when `sealed` was not sealed for `api_key` under this key.
+    fn open(&self, api_key: &str, sealed: &str) -> Option<String> {
+        let sealed = hex::decode(sealed).ok()?;
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: api_key.as_bytes(),
+        };
+        let secret = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .ok()?;
+        String::from_utf8(secret).ok()
+    }
+}
+
+fn request_mac(
+    signing_key: &str,
+    timestamp: &str,
+    method: &str,
+    path_and_query: &str,
+    body: &[u8],
+) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(signing_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(method.as_bytes());
+    mac.update(path_and_query.as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// Computes the hex encoded signature of a request.
+pub fn sign(
+    signing_key: &str,
+    timestamp: &str,
+    method: &str,
+    path_and_query: &str,
+    body: &[u8],
+) -> String {
+    let mac = request_mac(signing_key, timestamp, method, path_and_query, body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Creates a new api key for `user_id` and returns `(api_key, secret)`.
+/// The secret is only ever returned here, the database keeps it encrypted.
+pub async fn issue_api_key(
+    pool: &SqlitePool,
+    cipher: &SecretCipher,
+    user_id: u64,
+) -> Result<(String, String), sqlx::Error> {
+    let mut rng = rand::rng();
+    let api_key = hex::encode(rng.random::<[u8; 16]>());
+    let secret = hex::encode(rng.random::<[u8; 32]>());
+    let encrypted_secret = cipher.seal(&api_key, &secret);
+    let user_id = user_id as i64;
+
+    sqlx::query!(
+        r#"INSERT INTO api_keys (api_key, encrypted_secret, user_id) VALUES ($1, $2, $3)"#,
+        api_key,
+        encrypted_secret,
+        user_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok((api_key, secret))
+}
+
+fn header<'a>(req: &'a ServiceRequest, name: &'static str) -> Result<&'a str, AuthError> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(AuthError::MissingHeader(name))
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_millis() as u64
+}
+
+async fn authenticate(
+    req: &ServiceRequest,
+    body: &[u8],
+    pool: &SqlitePool,
+    settings: &AuthenticationSettings,
+    cipher: &SecretCipher,
+) -> Result<AuthenticatedUser, AuthError> {
+    let api_key = header(req, API_KEY_HEADER)?;
+    let timestamp = header(req, TIMESTAMP_HEADER)?;
+    let signature = header(req, SIGNATURE_HEADER)?;
+
+    let signed_at: u64 = timestamp.parse().map_err(|_| AuthError::InvalidTimestamp)?;
+    if now_millis().abs_diff(signed_at) > settings.recv_window_ms {
+        return Err(AuthError::OutsideRecvWindow);
+    }
+
+    let record = sqlx::query!(
+        r#"SELECT encrypted_secret, user_id FROM api_keys WHERE api_key = $1 AND revoked = 0"#,
+        api_key
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(AuthError::Database)?
+    .ok_or(AuthError::UnknownApiKey)?;
+    // Only keys revoked when secrets started being encrypted have none
+    let encrypted_secret = record.encrypted_secret.ok_or(AuthError::UnknownApiKey)?;
+    let secret = cipher.open(api_key, &encrypted_secret).ok_or_else(|| {
+        tracing::error!(
+            api_key,
+            "an api secret does not decrypt with the configured key"
+        );
+        AuthError::Undecryptable
+    })?;
+
+    let signature = hex::decode(signature).map_err(|_| AuthError::InvalidSignature)?;
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or(req.path());
+
+    request_mac(
+        &secret,
+        timestamp,
+        req.method().as_str(),
+        path_and_query,
+        body,
+    )
+    .verify_slice(&signature)
+    .map_err(|_| AuthError::InvalidSignature)?;
+
+    Ok(AuthenticatedUser {
+        user_id: record.user_id as u64,
+        api_key: api_key.to_string(),
+    })
+}
+
+/// Middleware rejecting any request that isn't signed by a known api key.
+/// On success the [`AuthenticatedUser`] is available to handlers through
+/// `web::ReqData<AuthenticatedUser>`.
+pub async fn require_signature(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let pool = req
+        .app_data::<web::Data<SqlitePool>>()
+        .expect("SqlitePool is not registered as app data")
+        .clone();
+    let settings = req
+        .app_data::<web::Data<AuthenticationSettings>>()
+        .expect("AuthenticationSettings are not registered as app data")
+        .clone();
+    let cipher = req
+        .app_data::<web::Data<SecretCipher>>()
+        .expect("SecretCipher is not registered as app data")
+        .clone();
+
+    // The body is part of the signature, read it and put it back for the handler
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(body.clone().into());
+
+    let user = authenticate(&req, &body, &pool, &settings, &cipher).await?;
+    req.extensions_mut().insert(user);
+
+    next.call(req).await
+}
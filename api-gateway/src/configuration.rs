@@ -1,9 +1,16 @@
+use crate::authentication::{InvalidEncryptionKey, SecretCipher};
+use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
+use sqlx::sqlite::SqliteConnectOptions;
 
 #[derive(serde::Deserialize)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub engine: ApplicationSettings,
+    pub database: DatabaseSettings,
+    pub authentication: AuthenticationSettings,
+    pub rate_limit: RateLimitSettings,
+    pub fees: FeeSettings,
 }
 
 #[derive(serde::Deserialize)]
@@ -13,6 +20,103 @@ pub struct ApplicationSettings {
     pub host: String,
 }
 
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBackend {
+    Sqlite,
+    Postgres,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DatabaseSettings {
+    /// Backend the persistor writes orders, trades and candles to. The
+    /// market data and fills endpoints read them from `file`, so only
+    /// `sqlite` is supported, see [`DatabaseSettings::check_backend`].
+    pub backend: DatabaseBackend,
+    pub file: String,
+}
+
+#[derive(Debug)]
+pub struct UnsupportedBackend(DatabaseBackend);
+
+impl std::fmt::Display for UnsupportedBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the gateway reads persisted events from SQLite only, it cannot serve a persistor \
+            on the {:?} backend: run the persistor on sqlite with the gateway's database file",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedBackend {}
+
+impl DatabaseSettings {
+    /// Fails unless the read endpoints can see what the persistor writes.
+    pub fn check_backend(&self) -> Result<(), UnsupportedBackend> {
+        match self.backend {
+            DatabaseBackend::Sqlite => Ok(()),
+            backend => Err(UnsupportedBackend(backend)),
+        }
+    }
+
+    pub fn get_config(&self) -> SqliteConnectOptions {
+        let base_path = std::env::current_dir().expect("Failed to determine the current directory");
+        let db_file = base_path.join(&self.file);
+        SqliteConnectOptions::default().filename(db_file)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct AuthenticationSettings {
+    /// How far (in milliseconds) a signed request's timestamp may drift from
+    /// the gateway clock before it is rejected.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub recv_window_ms: u64,
+    /// Hex encoded AES-256 key the api secrets are encrypted with. Only
+    /// `local.yml` has one, elsewhere set `APP_AUTHENTICATION__ENCRYPTION_KEY`.
+    pub encryption_key: Secret<String>,
+}
+
+impl AuthenticationSettings {
+    pub fn secret_cipher(&self) -> Result<SecretCipher, InvalidEncryptionKey> {
+        SecretCipher::new(self.encryption_key.expose_secret())
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub per_api_key: TokenBucketSettings,
+    pub per_ip: TokenBucketSettings,
+    /// Tokens taken by requests that create or cancel orders.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub order_entry_weight: u32,
+    /// Tokens taken by read-only requests.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub query_weight: u32,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct TokenBucketSettings {
+    /// Maximum burst, in tokens.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub refill_per_second: u32,
+}
+
+/// Trading fees, in basis points of the traded value.
+#[derive(serde::Deserialize, Clone)]
+pub struct FeeSettings {
+    /// Charged to orders that rested in the book.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub maker_bps: u32,
+    /// Charged to orders that took liquidity.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub taker_bps: u32,
+}
+
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     // Initialise our configuration reader
     let mut settings = config::Config::default();
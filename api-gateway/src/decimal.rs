@@ -0,0 +1,86 @@
+//! Conversions between the decimal strings of the public API and the scaled
+//! integers the engine works with, e.g. "64250.15" USD with a scaling factor
+//! of 2 is 6425015.
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecimalError {
+    Empty,
+    InvalidCharacter,
+    TooPrecise { max_decimals: u8 },
+    Overflow,
+}
+
+impl std::fmt::Display for DecimalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecimalError::Empty => write!(f, "value is empty"),
+            DecimalError::InvalidCharacter => {
+                write!(f, "value must be a plain decimal such as \"12.5\"")
+            }
+            DecimalError::TooPrecise { max_decimals } => {
+                write!(f, "value has more than {} decimal places", max_decimals)
+            }
+            DecimalError::Overflow => write!(f, "value is too large"),
+        }
+    }
+}
+
+impl DecimalError {
+    /// Machine readable code used in API error responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DecimalError::Empty | DecimalError::InvalidCharacter => "invalid_decimal",
+            DecimalError::TooPrecise { .. } => "too_precise",
+            DecimalError::Overflow => "out_of_range",
+        }
+    }
+}
+
+/// Parses an unsigned decimal string into an integer scaled by `10^scale`.
+/// Trailing zeros past `scale` are accepted, any other extra digit is not.
+pub fn parse_scaled(value: &str, scale: u8) -> Result<u64, DecimalError> {
+    let (whole, fraction) = match value.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (value, ""),
+    };
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(DecimalError::Empty);
+    }
+    if !whole
+        .bytes()
+        .chain(fraction.bytes())
+        .all(|b| b.is_ascii_digit())
+    {
+        return Err(DecimalError::InvalidCharacter);
+    }
+
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > scale as usize {
+        return Err(DecimalError::TooPrecise {
+            max_decimals: scale,
+        });
+    }
+
+    let mut scaled: u64 = 0;
+    let padding = std::iter::repeat_n(b'0', scale as usize - fraction.len());
+    for digit in whole.bytes().chain(fraction.bytes()).chain(padding) {
+        scaled = scaled
+            .checked_mul(10)
+            .and_then(|v| v.checked_add((digit - b'0') as u64))
+            .ok_or(DecimalError::Overflow)?;
+    }
+
+    Ok(scaled)
+}
+
+/// Formats a scaled integer back into a decimal string with exactly `scale`
+/// decimal places.
+pub fn format_scaled(value: u64, scale: u8) -> String {
+    if scale == 0 {
+        return value.to_string();
+    }
+
+    let digits = format!("{:0>width$}", value, width = scale as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale as usize);
+    format!("{}.{}", whole, fraction)
+}
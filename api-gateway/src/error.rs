@@ -0,0 +1,128 @@
+//! The JSON body every error response of the gateway shares:
+//!
+//! ```json
+//! {
+//!   "code": "validation_failed",
+//!   "message": "the request failed validation",
+//!   "errors": [{ "field": "price", "code": "too_precise", "message": "..." }]
+//! }
+//! ```
+//!
+//! `code` is stable and meant for machines, `message` for humans.
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FieldError {
+    /// Path of the offending field in the request body, e.g. `price`.
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(
+        field: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        FieldError {
+            field: field.into(),
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl ErrorBody {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        ErrorBody {
+            code,
+            message: message.into(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn respond(self, status: StatusCode) -> HttpResponse {
+        HttpResponse::build(status).json(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// The body was well-formed but one or more fields are invalid.
+    Validation(Vec<FieldError>),
+    /// The body couldn't be deserialized at all.
+    MalformedBody(FieldError),
+    /// The command channel to the engine is closed.
+    EngineUnavailable,
+    /// A request with the same idempotency key is still being processed.
+    IdempotencyKeyInUse,
+    /// The idempotency key was already used for a different request.
+    IdempotencyKeyReused,
+    /// The api key's user may not access the resource.
+    Forbidden,
+    Internal,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Validation(_) => write!(f, "the request failed validation"),
+            ApiError::MalformedBody(_) => write!(f, "the request body is malformed"),
+            ApiError::EngineUnavailable => write!(f, "the matching engine is unavailable"),
+            ApiError::IdempotencyKeyInUse => {
+                write!(f, "a request with this idempotency key is in progress")
+            }
+            ApiError::IdempotencyKeyReused => {
+                write!(f, "this idempotency key was used for a different request")
+            }
+            ApiError::Forbidden => write!(f, "the api key may not access this resource"),
+            ApiError::Internal => write!(f, "internal error"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) | ApiError::MalformedBody(_) => StatusCode::BAD_REQUEST,
+            ApiError::EngineUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::IdempotencyKeyInUse => StatusCode::CONFLICT,
+            ApiError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.body().respond(self.status_code())
+    }
+}
+
+impl ApiError {
+    pub fn body(&self) -> ErrorBody {
+        let (code, errors) = match self {
+            ApiError::Validation(errors) => ("validation_failed", errors.clone()),
+            ApiError::MalformedBody(error) => ("malformed_body", vec![error.clone()]),
+            ApiError::EngineUnavailable => ("engine_unavailable", Vec::new()),
+            ApiError::IdempotencyKeyInUse => ("idempotency_key_in_use", Vec::new()),
+            ApiError::IdempotencyKeyReused => ("idempotency_key_reused", Vec::new()),
+            ApiError::Forbidden => ("forbidden", Vec::new()),
+            ApiError::Internal => ("internal_error", Vec::new()),
+        };
+
+        ErrorBody {
+            code,
+            message: self.to_string(),
+            errors,
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! State of the link to the matching engine, shared between
+//! [`crate::startup::engine_connection_manager`] and the readiness probe.
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+pub struct EngineLink {
+    connected: AtomicBool,
+    /// Unix time in milliseconds of the last command written to the engine,
+    /// 0 until the first one.
+    last_write_ms: AtomicU64,
+}
+
+impl EngineLink {
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub fn record_write(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.last_write_ms.store(now, Ordering::Relaxed);
+    }
+
+    pub fn last_write_ms(&self) -> Option<u64> {
+        match self.last_write_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(ms),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Replays of requests carrying an `Idempotency-Key` header.
+//!
+//! The first request with a given key reserves it (per user), runs, and
+//! stores its response. Retries with the same key and body get the stored
+//! response back instead of reaching the engine a second time.
+use crate::authentication::AuthenticatedUser;
+use crate::error::{ApiError, FieldError};
+use actix_web::{
+    Error, HttpMessage, HttpResponse,
+    body::{BoxBody, MessageBody, to_bytes},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        StatusCode,
+        header::{CONTENT_TYPE, HeaderName, HeaderValue},
+    },
+    middleware::Next,
+    web,
+};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Keys older than this are forgotten and may be used again.
+const KEY_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+struct SavedResponse {
+    status_code: u16,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+impl SavedResponse {
+    fn into_response(self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status_code).unwrap_or(StatusCode::OK);
+        let mut response = HttpResponse::build(status);
+        if let Some(content_type) = self.content_type {
+            response.insert_header((CONTENT_TYPE, content_type));
+        }
+        response
+            .insert_header((REPLAYED_HEADER, HeaderValue::from_static("true")))
+            .body(self.body)
+    }
+}
+
+enum Reservation {
+    /// The key is new, the request has to be processed.
+    Reserved,
+    Completed(SavedResponse),
+    InProgress,
+    /// The key was used before with a different request.
+    Mismatch,
+}
+
+fn fingerprint(req: &ServiceRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str().as_bytes());
+    hasher.update(req.path().as_bytes());
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+async fn reserve(
+    pool: &SqlitePool,
+    user_id: i64,
+    key: &str,
+    fingerprint: &str,
+) -> Result<Reservation, sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2 AND created_at < strftime('%s', 'now') - $3"#,
+        user_id,
+        key,
+        KEY_TTL_SECONDS,
+    )
+    .execute(pool)
+    .await?;
+
+    let inserted = sqlx::query!(
+        r#"INSERT INTO idempotency (user_id, idempotency_key, request_fingerprint)
+        VALUES ($1, $2, $3) ON CONFLICT DO NOTHING"#,
+        user_id,
+        key,
+        fingerprint,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if inserted == 1 {
+        return Ok(Reservation::Reserved);
+    }
+
+    let saved = sqlx::query!(
+        r#"SELECT request_fingerprint, response_status_code, response_content_type, response_body
+        FROM idempotency WHERE user_id = $1 AND idempotency_key = $2"#,
+        user_id,
+        key,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if saved.request_fingerprint != fingerprint {
+        return Ok(Reservation::Mismatch);
+    }
+
+    Ok(match saved.response_status_code {
+        Some(status_code) => Reservation::Completed(SavedResponse {
+            status_code: status_code as u16,
+            content_type: saved.response_content_type,
+            body: saved.response_body.unwrap_or_default(),
+        }),
+        None => Reservation::InProgress,
+    })
+}
+
+async fn save_response(
+    pool: &SqlitePool,
+    user_id: i64,
+    key: &str,
+    response: &SavedResponse,
+) -> Result<(), sqlx::Error> {
+    let status_code = response.status_code as i64;
+    sqlx::query!(
+        r#"UPDATE idempotency
+        SET response_status_code = $1, response_content_type = $2, response_body = $3
+        WHERE user_id = $4 AND idempotency_key = $5"#,
+        status_code,
+        response.content_type,
+        response.body,
+        user_id,
+        key,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Frees a key whose request failed on our side before reaching the engine,
+/// so that it can be retried.
+async fn release(pool: &SqlitePool, user_id: i64, key: &str) {
+    if let Err(e) = sqlx::query!(
+        r#"DELETE FROM idempotency WHERE user_id = $1 AND idempotency_key = $2"#,
+        user_id,
+        key,
+    )
+    .execute(pool)
+    .await
+    {
+        tracing::error!(idempotency_key = key, error = ?e, "failed to release idempotency key");
+    }
+}
+
+fn idempotency_key(req: &ServiceRequest) -> Result<Option<String>, ApiError> {
+    let Some(value) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+
+    match value.to_str() {
+        Ok(key) if (1..=64).contains(&key.len()) => Ok(Some(key.to_string())),
+        _ => Err(ApiError::Validation(vec![FieldError::new(
+            IDEMPOTENCY_KEY_HEADER,
+            "invalid_idempotency_key",
+            "must be 1 to 64 visible ASCII characters",
+        )])),
+    }
+}
+
+/// Middleware replaying the stored response of requests whose
+/// `Idempotency-Key` was seen before, meant to run after authentication.
+pub async fn replay_idempotent(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let user = req.extensions().get::<AuthenticatedUser>().cloned();
+    let (key, user) = match (idempotency_key(&req)?, user) {
+        (Some(key), Some(user)) => (key, user),
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+    let user_id = user.user_id as i64;
+    let pool = req
+        .app_data::<web::Data<SqlitePool>>()
+        .expect("SqlitePool is not registered as app data")
+        .clone();
+
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(body.clone().into());
+    let fingerprint = fingerprint(&req, &body);
+
+    let reservation = reserve(&pool, user_id, &key, &fingerprint)
+        .await
+        .map_err(|e| {
+            tracing::error!(idempotency_key = key, error = ?e, "failed to reserve idempotency key");
+            ApiError::Internal
+        })?;
+    match reservation {
+        Reservation::Reserved => {}
+        Reservation::Completed(saved) => return Ok(req.into_response(saved.into_response())),
+        Reservation::InProgress => return Err(ApiError::IdempotencyKeyInUse.into()),
+        Reservation::Mismatch => return Err(ApiError::IdempotencyKeyReused.into()),
+    }
+
+    // Rate limited requests were not processed either, they may be retried
+    let response = match next.call(req).await {
+        Ok(response)
+            if !response.status().is_server_error()
+                && response.status() != StatusCode::TOO_MANY_REQUESTS =>
+        {
+            response
+        }
+        result => {
+            release(&pool, user_id, &key).await;
+            return result.map(|res| res.map_into_boxed_body());
+        }
+    };
+
+    // From here on the request may have reached the engine. A response that
+    // can't be saved keeps the key reserved, retries are refused as in progress
+    // until it expires rather than placing the order twice.
+    let (req, response) = response.into_parts();
+    let (head, body) = response.into_parts();
+    let Ok(body) = to_bytes(body).await else {
+        tracing::error!(idempotency_key = key, "failed to read the response to save");
+        return Err(ApiError::Internal.into());
+    };
+
+    let saved = SavedResponse {
+        status_code: head.status().as_u16(),
+        content_type: head
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
+        body: body.to_vec(),
+    };
+    if let Err(e) = save_response(&pool, user_id, &key, &saved).await {
+        tracing::error!(idempotency_key = key, error = ?e, "failed to save idempotent response");
+    }
+
+    Ok(ServiceResponse::new(
+        req,
+        head.set_body(body).map_into_boxed_body(),
+    ))
+}
@@ -0,0 +1,79 @@
+use crate::decimal::{DecimalError, format_scaled, parse_scaled};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct Instrument {
+    pub name: String,
+    /// Number of decimal places the engine keeps for amounts of this instrument.
+    pub scaling_factor: u8,
+}
+
+impl Instrument {
+    pub fn parse_amount(&self, value: &str) -> Result<u64, DecimalError> {
+        parse_scaled(value, self.scaling_factor)
+    }
+
+    pub fn format_amount(&self, value: u64) -> String {
+        format_scaled(value, self.scaling_factor)
+    }
+
+    /// Quote amount of `notional`, prices times quantities of this
+    /// instrument. The product is scaled by both instruments, the quote
+    /// amount only by the quote.
+    pub fn quote_amount(&self, notional: u128) -> u64 {
+        (notional / self.unit()) as u64
+    }
+
+    /// Fee of `fee_bps` on `notional`, as [`Instrument::quote_amount`],
+    /// rounded up.
+    pub fn quote_fee(&self, notional: u128, fee_bps: u32) -> u64 {
+        (notional * fee_bps as u128).div_ceil(10_000 * self.unit()) as u64
+    }
+
+    fn unit(&self) -> u128 {
+        10u128.pow(self.scaling_factor as u32)
+    }
+}
+
+/// The instruments the gateway accepts orders for, read from the `instruments`
+/// table at startup.
+#[derive(Debug, Clone, Default)]
+pub struct Instruments {
+    by_name: HashMap<String, Instrument>,
+}
+
+impl Instruments {
+    pub async fn load(pool: &SqlitePool) -> Result<Self, sqlx::Error> {
+        let rows = sqlx::query!(r#"SELECT name, scaling_factor FROM instruments"#)
+            .fetch_all(pool)
+            .await?;
+
+        let by_name = rows
+            .into_iter()
+            .filter_map(|row| row.name.map(|name| (name, row.scaling_factor)))
+            .map(|(name, scaling_factor)| {
+                let instrument = Instrument {
+                    name: name.clone(),
+                    scaling_factor: scaling_factor as u8,
+                };
+                (name, instrument)
+            })
+            .collect();
+
+        Ok(Instruments { by_name })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Instrument> {
+        self.by_name.get(name)
+    }
+
+    /// Base and quote of a pair named like `BTC-USD`.
+    pub fn pair(&self, name: &str) -> Option<(&Instrument, &Instrument)> {
+        let (base, quote) = name.split_once('-')?;
+        if base == quote {
+            return None;
+        }
+        Some((self.get(base)?, self.get(quote)?))
+    }
+}
@@ -1,4 +1,14 @@
+pub mod authentication;
 pub mod configuration;
+pub mod decimal;
+pub mod error;
+pub mod health;
+pub mod idempotency;
+pub mod instruments;
 pub mod messages;
+pub mod metrics;
+pub mod rate_limit;
 pub mod routes;
 pub mod startup;
+pub mod ticker;
+pub mod validation;
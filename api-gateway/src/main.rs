@@ -1,12 +1,17 @@
 use std::net::TcpListener;
 
+use api_gateway::health::EngineLink;
+use api_gateway::instruments::Instruments;
 use api_gateway::messages::trading::WireMessage;
+use api_gateway::ticker::{Tickers, follow_engine};
+use observability::telemetry::{get_subscriber, init_subscriber};
+use sqlx::sqlite::SqlitePoolOptions;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+    let subscriber = get_subscriber("api-gateway".into(), "info".into(), std::io::stdout);
+    init_subscriber(subscriber);
 
     let configuration =
         api_gateway::configuration::get_configuration().expect("Failed to read config file");
@@ -17,13 +22,43 @@ async fn main() -> std::io::Result<()> {
     ))
     .expect("Failed to bind http tcp listener");
 
+    let engine_link = Arc::new(EngineLink::default());
+    let engine_addr = format!(
+        "{}:{}",
+        configuration.engine.host, configuration.engine.port
+    );
     tokio::spawn(api_gateway::startup::engine_connection_manager(
         command_rx,
-        format!(
-            "{}:{}",
-            configuration.engine.host, configuration.engine.port
-        ),
+        engine_addr.clone(),
+        engine_link.clone(),
+    ));
+
+    configuration
+        .database
+        .check_backend()
+        .map_err(std::io::Error::other)?;
+    let connection_pool =
+        SqlitePoolOptions::new().connect_lazy_with(configuration.database.get_config());
+    let instruments = Instruments::load(&connection_pool)
+        .await
+        .expect("Failed to load instruments");
+    let tickers = Arc::new(Tickers::default());
+    tokio::spawn(follow_engine(
+        connection_pool.clone(),
+        engine_addr,
+        tickers.clone(),
     ));
 
-    api_gateway::startup::run_http(http_server_listener, command_tx)?.await
+    api_gateway::startup::run_http(
+        http_server_listener,
+        command_tx,
+        engine_link,
+        connection_pool,
+        instruments,
+        configuration.authentication,
+        configuration.rate_limit,
+        tickers,
+        configuration.fees,
+    )?
+    .await
 }
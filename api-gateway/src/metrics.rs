@@ -0,0 +1,154 @@
+//! Prometheus metrics of the gateway, scraped from `GET /metrics`.
+use crate::health::EngineLink;
+use crate::messages::trading::WireMessage;
+use actix_web::{
+    Error, HttpMessage, HttpResponse,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web,
+};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Unix time in microseconds at which the request reached the gateway,
+/// forwarded to the engine so it can measure end-to-end latency.
+#[derive(Clone, Copy)]
+pub struct ReceivedAt(pub u64);
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    command_queue_depth: IntGauge,
+    engine_connected: IntGauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let http_requests = IntCounterVec::new(
+            Opts::new(
+                "gateway_http_requests_total",
+                "HTTP requests by route and status",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "gateway_http_request_duration_seconds",
+                "Time spent answering HTTP requests",
+            )
+            .buckets(prometheus::exponential_buckets(0.0001, 2.0, 16).unwrap()),
+            &["method", "route"],
+        )
+        .unwrap();
+        let command_queue_depth = IntGauge::new(
+            "gateway_command_queue_depth",
+            "Commands waiting to be written to the engine",
+        )
+        .unwrap();
+        let engine_connected = IntGauge::new(
+            "gateway_engine_connected",
+            "1 while the TCP link to the engine is up",
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(command_queue_depth.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(engine_connected.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            command_queue_depth,
+            engine_connected,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// Middleware stamping requests with [`ReceivedAt`] and counting them, meant
+/// to run before everything else.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    req.extensions_mut().insert(ReceivedAt(now_micros()));
+    let metrics = req
+        .app_data::<web::Data<Metrics>>()
+        .expect("Metrics is not registered as app data")
+        .clone();
+    let method = req.method().to_string();
+
+    let result = next.call(req).await;
+
+    let (route, status) = match &result {
+        Ok(res) => (
+            res.request().match_pattern(),
+            res.status().as_u16().to_string(),
+        ),
+        Err(err) => (
+            None,
+            err.as_response_error().status_code().as_u16().to_string(),
+        ),
+    };
+    let route = route.unwrap_or_else(|| "unmatched".to_string());
+    metrics
+        .http_requests
+        .with_label_values(&[method.as_str(), route.as_str(), status.as_str()])
+        .inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&[method.as_str(), route.as_str()])
+        .observe(started.elapsed().as_secs_f64());
+
+    result
+}
+
+pub async fn metrics(
+    metrics: web::Data<Metrics>,
+    engine_link: web::Data<EngineLink>,
+    command_tx: web::Data<tokio::sync::mpsc::Sender<WireMessage>>,
+) -> HttpResponse {
+    metrics
+        .command_queue_depth
+        .set((command_tx.max_capacity() - command_tx.capacity()) as i64);
+    metrics
+        .engine_connected
+        .set(engine_link.is_connected() as i64);
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render())
+}
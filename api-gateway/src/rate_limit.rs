@@ -0,0 +1,269 @@
+//! Token bucket rate limiting, per client IP and per API key.
+//!
+//! The IP limit runs in front of authentication so that unsigned floods never
+//! reach the database, the API key limit runs after it so that only verified
+//! keys get a bucket.
+use crate::authentication::AuthenticatedUser;
+use crate::configuration::{RateLimitSettings, TokenBucketSettings};
+use crate::error::ErrorBody;
+use actix_web::{
+    Error, HttpMessage, HttpResponse, ResponseError,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        Method, StatusCode,
+        header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    },
+    middleware::Next,
+    web,
+};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+
+/// Buckets are pruned once a limiter tracks this many clients, at most
+/// once per [`PRUNE_INTERVAL`].
+const PRUNE_THRESHOLD: usize = 10_000;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn full(settings: &TokenBucketSettings, now: Instant) -> Self {
+        TokenBucket {
+            tokens: settings.capacity as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, settings: &TokenBucketSettings, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * settings.refill_per_second as f64)
+            .min(settings.capacity as f64);
+        self.last_refill = now;
+    }
+
+    fn try_acquire(
+        &mut self,
+        settings: &TokenBucketSettings,
+        weight: u32,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        self.refill(settings, now);
+        let weight = weight as f64;
+        if self.tokens >= weight {
+            self.tokens -= weight;
+            return Ok(());
+        }
+
+        let missing = weight - self.tokens;
+        Err(Duration::from_secs_f64(
+            missing / settings.refill_per_second.max(1) as f64,
+        ))
+    }
+}
+
+/// Outcome of a bucket check, used to fill in the rate limit headers.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+}
+
+#[derive(Debug)]
+pub struct RateLimited {
+    pub status: RateLimitStatus,
+    pub retry_after: Duration,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rate limit exceeded, retry in {}ms",
+            self.retry_after.as_millis()
+        )
+    }
+}
+
+impl ResponseError for RateLimited {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response =
+            ErrorBody::new("rate_limited", self.to_string()).respond(self.status_code());
+        insert_headers(response.headers_mut(), self.status);
+        // Retry-After only has second granularity, round up so clients don't retry early
+        let retry_after = self.retry_after.as_secs() + (self.retry_after.subsec_nanos() > 0) as u64;
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        response
+    }
+}
+
+fn insert_headers(headers: &mut HeaderMap, status: RateLimitStatus) {
+    headers.insert(RATE_LIMIT_LIMIT, HeaderValue::from(status.limit));
+    headers.insert(RATE_LIMIT_REMAINING, HeaderValue::from(status.remaining));
+}
+
+struct Buckets<K> {
+    by_key: HashMap<K, TokenBucket>,
+    last_pruned: Instant,
+}
+
+struct Limiter<K> {
+    settings: TokenBucketSettings,
+    buckets: Mutex<Buckets<K>>,
+}
+
+impl<K: Eq + Hash> Limiter<K> {
+    fn new(settings: TokenBucketSettings) -> Self {
+        Limiter {
+            settings,
+            buckets: Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                last_pruned: Instant::now(),
+            }),
+        }
+    }
+
+    fn check(&self, key: K, weight: u32) -> Result<RateLimitStatus, RateLimited> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // Pruning is O(n), with many live clients it would otherwise run on every request
+        if buckets.by_key.len() >= PRUNE_THRESHOLD
+            && now.duration_since(buckets.last_pruned) >= PRUNE_INTERVAL
+        {
+            // A bucket that has refilled completely carries no state worth keeping
+            let settings = &self.settings;
+            buckets.by_key.retain(|_, bucket| {
+                bucket.refill(settings, now);
+                bucket.tokens < settings.capacity as f64
+            });
+            buckets.last_pruned = now;
+        }
+
+        let bucket = buckets
+            .by_key
+            .entry(key)
+            .or_insert_with(|| TokenBucket::full(&self.settings, now));
+        let result = bucket.try_acquire(&self.settings, weight, now);
+        let status = RateLimitStatus {
+            limit: self.settings.capacity,
+            remaining: bucket.tokens.floor() as u32,
+        };
+
+        result.map(|_| status).map_err(|retry_after| RateLimited {
+            status,
+            retry_after,
+        })
+    }
+}
+
+pub struct RateLimiter {
+    per_api_key: Limiter<String>,
+    per_ip: Limiter<IpAddr>,
+    order_entry_weight: u32,
+    query_weight: u32,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        RateLimiter {
+            per_api_key: Limiter::new(settings.per_api_key),
+            per_ip: Limiter::new(settings.per_ip),
+            order_entry_weight: settings.order_entry_weight,
+            query_weight: settings.query_weight,
+        }
+    }
+
+    /// Read-only requests are cheaper than the ones that reach the engine.
+    fn weight(&self, method: &Method) -> u32 {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => self.query_weight,
+            _ => self.order_entry_weight,
+        }
+    }
+
+    pub fn check_ip(&self, ip: IpAddr, method: &Method) -> Result<RateLimitStatus, RateLimited> {
+        self.per_ip.check(ip, self.weight(method))
+    }
+
+    pub fn check_api_key(
+        &self,
+        api_key: &str,
+        method: &Method,
+    ) -> Result<RateLimitStatus, RateLimited> {
+        self.per_api_key
+            .check(api_key.to_string(), self.weight(method))
+    }
+
+    /// Charges the items of a batch beyond the first, which
+    /// [`limit_by_api_key`] charged like a single order.
+    pub fn check_batch(&self, api_key: &str, items: usize) -> Result<RateLimitStatus, RateLimited> {
+        let weight = items.saturating_sub(1) as u32 * self.order_entry_weight;
+        self.per_api_key.check(api_key.to_string(), weight)
+    }
+}
+
+fn rate_limiter(req: &ServiceRequest) -> web::Data<RateLimiter> {
+    req.app_data::<web::Data<RateLimiter>>()
+        .expect("RateLimiter is not registered as app data")
+        .clone()
+}
+
+/// Middleware limiting requests per client IP, meant to run before authentication.
+pub async fn limit_by_ip(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Some(peer) = req.peer_addr() {
+        rate_limiter(&req).check_ip(peer.ip(), req.method())?;
+    }
+
+    next.call(req).await
+}
+
+/// Middleware limiting requests per API key, meant to run after authentication.
+/// Successful responses carry the remaining budget of the key.
+pub async fn limit_by_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let api_key = req
+        .extensions()
+        .get::<AuthenticatedUser>()
+        .map(|user| user.api_key.clone());
+
+    let status = match api_key {
+        Some(api_key) => Some(rate_limiter(&req).check_api_key(&api_key, req.method())?),
+        None => None,
+    };
+
+    let mut response = next.call(req).await?;
+    // Batch handlers charge their items on top and leave the budget that remains
+    let status = response
+        .request()
+        .extensions()
+        .get::<RateLimitStatus>()
+        .copied()
+        .or(status);
+    if let Some(status) = status {
+        insert_headers(response.headers_mut(), status);
+    }
+
+    Ok(response)
+}
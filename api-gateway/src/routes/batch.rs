@@ -0,0 +1,190 @@
+//! Batch order entry: up to [`MAX_BATCH_SIZE`] orders or cancels sent to the
+//! engine as a single `BatchCommand`, which applies them in order.
+//!
+//! Items are validated one by one. Invalid items are reported back and left
+//! out of the batch, the valid ones are still submitted. Each item takes
+//! from the api key's rate limit like a single order.
+//!
+//! The endpoints are fire-and-forget: `submitted` only means the item passed
+//! the gateway's validation and was handed to the engine, which may still
+//! reject it. The engine's per-item outcome is published as a
+//! `BatchExecuted` event carrying the `batch_id` returned here, and the
+//! resulting fills show up in `GET /users/{id}/fills`.
+use crate::authentication::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::instruments::Instruments;
+use crate::messages::trading::{
+    BatchCommand, BatchItem, WireMessage, batch_item::Command, wire_message::Payload,
+};
+use crate::metrics::ReceivedAt;
+use crate::rate_limit::{RateLimited, RateLimiter};
+use crate::routes::order::{CancelOrderJson, PlaceLimitOrderJson};
+use crate::validation::{ValidatedJson, field_errors};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, web};
+use tracing_actix_web::RequestId;
+use uuid::Uuid;
+use validator::Validate;
+
+pub const MAX_BATCH_SIZE: u64 = 50;
+
+#[derive(serde::Deserialize, Validate)]
+pub struct PlaceBatchJson {
+    #[validate(length(min = 1, max = "MAX_BATCH_SIZE"))]
+    pub orders: Vec<PlaceLimitOrderJson>,
+}
+
+#[derive(serde::Deserialize, Validate)]
+pub struct CancelBatchJson {
+    #[validate(length(min = 1, max = "MAX_BATCH_SIZE"))]
+    pub orders: Vec<CancelOrderJson>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum BatchItemJson<T> {
+    /// Handed to the engine, not yet executed.
+    Submitted { index: usize, order: T },
+    Rejected {
+        index: usize,
+        error: crate::error::ErrorBody,
+    },
+}
+
+#[derive(serde::Serialize)]
+pub struct BatchResponseJson<T> {
+    /// Correlates with the engine's `BatchExecuted` event, empty when no item
+    /// was valid and nothing was sent.
+    pub batch_id: String,
+    pub results: Vec<BatchItemJson<T>>,
+}
+
+/// Charges every item of the batch to the caller's rate limit.
+fn charge_items(
+    req: &HttpRequest,
+    rate_limiter: &RateLimiter,
+    user: &AuthenticatedUser,
+    items: usize,
+) -> Result<(), RateLimited> {
+    let status = rate_limiter.check_batch(&user.api_key, items)?;
+    req.extensions_mut().insert(status);
+    Ok(())
+}
+
+async fn submit<T: serde::Serialize>(
+    user_id: u64,
+    prepared: Vec<Result<(Command, T), ApiError>>,
+    received_at: ReceivedAt,
+    request_id: RequestId,
+    command_tx: &tokio::sync::mpsc::Sender<WireMessage>,
+) -> Result<HttpResponse, ApiError> {
+    let mut items = Vec::with_capacity(prepared.len());
+    let mut results = Vec::with_capacity(prepared.len());
+    for (index, item) in prepared.into_iter().enumerate() {
+        match item {
+            Ok((command, order)) => {
+                items.push(BatchItem {
+                    command: Some(command),
+                });
+                results.push(BatchItemJson::Submitted { index, order });
+            }
+            Err(err) => results.push(BatchItemJson::Rejected {
+                index,
+                error: err.body(),
+            }),
+        }
+    }
+
+    if items.is_empty() {
+        return Ok(HttpResponse::Ok().json(BatchResponseJson {
+            batch_id: String::new(),
+            results,
+        }));
+    }
+
+    let batch_id = Uuid::new_v4().to_string();
+    let wire_message = WireMessage {
+        payload: Some(Payload::BatchCommand(BatchCommand {
+            batch_id: batch_id.clone(),
+            user_id,
+            items,
+        })),
+        gateway_received_at_us: received_at.0,
+        correlation_id: request_id.to_string(),
+        ..Default::default()
+    };
+
+    match command_tx.send(wire_message).await {
+        Ok(_) => {
+            tracing::info!(%batch_id, "sent batch to engine");
+            Ok(HttpResponse::Ok().json(BatchResponseJson { batch_id, results }))
+        }
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to send message to engine");
+            Err(ApiError::EngineUnavailable)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn place_batch(
+    req: HttpRequest,
+    form: ValidatedJson<PlaceBatchJson>,
+    user: web::ReqData<AuthenticatedUser>,
+    instruments: web::Data<Instruments>,
+    rate_limiter: web::Data<RateLimiter>,
+    received_at: web::ReqData<ReceivedAt>,
+    request_id: RequestId,
+    command_tx: web::Data<tokio::sync::mpsc::Sender<WireMessage>>,
+) -> actix_web::Result<HttpResponse> {
+    charge_items(&req, &rate_limiter, &user, form.orders.len())?;
+    let prepared = form
+        .orders
+        .iter()
+        .map(|order| {
+            order.validate().map_err(field_errors)?;
+            let (command, order) = order.to_command(user.user_id, &instruments)?;
+            Ok((Command::PlaceLimitOrder(command), order))
+        })
+        .collect();
+
+    submit(
+        user.user_id,
+        prepared,
+        *received_at,
+        request_id,
+        &command_tx,
+    )
+    .await
+    .map_err(Into::into)
+}
+
+pub async fn cancel_batch(
+    req: HttpRequest,
+    form: ValidatedJson<CancelBatchJson>,
+    user: web::ReqData<AuthenticatedUser>,
+    rate_limiter: web::Data<RateLimiter>,
+    received_at: web::ReqData<ReceivedAt>,
+    request_id: RequestId,
+    command_tx: web::Data<tokio::sync::mpsc::Sender<WireMessage>>,
+) -> actix_web::Result<HttpResponse> {
+    charge_items(&req, &rate_limiter, &user, form.orders.len())?;
+    let prepared = form
+        .orders
+        .iter()
+        .map(|order| {
+            order.validate().map_err(field_errors)?;
+            let command = order.to_command(user.user_id)?;
+            Ok((Command::CancelOrder(command), order))
+        })
+        .collect();
+
+    submit(
+        user.user_id,
+        prepared,
+        *received_at,
+        request_id,
+        &command_tx,
+    )
+    .await
+    .map_err(Into::into)
+}
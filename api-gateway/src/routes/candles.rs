@@ -0,0 +1,199 @@
+//! OHLCV candles of an instrument, kept up to date by the persistor.
+//!
+//! `GET /candles/BTC-USD?interval=1m&from=1700000000000&to=1700003600000`
+//! returns the candles opening from `from` up to, excluding, `to`, oldest
+//! first. Times are Unix milliseconds, `to` defaults to now and without
+//! `from` the latest candles are returned. Intervals without trades have no
+//! candle.
+use crate::error::{ApiError, FieldError};
+use crate::instruments::Instruments;
+use crate::metrics::ReceivedAt;
+use crate::routes::query::{parse_param, parse_time};
+use actix_web::{HttpResponse, web};
+use sqlx::SqlitePool;
+
+/// Candles returned per request at most.
+const MAX_CANDLES: i64 = 1000;
+
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interval {
+    #[serde(rename = "1s")]
+    Second,
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl Interval {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "1s" => Some(Interval::Second),
+            "1m" => Some(Interval::Minute),
+            "5m" => Some(Interval::FiveMinutes),
+            "1h" => Some(Interval::Hour),
+            "1d" => Some(Interval::Day),
+            _ => None,
+        }
+    }
+
+    pub fn seconds(self) -> i64 {
+        match self {
+            Interval::Second => 1,
+            Interval::Minute => 60,
+            Interval::FiveMinutes => 300,
+            Interval::Hour => 3600,
+            Interval::Day => 86_400,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CandlesQuery {
+    pub interval: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct CandleJson {
+    /// Unix time in milliseconds.
+    pub open_time: i64,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    /// Traded quantity, in the base currency.
+    pub volume: String,
+    /// Traded value, in the quote currency.
+    pub quote_volume: String,
+    pub trade_count: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct CandlesJson {
+    pub instrument: String,
+    pub interval: Interval,
+    pub candles: Vec<CandleJson>,
+}
+
+struct CandleRow {
+    open_time_us: i64,
+    open: i64,
+    high: i64,
+    low: i64,
+    close: i64,
+    volume: i64,
+    notional: i64,
+    trade_count: i64,
+}
+
+pub async fn candles(
+    instrument: web::Path<String>,
+    query: web::Query<CandlesQuery>,
+    instruments: web::Data<Instruments>,
+    received_at: web::ReqData<ReceivedAt>,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse, ApiError> {
+    let mut errors = Vec::new();
+    let pair = instruments.pair(&instrument);
+    if pair.is_none() {
+        errors.push(FieldError::new(
+            "instrument",
+            "unknown_instrument",
+            format!("{} is not a known instrument", instrument),
+        ));
+    }
+    if query.interval.is_none() {
+        errors.push(FieldError::new(
+            "interval",
+            "required",
+            "interval is required",
+        ));
+    }
+    let interval = parse_param(
+        "interval",
+        query.interval.as_deref(),
+        Interval::parse,
+        "invalid_interval",
+        "must be one of 1s, 1m, 5m, 1h and 1d",
+        &mut errors,
+    );
+    let from = parse_time("from", query.from.as_deref(), &mut errors);
+    let to = parse_time("to", query.to.as_deref(), &mut errors).unwrap_or(received_at.0 as i64);
+    let (Some((base, quote)), Some(interval)) = (pair, interval) else {
+        return Err(ApiError::Validation(errors));
+    };
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+
+    let interval_seconds = interval.seconds();
+    let rows = match from {
+        Some(from) => {
+            sqlx::query_as!(
+                CandleRow,
+                r#"SELECT open_time_us, open, high, low, close, volume, notional, trade_count
+                FROM candles
+                WHERE base_currency = $1 AND quote_currency = $2 AND interval_seconds = $3
+                    AND open_time_us >= $4 AND open_time_us < $5
+                ORDER BY open_time_us LIMIT $6"#,
+                base.name,
+                quote.name,
+                interval_seconds,
+                from,
+                to,
+                MAX_CANDLES,
+            )
+            .fetch_all(db_pool.get_ref())
+            .await
+        }
+        None => sqlx::query_as!(
+            CandleRow,
+            r#"SELECT open_time_us, open, high, low, close, volume, notional, trade_count
+            FROM candles
+            WHERE base_currency = $1 AND quote_currency = $2 AND interval_seconds = $3
+                AND open_time_us < $4
+            ORDER BY open_time_us DESC LIMIT $5"#,
+            base.name,
+            quote.name,
+            interval_seconds,
+            to,
+            MAX_CANDLES,
+        )
+        .fetch_all(db_pool.get_ref())
+        .await
+        .map(|mut rows| {
+            rows.reverse();
+            rows
+        }),
+    };
+    let rows = rows.map_err(|e| {
+        tracing::error!(error = ?e, "failed to read candles");
+        ApiError::Internal
+    })?;
+
+    let candles = rows
+        .into_iter()
+        .map(|row| CandleJson {
+            open_time: row.open_time_us / 1000,
+            open: quote.format_amount(row.open as u64),
+            high: quote.format_amount(row.high as u64),
+            low: quote.format_amount(row.low as u64),
+            close: quote.format_amount(row.close as u64),
+            volume: base.format_amount(row.volume as u64),
+            quote_volume: quote.format_amount(base.quote_amount(row.notional as u128)),
+            trade_count: row.trade_count,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(CandlesJson {
+        instrument: instrument.into_inner(),
+        interval,
+        candles,
+    }))
+}
@@ -0,0 +1,163 @@
+//! Fills of the caller's orders, newest first, paginated like
+//! [`trades`](super::trades). `GET /users/{id}/fills` only serves the user
+//! the api key belongs to.
+//!
+//! A trade is a fill of both its orders: the maker's, which rested in the
+//! book, and the taker's. A user trading with themselves gets both, so
+//! pages are cut at a trade id and an order id, `before` and
+//! `before_order`. Fees are charged in the quote currency at the
+//! rates of [`FeeSettings`], rounded up.
+use crate::authentication::AuthenticatedUser;
+use crate::configuration::FeeSettings;
+use crate::error::ApiError;
+use crate::instruments::Instruments;
+use crate::metrics::ReceivedAt;
+use crate::routes::order::SideJson;
+use crate::routes::query::parse_param;
+use crate::routes::trades::{PageQuery, side_of};
+use actix_web::{HttpResponse, web};
+use sqlx::SqlitePool;
+
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Maker,
+    Taker,
+}
+
+#[derive(serde::Deserialize)]
+pub struct FillsQuery {
+    #[serde(flatten)]
+    pub page: PageQuery,
+    /// Fills of the `before` trade are served from the order below this one.
+    pub before_order: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct FillJson {
+    pub trade_id: i64,
+    pub order_id: i64,
+    pub client_order_id: Option<String>,
+    pub instrument: String,
+    pub side: Option<SideJson>,
+    pub role: Role,
+    pub price: String,
+    pub quantity: String,
+    /// In the quote currency.
+    pub fee: String,
+    /// Unix time in milliseconds.
+    pub timestamp: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct FillsJson {
+    pub fills: Vec<FillJson>,
+    /// `before` of the next page, null on the last one.
+    pub next_before: Option<i64>,
+    /// `before_order` of the next page, null on the last one.
+    pub next_before_order: Option<i64>,
+}
+
+struct FillRow {
+    trade_id: i64,
+    order_id: i64,
+    client_order_id: Option<String>,
+    side: i64,
+    taker: bool,
+    base_currency: Option<String>,
+    quote_currency: Option<String>,
+    price: Option<i64>,
+    filled_qty: Option<i64>,
+    engine_timestamp_us: Option<i64>,
+}
+
+pub async fn fills(
+    user_id: web::Path<String>,
+    query: web::Query<FillsQuery>,
+    user: web::ReqData<AuthenticatedUser>,
+    instruments: web::Data<Instruments>,
+    fees: web::Data<FeeSettings>,
+    received_at: web::ReqData<ReceivedAt>,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse, ApiError> {
+    // Other users' ids are refused whether they exist or not
+    if user_id.parse::<u64>().ok() != Some(user.user_id) {
+        return Err(ApiError::Forbidden);
+    }
+    let mut errors = Vec::new();
+    let page = query.page.parse(&received_at, &mut errors);
+    // Without it, every fill of the `before` trade is skipped
+    let before_order = parse_param(
+        "before_order",
+        query.before_order.as_deref(),
+        |value| value.parse().ok(),
+        "invalid_cursor",
+        "must be an order id",
+        &mut errors,
+    )
+    .unwrap_or(i64::MIN);
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+
+    let user_id = user.user_id as i64;
+    let rows = sqlx::query_as!(
+        FillRow,
+        r#"SELECT t.id AS "trade_id!", o.order_id AS "order_id!", o.client_order_id, o.side,
+            t.taker_order_id = o.order_id AS "taker!: bool",
+            t.base_currency, t.quote_currency, t.price, t.filled_qty, t.engine_timestamp_us
+        FROM trades t
+        JOIN orders o ON o.order_id IN (t.taker_order_id, t.maker_order_id)
+        WHERE o.user_id = $1 AND (t.id, o.order_id) < ($2, $3)
+            AND t.engine_timestamp_us >= $4 AND t.engine_timestamp_us < $5
+        ORDER BY t.id DESC, o.order_id DESC LIMIT $6"#,
+        user_id,
+        page.before,
+        before_order,
+        page.from_us,
+        page.to_us,
+        page.limit,
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "failed to read fills");
+        ApiError::Internal
+    })?;
+
+    let next = rows.last().filter(|_| rows.len() as i64 == page.limit);
+    let next_before = next.map(|row| row.trade_id);
+    let next_before_order = next.map(|row| row.order_id);
+    let fills = rows
+        .into_iter()
+        .filter_map(|row| {
+            let base = instruments.get(row.base_currency.as_deref()?)?;
+            let quote = instruments.get(row.quote_currency.as_deref()?)?;
+            let price = row.price? as u64;
+            let quantity = row.filled_qty? as u64;
+            let (role, fee_bps) = match row.taker {
+                true => (Role::Taker, fees.taker_bps),
+                false => (Role::Maker, fees.maker_bps),
+            };
+            let fee = base.quote_fee(price as u128 * quantity as u128, fee_bps);
+            Some(FillJson {
+                trade_id: row.trade_id,
+                order_id: row.order_id,
+                client_order_id: row.client_order_id,
+                instrument: format!("{}-{}", base.name, quote.name),
+                side: side_of(row.side),
+                role,
+                price: quote.format_amount(price),
+                quantity: base.format_amount(quantity),
+                fee: quote.format_amount(fee),
+                timestamp: row.engine_timestamp_us? / 1000,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(FillsJson {
+        fills,
+        next_before,
+        next_before_order,
+    }))
+}
@@ -0,0 +1,71 @@
+use crate::health::EngineLink;
+use crate::messages::trading::WireMessage;
+use actix_web::{HttpResponse, web};
+use sqlx::SqlitePool;
+
+/// The gateway stops accepting traffic once the command channel is this full,
+/// in percent of its capacity.
+const MAX_QUEUE_FILL_PERCENT: usize = 90;
+
+#[derive(serde::Serialize)]
+struct EngineStatus {
+    connected: bool,
+    /// Unix time in milliseconds, `null` until the first command is written.
+    last_write_ms: Option<u64>,
+    command_queue_depth: usize,
+    command_queue_capacity: usize,
+}
+
+#[derive(serde::Serialize)]
+struct DatabaseStatus {
+    reachable: bool,
+}
+
+#[derive(serde::Serialize)]
+struct ReadinessJson {
+    ready: bool,
+    engine: EngineStatus,
+    database: DatabaseStatus,
+}
+
+/// The process is up and serving HTTP.
+pub async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Ready when the engine link is up, the command channel isn't backed up and
+/// the database answers. Responds 503 otherwise, with the same body.
+pub async fn ready(
+    engine_link: web::Data<EngineLink>,
+    command_tx: web::Data<tokio::sync::mpsc::Sender<WireMessage>>,
+    db_pool: web::Data<SqlitePool>,
+) -> HttpResponse {
+    let command_queue_capacity = command_tx.max_capacity();
+    let command_queue_depth = command_queue_capacity - command_tx.capacity();
+    let engine = EngineStatus {
+        connected: engine_link.is_connected(),
+        last_write_ms: engine_link.last_write_ms(),
+        command_queue_depth,
+        command_queue_capacity,
+    };
+    let database = DatabaseStatus {
+        reachable: sqlx::query("SELECT 1")
+            .execute(db_pool.get_ref())
+            .await
+            .is_ok(),
+    };
+
+    let ready = engine.connected
+        && command_queue_depth * 100 < command_queue_capacity * MAX_QUEUE_FILL_PERCENT
+        && database.reachable;
+    let status = ReadinessJson {
+        ready,
+        engine,
+        database,
+    };
+    if ready {
+        HttpResponse::Ok().json(status)
+    } else {
+        HttpResponse::ServiceUnavailable().json(status)
+    }
+}
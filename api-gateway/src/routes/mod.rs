@@ -1 +1,8 @@
+pub mod batch;
+pub mod candles;
+pub mod fills;
+pub mod health;
 pub mod order;
+pub mod query;
+pub mod ticker;
+pub mod trades;
@@ -1,67 +1,278 @@
-use std::sync::mpsc::Sender;
-
-use crate::messages::trading::{CancelOrder, PlaceLimitOrder, WireMessage, wire_message::Payload};
+use crate::authentication::AuthenticatedUser;
+use crate::error::{ApiError, FieldError};
+use crate::instruments::{Instrument, Instruments};
+use crate::messages::trading::{
+    CancelOrder, PlaceLimitOrder, Side, WireMessage, wire_message::Payload,
+};
+use crate::metrics::ReceivedAt;
+use crate::validation::{ValidatedJson, client_order_id_string, decimal_string};
 use actix_web::{HttpResponse, web};
+use tracing_actix_web::RequestId;
+use validator::Validate;
+
+/// Scaled amounts are persisted as SQLite integers, anything above this
+/// can't be stored.
+const MAX_SCALED_AMOUNT: u64 = i64::MAX as u64;
+
+/// Bound on the scaled price times quantity of an order. Fill notionals are
+/// summed per order and per candle in 64 bit integers, so an order must fit
+/// them on its own.
+const MAX_NOTIONAL: u128 = i64::MAX as u128;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SideJson {
+    Buy,
+    Sell,
+}
 
-#[derive(serde::Deserialize)]
+impl From<SideJson> for Side {
+    fn from(side: SideJson) -> Self {
+        match side {
+            SideJson::Buy => Side::Buy,
+            SideJson::Sell => Side::Sell,
+        }
+    }
+}
+
+/// A limit order as submitted by clients, amounts are decimal strings such as
+/// `"64250.15"`. The same shape, normalised to the instruments' precision, is
+/// returned once the order has been forwarded to the engine.
+#[derive(serde::Deserialize, serde::Serialize, Validate)]
 pub struct PlaceLimitOrderJson {
-    pub user_id: u64,
-    pub side: i32,
-    pub price: u64,
-    pub quantity: u64,
+    pub side: SideJson,
+    #[validate(length(max = 32), custom = "decimal_string")]
+    pub price: String,
+    #[validate(length(max = 32), custom = "decimal_string")]
+    pub quantity: String,
+    #[validate(length(min = 1, max = 16))]
     pub base_currency: String,
+    #[validate(length(min = 1, max = 16))]
     pub quote_currency: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom = "client_order_id_string")]
+    pub client_order_id: Option<String>,
+}
+
+struct ScaledOrder<'a> {
+    base: &'a Instrument,
+    quote: &'a Instrument,
+    price: u64,
+    quantity: u64,
+}
+
+fn scaled_amount(
+    instrument: &Instrument,
+    field: &str,
+    value: &str,
+    errors: &mut Vec<FieldError>,
+) -> Option<u64> {
+    match instrument.parse_amount(value) {
+        Ok(0) => {
+            errors.push(FieldError::new(
+                field,
+                "must_be_positive",
+                "must be greater than zero",
+            ));
+            None
+        }
+        Ok(amount) if amount > MAX_SCALED_AMOUNT => {
+            errors.push(FieldError::new(field, "out_of_range", "value is too large"));
+            None
+        }
+        Ok(amount) => Some(amount),
+        Err(e) => {
+            errors.push(FieldError::new(field, e.code(), e.to_string()));
+            None
+        }
+    }
+}
+
+fn known_instrument<'a>(
+    instruments: &'a Instruments,
+    field: &str,
+    name: &str,
+    errors: &mut Vec<FieldError>,
+) -> Option<&'a Instrument> {
+    let instrument = instruments.get(name);
+    if instrument.is_none() {
+        errors.push(FieldError::new(
+            field,
+            "unknown_instrument",
+            format!("{} is not a known instrument", name),
+        ));
+    }
+    instrument
+}
+
+impl PlaceLimitOrderJson {
+    /// Checks the order against the known instruments and scales its amounts,
+    /// prices are in the quote currency and quantities in the base currency.
+    fn scale<'a>(&self, instruments: &'a Instruments) -> Result<ScaledOrder<'a>, ApiError> {
+        let mut errors = Vec::new();
+        let base = known_instrument(
+            instruments,
+            "base_currency",
+            &self.base_currency,
+            &mut errors,
+        );
+        let quote = known_instrument(
+            instruments,
+            "quote_currency",
+            &self.quote_currency,
+            &mut errors,
+        );
+
+        if self.base_currency == self.quote_currency {
+            errors.push(FieldError::new(
+                "quote_currency",
+                "same_currency",
+                "must differ from base_currency",
+            ));
+        }
+
+        let price = quote.and_then(|quote| scaled_amount(quote, "price", &self.price, &mut errors));
+        let quantity =
+            base.and_then(|base| scaled_amount(base, "quantity", &self.quantity, &mut errors));
+
+        if let (Some(price), Some(quantity)) = (price, quantity)
+            && price as u128 * quantity as u128 > MAX_NOTIONAL
+        {
+            errors.push(FieldError::new(
+                "quantity",
+                "notional_too_large",
+                "price times quantity is too large",
+            ));
+        }
+
+        match (base, quote, price, quantity) {
+            (Some(base), Some(quote), Some(price), Some(quantity)) if errors.is_empty() => {
+                Ok(ScaledOrder {
+                    base,
+                    quote,
+                    price,
+                    quantity,
+                })
+            }
+            _ => Err(ApiError::Validation(errors)),
+        }
+    }
+
+    /// Builds the engine command for this order, along with the order as it
+    /// is echoed back to the client.
+    pub fn to_command(
+        &self,
+        user_id: u64,
+        instruments: &Instruments,
+    ) -> Result<(PlaceLimitOrder, PlaceLimitOrderJson), ApiError> {
+        let order = self.scale(instruments)?;
+
+        let command = PlaceLimitOrder {
+            user_id,
+            side: Side::from(self.side).into(),
+            price: order.price,
+            quantity: order.quantity,
+            base_currency: order.base.name.clone(),
+            quote_currency: order.quote.name.clone(),
+            client_order_id: self.client_order_id.clone().unwrap_or_default(),
+        };
+        let normalised = PlaceLimitOrderJson {
+            side: self.side,
+            price: order.quote.format_amount(order.price),
+            quantity: order.base.format_amount(order.quantity),
+            base_currency: order.base.name.clone(),
+            quote_currency: order.quote.name.clone(),
+            client_order_id: self.client_order_id.clone(),
+        };
+
+        Ok((command, normalised))
+    }
 }
 
 pub async fn place_limit_order(
-    form: web::Json<PlaceLimitOrderJson>,
+    form: ValidatedJson<PlaceLimitOrderJson>,
+    user: web::ReqData<AuthenticatedUser>,
+    instruments: web::Data<Instruments>,
+    received_at: web::ReqData<ReceivedAt>,
+    request_id: RequestId,
     command_tx: web::Data<tokio::sync::mpsc::Sender<WireMessage>>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
+    let (command, order) = form.to_command(user.user_id, &instruments)?;
+
     let wire_message = WireMessage {
-        payload: Some(Payload::PlaceLimitOrder(PlaceLimitOrder {
-            user_id: form.user_id,
-            side: form.side,
-            price: form.price,
-            quantity: form.quantity,
-            base_currency: form.base_currency.clone(),
-            quote_currency: form.quote_currency.clone(),
-        })),
+        payload: Some(Payload::PlaceLimitOrder(command)),
+        gateway_received_at_us: received_at.0,
+        correlation_id: request_id.to_string(),
+        ..Default::default()
     };
 
     match command_tx.send(wire_message).await {
         Ok(_) => {
-            log::info!("sent place_limit_order message to engine");
-            return HttpResponse::Ok().finish();
+            tracing::info!("sent place_limit_order message to engine");
+            Ok(HttpResponse::Ok().json(order))
         }
         Err(err) => {
-            log::error!(" failed to send message to engine: {err:?}");
-            return HttpResponse::InternalServerError().finish();
+            tracing::error!(error = ?err, "failed to send message to engine");
+            Err(ApiError::EngineUnavailable)
         }
     }
 }
 
-#[derive(serde::Deserialize)]
+/// Identifies the order to cancel either by `order_id` or by `client_order_id`.
+#[derive(serde::Deserialize, serde::Serialize, Validate)]
 pub struct CancelOrderJson {
-    pub order_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, message = "must be greater than zero"))]
+    pub order_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(custom = "client_order_id_string")]
+    pub client_order_id: Option<String>,
 }
+
+impl CancelOrderJson {
+    pub fn to_command(&self, user_id: u64) -> Result<CancelOrder, ApiError> {
+        let (order_id, client_order_id) = match (self.order_id, &self.client_order_id) {
+            (Some(order_id), None) => (order_id, String::new()),
+            (None, Some(client_order_id)) => (0, client_order_id.clone()),
+            _ => {
+                return Err(ApiError::Validation(vec![FieldError::new(
+                    "order_id",
+                    "exactly_one_required",
+                    "provide exactly one of order_id and client_order_id",
+                )]));
+            }
+        };
+
+        Ok(CancelOrder {
+            order_id,
+            user_id,
+            client_order_id,
+        })
+    }
+}
+
 pub async fn cancel_order(
-    form: web::Json<CancelOrderJson>,
+    form: ValidatedJson<CancelOrderJson>,
+    user: web::ReqData<AuthenticatedUser>,
+    received_at: web::ReqData<ReceivedAt>,
+    request_id: RequestId,
     command_tx: web::Data<tokio::sync::mpsc::Sender<WireMessage>>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let wire_message = WireMessage {
-        payload: Some(Payload::CancelOrder(CancelOrder {
-            order_id: form.order_id,
-        })),
+        payload: Some(Payload::CancelOrder(form.to_command(user.user_id)?)),
+        gateway_received_at_us: received_at.0,
+        correlation_id: request_id.to_string(),
+        ..Default::default()
     };
 
     match command_tx.send(wire_message).await {
         Ok(_) => {
-            log::info!("sent cancel order message to engine");
-            return HttpResponse::Ok().finish();
+            tracing::info!("sent cancel order message to engine");
+            Ok(HttpResponse::Ok().finish())
         }
         Err(err) => {
-            log::error!(" failed to send message to engine: {err:?}");
-            return HttpResponse::InternalServerError().finish();
+            tracing::error!(error = ?err, "failed to send message to engine");
+            Err(ApiError::EngineUnavailable)
         }
     }
 }
@@ -0,0 +1,40 @@
+//! Query parameters of the market data and fills routes.
+//!
+//! Parameters are deserialized as strings and parsed here, so that bad ones
+//! are reported like invalid body fields, each with its own code, rather
+//! than as a single malformed query.
+use crate::error::FieldError;
+
+/// Parses the `field` parameter, None when it is missing or `parse` refuses
+/// it. A refused one is added to `errors` with `code` and `message`.
+pub fn parse_param<T>(
+    field: &str,
+    value: Option<&str>,
+    parse: impl FnOnce(&str) -> Option<T>,
+    code: &str,
+    message: impl Into<String>,
+    errors: &mut Vec<FieldError>,
+) -> Option<T> {
+    let parsed = parse(value?);
+    if parsed.is_none() {
+        errors.push(FieldError::new(field, code, message));
+    }
+    parsed
+}
+
+/// Unix milliseconds, as microseconds.
+pub fn parse_time(field: &str, value: Option<&str>, errors: &mut Vec<FieldError>) -> Option<i64> {
+    parse_param(
+        field,
+        value,
+        |value| {
+            let millis = value.parse::<i64>().ok()?;
+            (0..=i64::MAX / 1000)
+                .contains(&millis)
+                .then(|| millis * 1000)
+        },
+        "invalid_timestamp",
+        "must be a Unix time in milliseconds",
+        errors,
+    )
+}
@@ -0,0 +1,110 @@
+//! Rolling 24 hour ticker of instruments, see [`crate::ticker`].
+//!
+//! `GET /ticker/BTC-USD` returns the ticker of an instrument, `GET /ticker`
+//! the tickers of every instrument that traded. Statistics cover the 24
+//! hours before the request and are null when nothing traded in them. The
+//! best bid and ask are the engine's top of the book.
+use crate::error::{ApiError, FieldError};
+use crate::instruments::{Instrument, Instruments};
+use crate::metrics::ReceivedAt;
+use crate::ticker::{TickerStats, Tickers, Top};
+use actix_web::{HttpResponse, web};
+
+#[derive(serde::Serialize)]
+pub struct TickerJson {
+    pub instrument: String,
+    pub last_price: Option<String>,
+    pub best_bid: Option<String>,
+    pub best_ask: Option<String>,
+    pub open: Option<String>,
+    pub high: Option<String>,
+    pub low: Option<String>,
+    /// Traded quantity, in the base currency.
+    pub volume: String,
+    /// Traded value, in the quote currency.
+    pub quote_volume: String,
+    pub trade_count: u64,
+    /// Volume weighted average price.
+    pub vwap: Option<String>,
+    /// From the open to the latest trade, e.g. `-1.25`.
+    pub price_change_percent: Option<String>,
+}
+
+fn ticker_json(
+    instrument: String,
+    base: &Instrument,
+    quote: &Instrument,
+    stats: Option<TickerStats>,
+    top: Top,
+) -> TickerJson {
+    let stats = stats.unwrap_or(TickerStats {
+        last_price: None,
+        open: None,
+        high: None,
+        low: None,
+        close: None,
+        volume: 0,
+        notional: 0,
+        trade_count: 0,
+    });
+    let price = |value: u64| quote.format_amount(value);
+    let vwap = (stats.volume > 0).then(|| price((stats.notional / stats.volume as u128) as u64));
+    let price_change_percent = match (stats.open, stats.close) {
+        (Some(open), Some(close)) if open > 0 => Some(format!(
+            "{:.2}",
+            (close as f64 - open as f64) / open as f64 * 100.0
+        )),
+        _ => None,
+    };
+
+    TickerJson {
+        instrument,
+        last_price: stats.last_price.map(price),
+        best_bid: top.best_bid.map(price),
+        best_ask: top.best_ask.map(price),
+        open: stats.open.map(price),
+        high: stats.high.map(price),
+        low: stats.low.map(price),
+        volume: base.format_amount(stats.volume),
+        quote_volume: price(base.quote_amount(stats.notional)),
+        trade_count: stats.trade_count,
+        vwap,
+        price_change_percent,
+    }
+}
+
+pub async fn ticker(
+    instrument: web::Path<String>,
+    instruments: web::Data<Instruments>,
+    tickers: web::Data<Tickers>,
+    received_at: web::ReqData<ReceivedAt>,
+) -> Result<HttpResponse, ApiError> {
+    let Some((base, quote)) = instruments.pair(&instrument) else {
+        return Err(ApiError::Validation(vec![FieldError::new(
+            "instrument",
+            "unknown_instrument",
+            format!("{} is not a known instrument", instrument),
+        )]));
+    };
+    let stats = tickers.get(&instrument, received_at.0 as i64);
+    let top = tickers.top(&instrument);
+    let json = ticker_json(instrument.into_inner(), base, quote, stats, top);
+    Ok(HttpResponse::Ok().json(json))
+}
+
+pub async fn tickers(
+    instruments: web::Data<Instruments>,
+    tickers: web::Data<Tickers>,
+    received_at: web::ReqData<ReceivedAt>,
+) -> Result<HttpResponse, ApiError> {
+    let mut all = Vec::new();
+    for (instrument, stats) in tickers.all(received_at.0 as i64) {
+        // Trades of instruments since removed are not served
+        let Some((base, quote)) = instruments.pair(&instrument) else {
+            continue;
+        };
+        let top = tickers.top(&instrument);
+        all.push(ticker_json(instrument, base, quote, Some(stats), top));
+    }
+    Ok(HttpResponse::Ok().json(all))
+}
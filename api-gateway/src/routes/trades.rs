@@ -0,0 +1,175 @@
+//! Recent trades of an instrument, read from the persistor's `trades`
+//! table.
+//!
+//! `GET /trades/BTC-USD?before=1234&from=1700000000000&to=1700003600000&limit=100`
+//! returns trades newest first. `before` is a trade id: passing the
+//! `next_before` of a response fetches the page after it, until it is null.
+//! Times are Unix milliseconds, `to` defaults to now and excludes trades at
+//! it, `limit` defaults to 100.
+use crate::error::{ApiError, FieldError};
+use crate::instruments::Instruments;
+use crate::metrics::ReceivedAt;
+use crate::routes::order::SideJson;
+use crate::routes::query::{parse_param, parse_time};
+use actix_web::{HttpResponse, web};
+use sqlx::SqlitePool;
+
+const DEFAULT_LIMIT: i64 = 100;
+/// Trades returned per request at most.
+const MAX_LIMIT: i64 = 1000;
+
+/// Paging parameters of the trades and fills routes.
+#[derive(serde::Deserialize)]
+pub struct PageQuery {
+    pub before: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<String>,
+}
+
+/// A page of trades, times in microseconds.
+pub struct Page {
+    pub before: i64,
+    pub from_us: i64,
+    pub to_us: i64,
+    pub limit: i64,
+}
+
+impl PageQuery {
+    /// Appends the errors of invalid parameters to `errors`.
+    pub fn parse(&self, received_at: &ReceivedAt, errors: &mut Vec<FieldError>) -> Page {
+        Page {
+            before: parse_param(
+                "before",
+                self.before.as_deref(),
+                |value| value.parse().ok(),
+                "invalid_cursor",
+                "must be a trade id",
+                errors,
+            )
+            .unwrap_or(i64::MAX),
+            from_us: parse_time("from", self.from.as_deref(), errors).unwrap_or(0),
+            to_us: parse_time("to", self.to.as_deref(), errors).unwrap_or(received_at.0 as i64),
+            limit: parse_param(
+                "limit",
+                self.limit.as_deref(),
+                |value| {
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+                },
+                "invalid_limit",
+                format!("must be between 1 and {}", MAX_LIMIT),
+                errors,
+            )
+            .unwrap_or(DEFAULT_LIMIT),
+        }
+    }
+}
+
+/// Side of a `side` or `aggressor_side` column.
+pub fn side_of(value: i64) -> Option<SideJson> {
+    match value {
+        1 => Some(SideJson::Buy),
+        2 => Some(SideJson::Sell),
+        _ => None,
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct TradeJson {
+    pub id: i64,
+    /// Engine sequence number of the trade.
+    pub sequence: Option<i64>,
+    pub price: String,
+    pub quantity: String,
+    /// Side of the order that took liquidity.
+    pub aggressor_side: Option<SideJson>,
+    /// Unix time in milliseconds.
+    pub timestamp: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct TradesJson {
+    pub instrument: String,
+    pub trades: Vec<TradeJson>,
+    /// `before` of the next page, null on the last one.
+    pub next_before: Option<i64>,
+}
+
+struct TradeRow {
+    id: i64,
+    sequence: Option<i64>,
+    price: Option<i64>,
+    filled_qty: Option<i64>,
+    aggressor_side: Option<i64>,
+    engine_timestamp_us: Option<i64>,
+}
+
+pub async fn trades(
+    instrument: web::Path<String>,
+    query: web::Query<PageQuery>,
+    instruments: web::Data<Instruments>,
+    received_at: web::ReqData<ReceivedAt>,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse, ApiError> {
+    let mut errors = Vec::new();
+    let pair = instruments.pair(&instrument);
+    if pair.is_none() {
+        errors.push(FieldError::new(
+            "instrument",
+            "unknown_instrument",
+            format!("{} is not a known instrument", instrument),
+        ));
+    }
+    let page = query.parse(&received_at, &mut errors);
+    let Some((base, quote)) = pair.filter(|_| errors.is_empty()) else {
+        return Err(ApiError::Validation(errors));
+    };
+
+    let rows = sqlx::query_as!(
+        TradeRow,
+        r#"SELECT id AS "id!", sequence, price, filled_qty, aggressor_side, engine_timestamp_us
+        FROM trades
+        WHERE base_currency = $1 AND quote_currency = $2 AND id < $3
+            AND engine_timestamp_us >= $4 AND engine_timestamp_us < $5
+        ORDER BY id DESC LIMIT $6"#,
+        base.name,
+        quote.name,
+        page.before,
+        page.from_us,
+        page.to_us,
+        page.limit,
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(|e| {
+        tracing::error!(error = ?e, "failed to read trades");
+        ApiError::Internal
+    })?;
+
+    let next_before = rows
+        .last()
+        .filter(|_| rows.len() as i64 == page.limit)
+        .map(|row| row.id);
+    let trades = rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(TradeJson {
+                id: row.id,
+                sequence: row.sequence,
+                price: quote.format_amount(row.price? as u64),
+                quantity: base.format_amount(row.filled_qty? as u64),
+                aggressor_side: row.aggressor_side.and_then(side_of),
+                timestamp: row.engine_timestamp_us? / 1000,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(TradesJson {
+        instrument: instrument.into_inner(),
+        trades,
+        next_before,
+    }))
+}
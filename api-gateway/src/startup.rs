@@ -1,25 +1,101 @@
+use crate::authentication;
+use crate::configuration::{AuthenticationSettings, FeeSettings, RateLimitSettings};
+use crate::health::EngineLink;
+use crate::idempotency;
+use crate::instruments::Instruments;
 use crate::messages::trading::WireMessage;
-use crate::routes::order;
-use actix_web::{App, HttpServer, dev::Server, web};
+use crate::metrics::{self, Metrics};
+use crate::rate_limit::{self, RateLimiter};
+use crate::routes::{batch, candles, fills, health, order, ticker, trades};
+use crate::ticker::Tickers;
+use actix_web::{App, HttpServer, dev::Server, middleware::from_fn, web};
 use prost::Message;
 use rand::Rng;
 use socket2::TcpKeepalive;
+use sqlx::SqlitePool;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tracing_actix_web::TracingLogger;
 
+#[allow(clippy::too_many_arguments)]
 pub fn run_http(
     listener: TcpListener,
     command_tx: tokio::sync::mpsc::Sender<WireMessage>,
+    engine_link: Arc<EngineLink>,
+    db_pool: SqlitePool,
+    instruments: Instruments,
+    auth_settings: AuthenticationSettings,
+    rate_limit_settings: RateLimitSettings,
+    tickers: Arc<Tickers>,
+    fee_settings: FeeSettings,
 ) -> Result<Server, std::io::Error> {
     let sender = web::Data::new(command_tx);
+    let engine_link = web::Data::from(engine_link);
+    let db_pool = web::Data::new(db_pool);
+    let instruments = web::Data::new(instruments);
+    let secret_cipher = web::Data::new(
+        auth_settings
+            .secret_cipher()
+            .map_err(std::io::Error::other)?,
+    );
+    let auth_settings = web::Data::new(auth_settings);
+    let rate_limiter = web::Data::new(RateLimiter::new(rate_limit_settings));
+    let metrics = web::Data::new(Metrics::new());
+    let tickers = web::Data::from(tickers);
+    let fee_settings = web::Data::new(fee_settings);
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(metrics::track_requests))
             .wrap(TracingLogger::default())
-            .route("/orders", web::post().to(order::place_limit_order))
-            .route("/orders", web::delete().to(order::cancel_order))
+            // Probes are public, they are registered before the authenticated scope
+            .route("/metrics", web::get().to(metrics::metrics))
+            .service(
+                web::scope("/health")
+                    .route("/live", web::get().to(health::live))
+                    .route("/ready", web::get().to(health::ready)),
+            )
+            // Market data is public, only limited per client IP
+            .service(
+                web::scope("/candles")
+                    .wrap(from_fn(rate_limit::limit_by_ip))
+                    .route("/{instrument}", web::get().to(candles::candles)),
+            )
+            .service(
+                web::scope("/ticker")
+                    .wrap(from_fn(rate_limit::limit_by_ip))
+                    .route("", web::get().to(ticker::tickers))
+                    .route("/{instrument}", web::get().to(ticker::ticker)),
+            )
+            .service(
+                web::scope("/trades")
+                    .wrap(from_fn(rate_limit::limit_by_ip))
+                    .route("/{instrument}", web::get().to(trades::trades)),
+            )
+            .service(
+                // Middlewares run from the bottom up: ip limit, signature, api key limit, idempotency
+                web::scope("")
+                    .wrap(from_fn(idempotency::replay_idempotent))
+                    .wrap(from_fn(rate_limit::limit_by_api_key))
+                    .wrap(from_fn(authentication::require_signature))
+                    .wrap(from_fn(rate_limit::limit_by_ip))
+                    .route("/orders", web::post().to(order::place_limit_order))
+                    .route("/orders", web::delete().to(order::cancel_order))
+                    .route("/orders/batch", web::post().to(batch::place_batch))
+                    .route("/orders/batch", web::delete().to(batch::cancel_batch))
+                    .route("/users/{id}/fills", web::get().to(fills::fills)),
+            )
             .app_data(sender.clone())
+            .app_data(engine_link.clone())
+            .app_data(db_pool.clone())
+            .app_data(instruments.clone())
+            .app_data(auth_settings.clone())
+            .app_data(secret_cipher.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(metrics.clone())
+            .app_data(tickers.clone())
+            .app_data(fee_settings.clone())
     })
     .listen(listener)?
     .run();
@@ -27,7 +103,7 @@ pub fn run_http(
     Ok(server)
 }
 
-fn keepalive(stream: TcpStream) -> TcpStream {
+pub(crate) fn keepalive(stream: TcpStream) -> TcpStream {
     // sigh...tokio removed the set_keepalive so now we have to write 10 more lines of
     // code just to get the socket to die quicker!
     let stream: std::net::TcpStream = stream.into_std().unwrap();
@@ -39,22 +115,23 @@ fn keepalive(stream: TcpStream) -> TcpStream {
 
     socket.set_tcp_keepalive(&keepalive).unwrap();
     let stream: std::net::TcpStream = socket.into();
-    let stream: tokio::net::TcpStream = tokio::net::TcpStream::from_std(stream).unwrap();
-    return stream;
+    tokio::net::TcpStream::from_std(stream).unwrap()
 }
 
 pub async fn engine_connection_manager(
     mut receiver: tokio::sync::mpsc::Receiver<WireMessage>,
     engine_addr: String,
+    link: Arc<EngineLink>,
 ) {
     let mut backoff = tokio::time::Duration::from_millis(100);
     const MAX_BACKOFF: tokio::time::Duration = tokio::time::Duration::from_secs(30);
 
     loop {
-        log::info!("attempting to connect to {}", &engine_addr);
+        tracing::info!(%engine_addr, "attempting to connect to matching engine");
         match TcpStream::connect(&engine_addr).await {
             Ok(stream) => {
-                log::info!("connected to matching engine");
+                tracing::info!("connected to matching engine");
+                link.set_connected(true);
                 backoff = tokio::time::Duration::from_millis(100);
                 let mut stream = keepalive(stream);
 
@@ -63,20 +140,27 @@ pub async fn engine_connection_manager(
                     command.encode(&mut buf).unwrap();
 
                     if let Err(e) = stream.write_u32(buf.len() as u32).await {
-                        log::error!(
-                            "failed to write length onto stream, connection closed: {}",
-                            e
+                        tracing::error!(
+                            error = %e,
+                            correlation_id = %command.correlation_id,
+                            "failed to write length onto stream, connection closed"
                         );
                         break;
                     }
                     if let Err(e) = stream.write_all(&buf).await {
-                        log::error!("Failed to send command to matching engine: {}", e);
+                        tracing::error!(
+                            error = %e,
+                            correlation_id = %command.correlation_id,
+                            "failed to send command to matching engine"
+                        );
                         break;
                     }
+                    link.record_write();
                 }
+                link.set_connected(false);
             }
             Err(e) => {
-                log::error!("Failed to connect: {}. Retrying in {:?}...", e, backoff);
+                tracing::error!(error = %e, ?backoff, "failed to connect to matching engine, retrying");
                 tokio::time::sleep(backoff).await;
 
                 backoff = (backoff * 2).min(MAX_BACKOFF);
@@ -0,0 +1,427 @@
+//! Rolling 24 hour statistics and the top of the book per instrument, kept
+//! up to date from the engine's event stream.
+//!
+//! [`follow_engine`] loads the trades of the last 24 hours the persistor
+//! wrote at startup, then subscribes to the engine's events: trades move
+//! the window and `BookTop` events the best bid and ask. A broken stream is
+//! resumed after the last event seen, or from the next event when the
+//! engine restarted since, its book top standing in for the events missed.
+//! Trades are summed
+//! into one second buckets that are dropped as they leave the window, so
+//! the window moves by the second and the memory held per instrument is
+//! bounded.
+use crate::messages::trading::{BookTop, Subscribe, WireMessage, wire_message::Payload};
+use crate::metrics::now_micros;
+use crate::startup::keepalive;
+use prost::Message;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::Duration;
+
+const WINDOW_US: i64 = 24 * 3600 * 1_000_000;
+const BUCKET_US: i64 = 1_000_000;
+/// Trades read per query at startup.
+const PAGE: i64 = 1000;
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Trades of one second.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    start_us: i64,
+    open: u64,
+    high: u64,
+    low: u64,
+    close: u64,
+    volume: u64,
+    notional: u128,
+    trade_count: u64,
+}
+
+#[derive(Debug, Default)]
+struct Ticker {
+    /// Oldest first.
+    buckets: VecDeque<Bucket>,
+    /// Of the latest trade, kept once it left the window.
+    last: Option<(i64, u64)>,
+    volume: u64,
+    notional: u128,
+    trade_count: u64,
+    high: Option<u64>,
+    low: Option<u64>,
+}
+
+impl Ticker {
+    fn record(&mut self, timestamp_us: i64, price: u64, quantity: u64) {
+        if self.last.is_none_or(|(last_us, _)| timestamp_us >= last_us) {
+            self.last = Some((timestamp_us, price));
+        }
+        self.volume += quantity;
+        self.notional += price as u128 * quantity as u128;
+        self.trade_count += 1;
+        self.high = Some(self.high.map_or(price, |high| high.max(price)));
+        self.low = Some(self.low.map_or(price, |low| low.min(price)));
+
+        let start_us = timestamp_us - timestamp_us.rem_euclid(BUCKET_US);
+        // Trades mostly come in time order, late ones go to the bucket they fall in
+        let index = self.buckets.partition_point(|b| b.start_us <= start_us);
+        let latest = index == self.buckets.len();
+        match index.checked_sub(1).map(|i| &mut self.buckets[i]) {
+            Some(bucket) if bucket.start_us == start_us => {
+                bucket.high = bucket.high.max(price);
+                bucket.low = bucket.low.min(price);
+                if latest {
+                    bucket.close = price;
+                }
+                bucket.volume += quantity;
+                bucket.notional += price as u128 * quantity as u128;
+                bucket.trade_count += 1;
+            }
+            _ => self.buckets.insert(
+                index,
+                Bucket {
+                    start_us,
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume: quantity,
+                    notional: price as u128 * quantity as u128,
+                    trade_count: 1,
+                },
+            ),
+        }
+    }
+
+    /// Drops the buckets that ended 24 hours before `now_us` or earlier.
+    fn evict(&mut self, now_us: i64) {
+        let mut extremes_left = false;
+        while let Some(bucket) = self
+            .buckets
+            .front()
+            .filter(|b| b.start_us + BUCKET_US <= now_us - WINDOW_US)
+        {
+            self.volume -= bucket.volume;
+            self.notional -= bucket.notional;
+            self.trade_count -= bucket.trade_count;
+            extremes_left |= Some(bucket.high) == self.high || Some(bucket.low) == self.low;
+            self.buckets.pop_front();
+        }
+        if extremes_left {
+            self.high = self.buckets.iter().map(|b| b.high).max();
+            self.low = self.buckets.iter().map(|b| b.low).min();
+        }
+    }
+
+    fn stats(&self) -> TickerStats {
+        TickerStats {
+            last_price: self.last.map(|(_, price)| price),
+            open: self.buckets.front().map(|b| b.open),
+            high: self.high,
+            low: self.low,
+            close: self.buckets.back().map(|b| b.close),
+            volume: self.volume,
+            notional: self.notional,
+            trade_count: self.trade_count,
+        }
+    }
+}
+
+/// Statistics of an instrument over the last 24 hours, amounts scaled like
+/// the engine's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickerStats {
+    /// Price of the latest trade, even older than 24 hours.
+    pub last_price: Option<u64>,
+    pub open: Option<u64>,
+    pub high: Option<u64>,
+    pub low: Option<u64>,
+    /// Price of the latest trade in the window.
+    pub close: Option<u64>,
+    /// Traded quantity, in the base currency.
+    pub volume: u64,
+    /// Sum of price * quantity.
+    pub notional: u128,
+    pub trade_count: u64,
+}
+
+/// Best prices of an instrument's book, None on an empty side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Top {
+    pub best_bid: Option<u64>,
+    pub best_ask: Option<u64>,
+}
+
+/// The tickers of every instrument traded, keyed by pair name like `BTC-USD`.
+#[derive(Debug, Default)]
+pub struct Tickers {
+    by_instrument: Mutex<HashMap<String, Ticker>>,
+    tops: Mutex<HashMap<String, Top>>,
+}
+
+impl Tickers {
+    pub fn record(&self, instrument: &str, timestamp_us: i64, price: u64, quantity: u64) {
+        let mut tickers = self.by_instrument.lock().unwrap();
+        let ticker = tickers.entry(instrument.to_string()).or_default();
+        ticker.record(timestamp_us, price, quantity);
+    }
+
+    pub fn set_top(&self, top: &BookTop) {
+        let instrument = format!("{}-{}", top.base_currency, top.quote_currency);
+        let price = |price: u64| (price > 0).then_some(price);
+        self.tops.lock().unwrap().insert(
+            instrument,
+            Top {
+                best_bid: price(top.best_bid),
+                best_ask: price(top.best_ask),
+            },
+        );
+    }
+
+    /// Top of the book of `instrument`, empty before the engine sent one.
+    pub fn top(&self, instrument: &str) -> Top {
+        let tops = self.tops.lock().unwrap();
+        tops.get(instrument).copied().unwrap_or_default()
+    }
+
+    /// Statistics of `instrument` over the 24 hours before `now_us`, None
+    /// when it never traded.
+    pub fn get(&self, instrument: &str, now_us: i64) -> Option<TickerStats> {
+        let mut tickers = self.by_instrument.lock().unwrap();
+        let ticker = tickers.get_mut(instrument)?;
+        ticker.evict(now_us);
+        Some(ticker.stats())
+    }
+
+    /// Statistics of every instrument that traded, by name.
+    pub fn all(&self, now_us: i64) -> Vec<(String, TickerStats)> {
+        let mut tickers = self.by_instrument.lock().unwrap();
+        let mut all: Vec<_> = tickers
+            .iter_mut()
+            .map(|(instrument, ticker)| {
+                ticker.evict(now_us);
+                (instrument.clone(), ticker.stats())
+            })
+            .collect();
+        all.sort_by(|(a, _), (b, _)| a.cmp(b));
+        all
+    }
+}
+
+struct TradeRow {
+    id: i64,
+    base_currency: Option<String>,
+    quote_currency: Option<String>,
+    price: Option<i64>,
+    filled_qty: Option<i64>,
+    engine_timestamp_us: Option<i64>,
+    epoch: i64,
+    sequence: Option<i64>,
+}
+
+/// Where an engine event is in the stream, ordered like the events.
+type Position = (u64, u64);
+
+impl Tickers {
+    /// Records the trades of `rows`, skipping the ones persisted before
+    /// their price and instrument were. Returns the position of the last
+    /// sequenced one.
+    fn record_rows(&self, rows: &[TradeRow]) -> Option<Position> {
+        let mut last = None;
+        for row in rows {
+            if let Some(sequence) = row.sequence {
+                last = last.max(Some((row.epoch as u64, sequence as u64)));
+            }
+            let (Some(base), Some(quote), Some(price), Some(quantity), Some(timestamp_us)) = (
+                &row.base_currency,
+                &row.quote_currency,
+                row.price,
+                row.filled_qty,
+                row.engine_timestamp_us,
+            ) else {
+                continue;
+            };
+            let instrument = format!("{}-{}", base, quote);
+            self.record(&instrument, timestamp_us, price as u64, quantity as u64);
+        }
+        last
+    }
+
+    /// Applies an event of the engine's stream, moving `last` to it. Events
+    /// at or before `last` were seen already, from the `trades` table or an
+    /// earlier subscription.
+    fn apply(&self, event: WireMessage, last: &mut Option<Position>) {
+        let position = (event.epoch, event.sequence);
+        if last.is_some_and(|last| position <= last) {
+            return;
+        }
+        *last = Some(position);
+        match event.payload {
+            Some(Payload::TradeOccurred(trade)) => {
+                let instrument = format!("{}-{}", trade.base_currency, trade.quote_currency);
+                self.record(
+                    &instrument,
+                    event.engine_timestamp_us as i64,
+                    trade.price,
+                    trade.quantity,
+                );
+            }
+            Some(Payload::BookTop(top)) => self.set_top(&top),
+            _ => {}
+        }
+    }
+}
+
+async fn trades_after(pool: &SqlitePool, after_id: i64) -> Result<Vec<TradeRow>, sqlx::Error> {
+    sqlx::query_as!(
+        TradeRow,
+        r#"SELECT id AS "id!", base_currency, quote_currency, price, filled_qty, engine_timestamp_us,
+            epoch, sequence
+        FROM trades WHERE id > $1 ORDER BY id LIMIT $2"#,
+        after_id,
+        PAGE,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Keeps `tickers` up to date with the engine at `engine_addr`, after
+/// loading the trades of the last 24 hours from the `trades` table. Runs
+/// forever, database and connection errors are logged and retried.
+pub async fn follow_engine(pool: SqlitePool, engine_addr: String, tickers: Arc<Tickers>) {
+    let mut backoff = Duration::from_millis(100);
+    let mut last = loop {
+        match bootstrap(&pool, &tickers).await {
+            Ok(last) => break last,
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to load the trades of the last 24 hours");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    };
+
+    backoff = Duration::from_millis(100);
+    let mut engine_epoch = None;
+    loop {
+        match TcpStream::connect(&engine_addr).await {
+            Ok(stream) => {
+                tracing::info!(%engine_addr, "connected to the engine's event stream");
+                backoff = Duration::from_millis(100);
+                let error =
+                    subscribe(keepalive(stream), &tickers, &mut last, &mut engine_epoch).await;
+                tracing::error!(error = %error, "event stream from the engine closed, resubscribing");
+            }
+            Err(e) => {
+                tracing::error!(error = %e, ?backoff, "failed to connect to matching engine, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Where to subscribe from: right after `last` while the engine runs its
+/// epoch, which is assumed until it tells otherwise, else 0 for the next
+/// event.
+fn from_sequence(last: Option<Position>, engine_epoch: Option<u64>) -> u64 {
+    match last {
+        Some((epoch, sequence)) if engine_epoch.is_none_or(|engine| engine == epoch) => {
+            sequence + 1
+        }
+        _ => 0,
+    }
+}
+
+/// Subscribes to the engine's events and applies them until the stream
+/// fails. Ends at once when the engine's epoch, stamped on `Subscribed`,
+/// shows the subscription should have been from 0.
+async fn subscribe(
+    mut stream: TcpStream,
+    tickers: &Tickers,
+    last: &mut Option<Position>,
+    engine_epoch: &mut Option<u64>,
+) -> std::io::Error {
+    let from = from_sequence(*last, *engine_epoch);
+    let request = WireMessage {
+        payload: Some(Payload::Subscribe(Subscribe {
+            from_sequence: from,
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    if let Err(e) = stream.write_u32(request.len() as u32).await {
+        return e;
+    }
+    if let Err(e) = stream.write_all(&request).await {
+        return e;
+    }
+
+    loop {
+        let len = match stream.read_u32().await {
+            Ok(len) => len,
+            Err(e) => return e,
+        };
+        let mut buf = vec![0; len as usize];
+        if let Err(e) = stream.read_exact(&mut buf).await {
+            return e;
+        }
+        match WireMessage::decode(buf.as_slice()) {
+            Ok(WireMessage {
+                payload: Some(Payload::Subscribed(subscribed)),
+                epoch,
+                ..
+            }) => {
+                *engine_epoch = Some(epoch);
+                if from_sequence(*last, *engine_epoch) != from {
+                    return std::io::Error::other("the engine restarted");
+                }
+                if let Some(top) = subscribed.book_top {
+                    tickers.set_top(&top);
+                }
+            }
+            Ok(event) => tickers.apply(event, last),
+            Err(e) => return std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Records the latest trade of each instrument, so that instruments idle
+/// for a day keep a last price, and the trades of the last 24 hours after
+/// it. Returns the position of the last engine event read.
+async fn bootstrap(pool: &SqlitePool, tickers: &Tickers) -> Result<Option<Position>, sqlx::Error> {
+    let window_start_us = now_micros() as i64 - WINDOW_US;
+    let latest = sqlx::query_as!(
+        TradeRow,
+        r#"SELECT id AS "id!", base_currency, quote_currency, price, filled_qty, engine_timestamp_us,
+            epoch, sequence
+        FROM trades WHERE id IN (
+            SELECT MAX(id) FROM trades
+            WHERE engine_timestamp_us < $1
+            GROUP BY base_currency, quote_currency
+        )"#,
+        window_start_us,
+    )
+    .fetch_all(pool)
+    .await?;
+    // Only their price counts, they leave the window at the first eviction
+    let mut last = tickers.record_rows(&latest);
+
+    let mut after_id = sqlx::query_scalar!(
+        r#"SELECT MAX(id) AS "id: i64" FROM trades WHERE engine_timestamp_us < $1"#,
+        window_start_us,
+    )
+    .fetch_one(pool)
+    .await?
+    .unwrap_or(0);
+    loop {
+        let rows = trades_after(pool, after_id).await?;
+        last = last.max(tickers.record_rows(&rows));
+        match rows.last() {
+            Some(row) if rows.len() as i64 == PAGE => after_id = row.id,
+            _ => return Ok(last),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::{ApiError, FieldError};
+use actix_web::{FromRequest, HttpRequest, dev::Payload, web};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::pin::Pin;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+/// JSON extractor that reports deserialization errors with the path of the
+/// offending field and runs the body's `Validate` rules before the handler.
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> std::ops::Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = web::Bytes::from_request(req, payload);
+        Box::pin(async move {
+            let body = body.await?;
+            let value: T = parse_body(&body)?;
+            value.validate().map_err(field_errors)?;
+            Ok(ValidatedJson(value))
+        })
+    }
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        let field = err.path().to_string();
+        let code = match err.inner().classify() {
+            serde_json::error::Category::Data => "invalid_value",
+            _ => "invalid_json",
+        };
+        ApiError::MalformedBody(FieldError::new(field, code, err.inner().to_string()))
+    })
+}
+
+/// Flattens `validator`'s errors into our field errors, sorted by field so
+/// responses are deterministic.
+pub fn field_errors(errors: ValidationErrors) -> ApiError {
+    let mut fields: Vec<FieldError> = errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |error| {
+                let message = error
+                    .message
+                    .as_ref()
+                    .map(|m| m.to_string())
+                    .unwrap_or_else(|| format!("{} is invalid", field));
+                FieldError::new(field, error.code.clone(), message)
+            })
+        })
+        .collect();
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+    ApiError::Validation(fields)
+}
+
+/// Accepts plain unsigned decimals such as `"64250.15"`; precision is checked
+/// against the instrument later on.
+pub fn decimal_string(value: &str) -> Result<(), ValidationError> {
+    let mut dots = 0;
+    let well_formed = value.chars().all(|c| match c {
+        '.' => {
+            dots += 1;
+            dots == 1
+        }
+        c => c.is_ascii_digit(),
+    }) && value.chars().any(|c| c.is_ascii_digit());
+
+    if well_formed {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("invalid_decimal");
+        error.message = Some("must be a plain decimal such as \"12.5\"".into());
+        Err(error)
+    }
+}
+
+/// Client chosen ids: 1 to 36 characters out of `[A-Za-z0-9_-]`, enough for a UUID.
+pub fn client_order_id_string(value: &str) -> Result<(), ValidationError> {
+    let well_formed = (1..=36).contains(&value.len())
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+
+    if well_formed {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("invalid_client_order_id");
+        error.message = Some("must be 1 to 36 characters of [A-Za-z0-9_-]".into());
+        Err(error)
+    }
+}
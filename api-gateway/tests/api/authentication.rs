@@ -0,0 +1,95 @@
+use crate::helpers::{TEST_USER_ID, limit_order, now_millis, spawn_app};
+use api_gateway::messages::trading::wire_message::Payload;
+
+#[tokio::test]
+async fn signed_order_is_forwarded_with_the_key_owner() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    match app.command_rx.recv().await.unwrap().payload {
+        Some(Payload::PlaceLimitOrder(order)) => assert_eq!(order.user_id, TEST_USER_ID),
+        other => panic!("unexpected command {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn unsigned_request_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/orders", app.address))
+        .json(&limit_order())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "missing_header");
+}
+
+#[tokio::test]
+async fn request_signed_with_wrong_secret_is_rejected() {
+    let mut app = spawn_app().await;
+    app.secret = "not-the-secret".into();
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(app.command_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn request_outside_recv_window_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .signed_request_at(
+            reqwest::Method::POST,
+            "/orders",
+            &limit_order(),
+            now_millis() - 60_000,
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn revoked_key_is_rejected() {
+    let app = spawn_app().await;
+    sqlx::query("UPDATE api_keys SET revoked = 1")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_stored_secret_cannot_sign_requests() {
+    let mut app = spawn_app().await;
+    let stored: String = sqlx::query_scalar("SELECT encrypted_secret FROM api_keys")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!stored.contains(&app.secret));
+    app.secret = stored;
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(app.command_rx.try_recv().is_err());
+}
@@ -0,0 +1,98 @@
+use crate::helpers::{TEST_USER_ID, limit_order, spawn_app};
+use api_gateway::messages::trading::{batch_item::Command, wire_message::Payload};
+
+#[tokio::test]
+async fn batch_reports_each_order_and_sends_the_valid_ones_at_once() {
+    let mut app = spawn_app().await;
+    let mut too_precise = limit_order();
+    too_precise["price"] = serde_json::json!("64250.151");
+    let mut sell = limit_order();
+    sell["side"] = serde_json::json!("sell");
+    let batch = serde_json::json!({ "orders": [limit_order(), too_precise, sell] });
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders/batch", &batch)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let statuses: Vec<&str> = body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, ["submitted", "rejected", "submitted"]);
+    assert_eq!(body["results"][1]["index"], 1);
+    assert_eq!(
+        body["results"][1]["error"]["errors"][0]["code"],
+        "too_precise"
+    );
+
+    match app.command_rx.recv().await.unwrap().payload {
+        Some(Payload::BatchCommand(batch)) => {
+            assert_eq!(batch.batch_id, body["batch_id"].as_str().unwrap());
+            assert_eq!(batch.user_id, TEST_USER_ID);
+            assert_eq!(batch.items.len(), 2);
+        }
+        other => panic!("unexpected command {:?}", other),
+    }
+    assert!(app.command_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn empty_and_oversized_batches_are_rejected() {
+    let mut app = spawn_app().await;
+    let test_cases = [
+        (Vec::new(), "an empty batch"),
+        (vec![limit_order(); 51], "more than 50 orders"),
+    ];
+
+    for (orders, description) in test_cases {
+        let response = app
+            .signed_request(
+                reqwest::Method::POST,
+                "/orders/batch",
+                &serde_json::json!({ "orders": orders }),
+            )
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "the API did not reject {}",
+            description
+        );
+    }
+    assert!(app.command_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn batch_cancel_is_forwarded_in_order() {
+    let mut app = spawn_app().await;
+    let batch = serde_json::json!({
+        "orders": [{ "order_id": 7 }, { "client_order_id": "grid-1" }]
+    });
+
+    let response = app
+        .signed_request(reqwest::Method::DELETE, "/orders/batch", &batch)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    match app.command_rx.recv().await.unwrap().payload {
+        Some(Payload::BatchCommand(batch)) => {
+            let commands: Vec<_> = batch.items.into_iter().map(|i| i.command).collect();
+            match commands.as_slice() {
+                [
+                    Some(Command::CancelOrder(first)),
+                    Some(Command::CancelOrder(second)),
+                ] => {
+                    assert_eq!(first.order_id, 7);
+                    assert_eq!(second.client_order_id, "grid-1");
+                }
+                other => panic!("unexpected batch items {:?}", other),
+            }
+        }
+        other => panic!("unexpected command {:?}", other),
+    }
+}
@@ -0,0 +1,114 @@
+use crate::helpers::spawn_app;
+use sqlx::SqlitePool;
+
+/// 2023-11-14T22:13:00Z, in milliseconds.
+const MINUTE_MS: i64 = 1_699_999_980_000;
+
+/// A BTC-USD candle of a single trade of 0.015 BTC at `price` cents.
+async fn insert_candle(pool: &SqlitePool, interval_seconds: i64, open_time_ms: i64, price: i64) {
+    let volume = 1_500_000;
+    sqlx::query(
+        "INSERT INTO candles (base_currency, quote_currency, interval_seconds, open_time_us, open, high, low, close, volume, notional, trade_count)
+        VALUES ('BTC', 'USD', $1, $2, $3, $3, $3, $3, $4, $5, 1)",
+    )
+    .bind(interval_seconds)
+    .bind(open_time_ms * 1000)
+    .bind(price)
+    .bind(volume)
+    .bind(price * volume)
+    .execute(pool)
+    .await
+    .expect("Failed to insert candle");
+}
+
+async fn get_candles(address: &str, query: &str) -> reqwest::Response {
+    reqwest::get(format!("{}/candles/{}", address, query))
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn candles_are_served_oldest_first_with_decimal_amounts() {
+    let app = spawn_app().await;
+    for minute in 0..3 {
+        insert_candle(
+            &app.db_pool,
+            60,
+            MINUTE_MS + minute * 60_000,
+            6425015 + minute,
+        )
+        .await;
+    }
+    insert_candle(&app.db_pool, 300, MINUTE_MS, 6425015).await;
+
+    let query = format!(
+        "BTC-USD?interval=1m&from={}&to={}",
+        MINUTE_MS + 60_000,
+        MINUTE_MS + 180_000
+    );
+    let response = get_candles(&app.address, &query).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["instrument"], "BTC-USD");
+    assert_eq!(body["interval"], "1m");
+    let candles = body["candles"].as_array().unwrap();
+    assert_eq!(candles.len(), 2);
+    assert_eq!(candles[0]["open_time"], MINUTE_MS + 60_000);
+    assert_eq!(candles[0]["open"], "64250.16");
+    assert_eq!(candles[1]["close"], "64250.17");
+    assert_eq!(candles[1]["volume"], "0.01500000");
+    assert_eq!(candles[1]["quote_volume"], "963.75");
+    assert_eq!(candles[1]["trade_count"], 1);
+}
+
+#[tokio::test]
+async fn without_from_the_latest_candles_are_served() {
+    let app = spawn_app().await;
+    for minute in 0..3 {
+        insert_candle(&app.db_pool, 60, MINUTE_MS + minute * 60_000, 6425015).await;
+    }
+
+    let query = format!("BTC-USD?interval=1m&to={}", MINUTE_MS + 120_000);
+    let response = get_candles(&app.address, &query).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let open_times: Vec<_> = body["candles"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|candle| candle["open_time"].as_i64().unwrap())
+        .collect();
+    assert_eq!(open_times, [MINUTE_MS, MINUTE_MS + 60_000]);
+}
+
+#[tokio::test]
+async fn invalid_parameters_are_reported_per_field() {
+    let app = spawn_app().await;
+
+    let response = get_candles(&app.address, "BTC-DOGE?interval=2m&from=yesterday").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "validation_failed");
+    let codes: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| {
+            (
+                error["field"].as_str().unwrap(),
+                error["code"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            ("instrument", "unknown_instrument"),
+            ("interval", "invalid_interval"),
+            ("from", "invalid_timestamp"),
+        ]
+    );
+}
@@ -0,0 +1,12 @@
+use api_gateway::configuration::{DatabaseBackend, get_configuration};
+
+#[test]
+fn a_postgres_backend_is_refused() {
+    let mut configuration = get_configuration().expect("Failed to read configuration");
+    assert!(configuration.database.check_backend().is_ok());
+
+    configuration.database.backend = DatabaseBackend::Postgres;
+
+    let error = configuration.database.check_backend().unwrap_err();
+    assert!(error.to_string().contains("SQLite only"));
+}
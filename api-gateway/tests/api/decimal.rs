@@ -0,0 +1,45 @@
+use api_gateway::decimal::{DecimalError, format_scaled, parse_scaled};
+
+#[test]
+fn parses_whole_and_fractional_values() {
+    assert_eq!(parse_scaled("64250.15", 2), Ok(6425015));
+    assert_eq!(parse_scaled("0.015", 8), Ok(1_500_000));
+    assert_eq!(parse_scaled("3", 2), Ok(300));
+    assert_eq!(parse_scaled(".5", 2), Ok(50));
+}
+
+#[test]
+fn trailing_zeros_past_the_scale_are_accepted() {
+    assert_eq!(parse_scaled("1.2300", 2), Ok(123));
+}
+
+#[test]
+fn too_much_precision_is_rejected() {
+    assert_eq!(
+        parse_scaled("64250.151", 2),
+        Err(DecimalError::TooPrecise { max_decimals: 2 })
+    );
+}
+
+#[test]
+fn malformed_values_are_rejected() {
+    assert_eq!(parse_scaled("", 2), Err(DecimalError::Empty));
+    assert_eq!(parse_scaled(".", 2), Err(DecimalError::Empty));
+    assert_eq!(parse_scaled("-1", 2), Err(DecimalError::InvalidCharacter));
+    assert_eq!(parse_scaled("1e5", 2), Err(DecimalError::InvalidCharacter));
+    assert_eq!(
+        parse_scaled("1.2.3", 2),
+        Err(DecimalError::InvalidCharacter)
+    );
+    assert_eq!(
+        parse_scaled("184467440737095516.16", 2),
+        Err(DecimalError::Overflow)
+    );
+}
+
+#[test]
+fn formats_with_the_full_scale() {
+    assert_eq!(format_scaled(6425015, 2), "64250.15");
+    assert_eq!(format_scaled(1_500_000, 8), "0.01500000");
+    assert_eq!(format_scaled(7, 0), "7");
+}
@@ -0,0 +1,66 @@
+use crate::helpers::{limit_order, spawn_app};
+
+#[tokio::test]
+async fn liveness_needs_no_signature() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/health/live", app.address))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn readiness_reports_the_engine_link() {
+    let app = spawn_app().await;
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(format!("{}/health/ready", app.address))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["engine"]["connected"], true);
+    assert_eq!(body["engine"]["command_queue_depth"], 1);
+    assert_eq!(body["database"]["reachable"], true);
+}
+
+#[tokio::test]
+async fn not_ready_while_the_engine_is_disconnected() {
+    let app = spawn_app().await;
+    app.engine_link.set_connected(false);
+
+    let response = reqwest::get(format!("{}/health/ready", app.address))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["engine"]["connected"], false);
+}
+
+#[tokio::test]
+async fn metrics_count_requests_by_route_and_status() {
+    let app = spawn_app().await;
+    app.signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+
+    let response = reqwest::get(format!("{}/metrics", app.address))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(
+        body.contains(
+            r#"gateway_http_requests_total{method="POST",route="/orders",status="200"} 1"#
+        )
+    );
+    assert!(body.contains("gateway_command_queue_depth 1"));
+}
@@ -0,0 +1,178 @@
+use api_gateway::authentication::{issue_api_key, sign};
+use api_gateway::configuration::{Settings, get_configuration};
+use api_gateway::health::EngineLink;
+use api_gateway::instruments::Instruments;
+use api_gateway::messages::trading::WireMessage;
+use api_gateway::ticker::Tickers;
+use observability::telemetry::{get_subscriber, init_subscriber};
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::net::TcpListener;
+use std::sync::Arc;
+
+// Logs stay quiet unless `TEST_LOG` is set, e.g. `TEST_LOG=true cargo test | bunyan`
+static TRACING: Lazy<()> = Lazy::new(|| {
+    let default_filter_level = "info".to_string();
+    let subscriber_name = "test".to_string();
+    if std::env::var("TEST_LOG").is_ok() {
+        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        init_subscriber(subscriber);
+    } else {
+        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        init_subscriber(subscriber);
+    }
+});
+
+pub struct TestApp {
+    pub address: String,
+    pub db_pool: SqlitePool,
+    pub command_rx: tokio::sync::mpsc::Receiver<WireMessage>,
+    /// Not driven by a connection manager, tests flip it by hand.
+    pub engine_link: Arc<EngineLink>,
+    /// Not following an engine, ticker tests start a follower themselves.
+    pub tickers: Arc<Tickers>,
+    pub api_key: String,
+    pub secret: String,
+}
+
+pub const TEST_USER_ID: u64 = 42;
+
+/// A valid BTC-USD buy order.
+pub fn limit_order() -> serde_json::Value {
+    serde_json::json!({
+        "side": "buy",
+        "price": "64250.15",
+        "quantity": "0.015",
+        "base_currency": "BTC",
+        "quote_currency": "USD",
+    })
+}
+
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+impl TestApp {
+    /// Sends a request signed with the test user's api key.
+    pub async fn signed_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.signed_request_at(method, path, body, now_millis())
+            .await
+    }
+
+    pub async fn signed_request_at(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: &serde_json::Value,
+        timestamp: u64,
+    ) -> reqwest::Response {
+        self.signed_request_with(method, path, body, timestamp, &[])
+            .await
+    }
+
+    pub async fn signed_request_with(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: &serde_json::Value,
+        timestamp: u64,
+        headers: &[(&str, &str)],
+    ) -> reqwest::Response {
+        let body = serde_json::to_vec(body).unwrap();
+        let timestamp = timestamp.to_string();
+        let signature = sign(&self.secret, &timestamp, method.as_str(), path, &body);
+
+        let mut request = reqwest::Client::new()
+            .request(method, format!("{}{}", self.address, path))
+            .header("Content-Type", "application/json")
+            .header("X-API-KEY", &self.api_key)
+            .header("X-TIMESTAMP", timestamp)
+            .header("X-SIGNATURE", signature);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        request
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+}
+
+pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawns the gateway with the base configuration, adjusted by `configure`.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    Lazy::force(&TRACING);
+
+    let mut configuration = get_configuration().expect("Failed to read configuration");
+    configure(&mut configuration);
+
+    // A single connection keeps every query on the same in-memory database
+    let db_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory database");
+    sqlx::migrate!("../migrations")
+        .run(&db_pool)
+        .await
+        .expect("Failed to migrate the database");
+
+    let cipher = configuration
+        .authentication
+        .secret_cipher()
+        .expect("Invalid encryption key");
+    let (api_key, secret) = issue_api_key(&db_pool, &cipher, TEST_USER_ID)
+        .await
+        .expect("Failed to issue api key");
+
+    let instruments = Instruments::load(&db_pool)
+        .await
+        .expect("Failed to load instruments");
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+    let port = listener.local_addr().unwrap().port();
+    let (command_tx, command_rx) = tokio::sync::mpsc::channel::<WireMessage>(100);
+
+    let engine_link = Arc::new(EngineLink::default());
+    engine_link.set_connected(true);
+
+    let tickers = Arc::new(Tickers::default());
+
+    let server = api_gateway::startup::run_http(
+        listener,
+        command_tx,
+        engine_link.clone(),
+        db_pool.clone(),
+        instruments,
+        configuration.authentication,
+        configuration.rate_limit,
+        tickers.clone(),
+        configuration.fees,
+    )
+    .expect("Failed to start server");
+    tokio::spawn(server);
+
+    TestApp {
+        address: format!("http://127.0.0.1:{}", port),
+        db_pool,
+        command_rx,
+        engine_link,
+        tickers,
+        api_key,
+        secret,
+    }
+}
@@ -0,0 +1,106 @@
+use crate::helpers::{limit_order, now_millis, spawn_app};
+
+#[tokio::test]
+async fn retry_with_the_same_key_replays_the_first_response() {
+    let mut app = spawn_app().await;
+    let headers = [("Idempotency-Key", "retry-1")];
+
+    let first = app
+        .signed_request_with(
+            reqwest::Method::POST,
+            "/orders",
+            &limit_order(),
+            now_millis(),
+            &headers,
+        )
+        .await;
+    assert_eq!(first.status().as_u16(), 200);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first_body = first.text().await.unwrap();
+
+    let retry = app
+        .signed_request_with(
+            reqwest::Method::POST,
+            "/orders",
+            &limit_order(),
+            now_millis(),
+            &headers,
+        )
+        .await;
+    assert_eq!(retry.status().as_u16(), 200);
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    assert_eq!(retry.text().await.unwrap(), first_body);
+
+    // Only the first request reached the engine
+    assert!(app.command_rx.recv().await.is_some());
+    assert!(app.command_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_another_request_is_rejected() {
+    let app = spawn_app().await;
+    let headers = [("Idempotency-Key", "retry-2")];
+    let mut other_order = limit_order();
+    other_order["price"] = serde_json::json!("64000");
+
+    app.signed_request_with(
+        reqwest::Method::POST,
+        "/orders",
+        &limit_order(),
+        now_millis(),
+        &headers,
+    )
+    .await;
+    let response = app
+        .signed_request_with(
+            reqwest::Method::POST,
+            "/orders",
+            &other_order,
+            now_millis(),
+            &headers,
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "idempotency_key_reused");
+}
+
+#[tokio::test]
+async fn a_response_that_could_not_be_saved_keeps_the_key_reserved() {
+    let mut app = spawn_app().await;
+    let headers = [("Idempotency-Key", "retry-3")];
+    sqlx::query(
+        "CREATE TRIGGER fail_save BEFORE UPDATE ON idempotency
+        BEGIN SELECT RAISE(FAIL, 'disk full'); END",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let first = app
+        .signed_request_with(
+            reqwest::Method::POST,
+            "/orders",
+            &limit_order(),
+            now_millis(),
+            &headers,
+        )
+        .await;
+    assert_eq!(first.status().as_u16(), 200);
+
+    let retry = app
+        .signed_request_with(
+            reqwest::Method::POST,
+            "/orders",
+            &limit_order(),
+            now_millis(),
+            &headers,
+        )
+        .await;
+    assert_eq!(retry.status().as_u16(), 409);
+
+    // The order reached the engine once
+    assert!(app.command_rx.recv().await.is_some());
+    assert!(app.command_rx.try_recv().is_err());
+}
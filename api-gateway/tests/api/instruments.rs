@@ -0,0 +1,25 @@
+use api_gateway::instruments::Instrument;
+
+fn btc() -> Instrument {
+    Instrument {
+        name: "BTC".into(),
+        scaling_factor: 8,
+    }
+}
+
+#[test]
+fn notionals_lose_the_base_scale() {
+    // 0.5 BTC at 64250.15 USD, scaled by 2
+    let notional = 6425015u128 * 50_000_000;
+
+    assert_eq!(btc().quote_amount(notional), 3212507);
+}
+
+#[test]
+fn fees_round_up_to_the_quote_scale() {
+    let notional = 6425015u128 * 50_000_000;
+
+    // 32125.075 USD at 10 bps is 32.125075
+    assert_eq!(btc().quote_fee(notional, 10), 3213);
+    assert_eq!(btc().quote_fee(0, 10), 0);
+}
@@ -0,0 +1,13 @@
+mod authentication;
+mod batch;
+mod candles;
+mod configuration;
+mod decimal;
+mod health;
+mod helpers;
+mod idempotency;
+mod instruments;
+mod order;
+mod rate_limit;
+mod ticker;
+mod trades;
@@ -0,0 +1,230 @@
+use crate::helpers::{limit_order, spawn_app};
+use api_gateway::messages::trading::{Side, wire_message::Payload};
+
+#[tokio::test]
+async fn decimal_amounts_are_scaled_by_the_instrument() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["side"], "buy");
+    assert_eq!(body["price"], "64250.15");
+    assert_eq!(body["quantity"], "0.01500000");
+
+    match app.command_rx.recv().await.unwrap().payload {
+        Some(Payload::PlaceLimitOrder(order)) => {
+            assert_eq!(order.side(), Side::Buy);
+            assert_eq!(order.price, 6425015);
+            assert_eq!(order.quantity, 1_500_000);
+        }
+        other => panic!("unexpected command {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn invalid_orders_are_rejected_with_400() {
+    let mut app = spawn_app().await;
+    let test_cases = [
+        ("price", serde_json::json!("64250.151"), "too precise price"),
+        (
+            "quantity",
+            serde_json::json!("0.000000001"),
+            "too precise quantity",
+        ),
+        ("price", serde_json::json!(6425015), "integer price"),
+        ("side", serde_json::json!("sideways"), "unknown side"),
+        ("side", serde_json::json!(1), "integer side"),
+        (
+            "base_currency",
+            serde_json::json!("DOGE"),
+            "unknown instrument",
+        ),
+    ];
+
+    for (field, value, description) in test_cases {
+        let mut order = limit_order();
+        order[field] = value;
+
+        let response = app
+            .signed_request(reqwest::Method::POST, "/orders", &order)
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "the API did not reject an order with {}",
+            description
+        );
+    }
+    assert!(app.command_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn validation_errors_list_every_offending_field() {
+    let app = spawn_app().await;
+    let mut order = limit_order();
+    order["price"] = serde_json::json!("64250.151");
+    order["quantity"] = serde_json::json!("0");
+    order["base_currency"] = serde_json::json!("USD");
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &order)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "validation_failed");
+    let errors: Vec<(String, String)> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| {
+            (
+                e["field"].as_str().unwrap().to_string(),
+                e["code"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    assert!(errors.contains(&("price".into(), "too_precise".into())));
+    assert!(errors.contains(&("quantity".into(), "must_be_positive".into())));
+    assert!(errors.contains(&("quote_currency".into(), "same_currency".into())));
+}
+
+#[tokio::test]
+async fn orders_past_the_largest_notional_are_rejected() {
+    let mut app = spawn_app().await;
+    // 64250.15 USD times 15000 BTC, 6425015 cents times 1.5e12 satoshis,
+    // is past what 64 bit notionals hold
+    let mut order = limit_order();
+    order["quantity"] = serde_json::json!("15000");
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &order)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "quantity");
+    assert_eq!(body["errors"][0]["code"], "notional_too_large");
+    assert!(app.command_rx.try_recv().is_err());
+
+    // Half of it fits
+    order["quantity"] = serde_json::json!("7500");
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &order)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn malformed_body_reports_the_field_path() {
+    let app = spawn_app().await;
+    let mut order = limit_order();
+    order["side"] = serde_json::json!("sideways");
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &order)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "malformed_body");
+    assert_eq!(body["errors"][0]["field"], "side");
+    assert_eq!(body["errors"][0]["code"], "invalid_value");
+}
+
+#[tokio::test]
+async fn cancel_of_order_zero_is_rejected() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .signed_request(
+            reqwest::Method::DELETE,
+            "/orders",
+            &serde_json::json!({ "order_id": 0 }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "order_id");
+    assert_eq!(body["errors"][0]["code"], "range");
+    assert!(app.command_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn client_order_id_is_forwarded_to_the_engine() {
+    let mut app = spawn_app().await;
+    let mut order = limit_order();
+    order["client_order_id"] = serde_json::json!("quote-7f3a");
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &order)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["client_order_id"], "quote-7f3a");
+    match app.command_rx.recv().await.unwrap().payload {
+        Some(Payload::PlaceLimitOrder(order)) => assert_eq!(order.client_order_id, "quote-7f3a"),
+        other => panic!("unexpected command {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn cancel_by_client_order_id_is_forwarded_to_the_engine() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .signed_request(
+            reqwest::Method::DELETE,
+            "/orders",
+            &serde_json::json!({ "client_order_id": "quote-7f3a" }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    match app.command_rx.recv().await.unwrap().payload {
+        Some(Payload::CancelOrder(cancel)) => {
+            assert_eq!(cancel.order_id, 0);
+            assert_eq!(cancel.client_order_id, "quote-7f3a");
+        }
+        other => panic!("unexpected command {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn cancel_needs_exactly_one_order_reference() {
+    let app = spawn_app().await;
+    let test_cases = [
+        serde_json::json!({}),
+        serde_json::json!({ "order_id": 1, "client_order_id": "quote-7f3a" }),
+    ];
+
+    for body in test_cases {
+        let response = app
+            .signed_request(reqwest::Method::DELETE, "/orders", &body)
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "accepted {}", body);
+    }
+}
+
+#[tokio::test]
+async fn commands_carry_the_request_correlation_id() {
+    let mut app = spawn_app().await;
+
+    for _ in 0..2 {
+        app.signed_request(reqwest::Method::POST, "/orders", &limit_order())
+            .await;
+    }
+
+    let first = app.command_rx.recv().await.unwrap();
+    let second = app.command_rx.recv().await.unwrap();
+    assert!(!first.correlation_id.is_empty());
+    assert_ne!(first.correlation_id, second.correlation_id);
+}
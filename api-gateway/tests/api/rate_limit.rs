@@ -0,0 +1,100 @@
+use crate::helpers::{limit_order, spawn_app_with};
+
+#[tokio::test]
+async fn responses_carry_the_remaining_budget() {
+    let app = spawn_app_with(|c| {
+        c.rate_limit.per_api_key.capacity = 10;
+        c.rate_limit.order_entry_weight = 2;
+    })
+    .await;
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["x-ratelimit-limit"], "10");
+    assert_eq!(response.headers()["x-ratelimit-remaining"], "8");
+}
+
+#[tokio::test]
+async fn api_key_over_the_limit_gets_429_with_retry_after() {
+    let app = spawn_app_with(|c| {
+        c.rate_limit.per_api_key.capacity = 2;
+        c.rate_limit.per_api_key.refill_per_second = 1;
+        c.rate_limit.order_entry_weight = 1;
+    })
+    .await;
+
+    for _ in 0..2 {
+        let response = app
+            .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let response = app
+        .signed_request(reqwest::Method::POST, "/orders", &limit_order())
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers()["retry-after"], "1");
+    assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+}
+
+#[tokio::test]
+async fn ip_limit_applies_before_authentication() {
+    let app = spawn_app_with(|c| {
+        c.rate_limit.per_ip.capacity = 1;
+        c.rate_limit.per_ip.refill_per_second = 1;
+        c.rate_limit.order_entry_weight = 1;
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    let first = client
+        .post(format!("{}/orders", app.address))
+        .json(&limit_order())
+        .send()
+        .await
+        .unwrap();
+    let second = client
+        .post(format!("{}/orders", app.address))
+        .json(&limit_order())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(first.status().as_u16(), 401);
+    assert_eq!(second.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn each_item_of_a_batch_is_charged_like_an_order() {
+    let mut app = spawn_app_with(|c| {
+        c.rate_limit.per_api_key.capacity = 10;
+        c.rate_limit.per_api_key.refill_per_second = 1;
+        c.rate_limit.order_entry_weight = 2;
+    })
+    .await;
+
+    let response = app
+        .signed_request(
+            reqwest::Method::POST,
+            "/orders/batch",
+            &serde_json::json!({ "orders": vec![limit_order(); 3] }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["x-ratelimit-remaining"], "4");
+    assert!(app.command_rx.recv().await.is_some());
+
+    let response = app
+        .signed_request(
+            reqwest::Method::POST,
+            "/orders/batch",
+            &serde_json::json!({ "orders": vec![limit_order(); 3] }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(app.command_rx.try_recv().is_err());
+}
@@ -0,0 +1,332 @@
+use crate::helpers::{TestApp, now_millis, spawn_app};
+use api_gateway::messages::trading::{
+    BookTop, Side, Subscribed, TradeOccurred, WireMessage, wire_message::Payload,
+};
+use api_gateway::ticker::follow_engine;
+use prost::Message;
+use sqlx::SqlitePool;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+const HOUR_US: i64 = 3600 * 1_000_000;
+const EPOCH: u64 = 1_700_000_000_000_000;
+
+async fn insert_order(pool: &SqlitePool, order_id: i64, base: &str, side: i64, price: i64) {
+    sqlx::query(
+        "INSERT INTO orders (order_id, base_currency, quote_currency, side, quantity, price, status)
+        VALUES ($1, $2, 'USD', $3, 1000000, $4, 'filled')",
+    )
+    .bind(order_id)
+    .bind(base)
+    .bind(side)
+    .bind(price)
+    .execute(pool)
+    .await
+    .expect("Failed to insert order");
+}
+
+fn timestamp_us(ago_us: i64) -> i64 {
+    now_millis() as i64 * 1000 - ago_us
+}
+
+/// A trade between orders 1 and 2, `ago_us` before now, persisted from the
+/// event at `sequence`.
+async fn insert_trade(
+    pool: &SqlitePool,
+    sequence: i64,
+    base: &str,
+    price: i64,
+    quantity: i64,
+    ago_us: i64,
+) {
+    sqlx::query(
+        "INSERT INTO trades (taker_order_id, maker_order_id, filled_qty, price, aggressor_side, base_currency, quote_currency, engine_timestamp_us, epoch, sequence)
+        VALUES (1, 2, $1, $2, 1, $3, 'USD', $4, $5, $6)",
+    )
+    .bind(quantity)
+    .bind(price)
+    .bind(base)
+    .bind(timestamp_us(ago_us))
+    .bind(EPOCH as i64)
+    .bind(sequence)
+    .execute(pool)
+    .await
+    .expect("Failed to insert trade");
+}
+
+fn trade(epoch: u64, sequence: u64, price: u64, quantity: u64, ago_us: i64) -> WireMessage {
+    WireMessage {
+        payload: Some(Payload::TradeOccurred(TradeOccurred {
+            taker_order_id: 1,
+            maker_order_id: 2,
+            quantity,
+            price,
+            taker_side: Side::Buy.into(),
+            base_currency: "BTC".into(),
+            quote_currency: "USD".into(),
+        })),
+        engine_timestamp_us: timestamp_us(ago_us) as u64,
+        sequence,
+        epoch,
+        ..Default::default()
+    }
+}
+
+fn book_top(best_bid: u64, best_ask: u64) -> BookTop {
+    BookTop {
+        base_currency: "BTC".into(),
+        quote_currency: "USD".into(),
+        best_bid,
+        bid_quantity: 1_000_000,
+        best_ask,
+        ask_quantity: 1_000_000,
+    }
+}
+
+/// An engine running `epoch` that logged `events`.
+struct Engine {
+    address: String,
+    /// The sequence each subscription was from.
+    subscriptions: UnboundedReceiver<u64>,
+}
+
+/// Serves subscriptions like the engine: `Subscribed` with `book_top`, then
+/// `events` from the sequence asked for, or all of them as if they came
+/// next when it is 0. The first `closed` connections are closed after
+/// that, the next ones kept open.
+async fn spawn_engine(
+    epoch: u64,
+    book_top: Option<BookTop>,
+    events: Vec<WireMessage>,
+    closed: usize,
+) -> Engine {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (subscriptions_tx, subscriptions) = unbounded_channel();
+    tokio::spawn(async move {
+        for connection in 0.. {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let len = stream.read_u32().await.unwrap();
+            let mut buf = vec![0; len as usize];
+            stream.read_exact(&mut buf).await.unwrap();
+            let Some(Payload::Subscribe(subscribe)) =
+                WireMessage::decode(buf.as_slice()).unwrap().payload
+            else {
+                panic!("expected a Subscribe");
+            };
+            let from = subscribe.from_sequence;
+            subscriptions_tx.send(from).unwrap();
+
+            let subscribed = WireMessage {
+                payload: Some(Payload::Subscribed(Subscribed {
+                    from_sequence: from,
+                    first_available: 1,
+                    book_top: book_top.clone(),
+                })),
+                epoch,
+                ..Default::default()
+            };
+            let events = events.iter().filter(|event| event.sequence >= from);
+            for message in std::iter::once(&subscribed).chain(events) {
+                let frame = message.encode_to_vec();
+                stream.write_u32(frame.len() as u32).await.unwrap();
+                stream.write_all(&frame).await.unwrap();
+            }
+            if connection >= closed {
+                // A closed stream would be subscribed to again
+                tokio::spawn(async move {
+                    let _open = stream;
+                    std::future::pending::<()>().await;
+                });
+            }
+        }
+    });
+    Engine {
+        address,
+        subscriptions,
+    }
+}
+
+/// Follows the engine at `engine_addr`, once the test's trades are persisted.
+fn follow(app: &TestApp, engine_addr: String) {
+    tokio::spawn(follow_engine(
+        app.db_pool.clone(),
+        engine_addr,
+        app.tickers.clone(),
+    ));
+}
+
+/// The ticker once `ready` holds for it.
+async fn wait_for_ticker(
+    address: &str,
+    instrument: &str,
+    ready: impl Fn(&serde_json::Value) -> bool,
+) -> serde_json::Value {
+    for _ in 0..100 {
+        let response = reqwest::get(format!("{}/ticker/{}", address, instrument))
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(response.status().as_u16(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        if ready(&body) {
+            return body;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("The ticker of {} never got ready", instrument);
+}
+
+#[tokio::test]
+async fn the_ticker_covers_the_trades_of_the_last_24_hours() {
+    let app = spawn_app().await;
+    insert_order(&app.db_pool, 1, "BTC", 1, 6500000).await;
+    insert_order(&app.db_pool, 2, "BTC", 2, 6000000).await;
+    insert_trade(&app.db_pool, 1, "BTC", 6000000, 1_000_000, 25 * HOUR_US).await;
+    insert_trade(&app.db_pool, 2, "BTC", 6300000, 1_000_000, 2 * HOUR_US).await;
+    let mut engine = spawn_engine(
+        EPOCH,
+        Some(book_top(6400000, 6450000)),
+        vec![
+            // Persisted already, not subscribed to again
+            trade(EPOCH, 2, 6300000, 1_000_000, 2 * HOUR_US),
+            trade(EPOCH, 3, 6426000, 3_000_000, HOUR_US),
+            WireMessage {
+                payload: Some(Payload::BookTop(book_top(6400000, 6440000))),
+                sequence: 4,
+                epoch: EPOCH,
+                ..Default::default()
+            },
+        ],
+        0,
+    )
+    .await;
+    follow(&app, engine.address.clone());
+
+    // The last event moved the ask
+    let ticker = wait_for_ticker(&app.address, "BTC-USD", |ticker| {
+        ticker["best_ask"] == "64400.00"
+    })
+    .await;
+
+    // Right after the last persisted trade
+    assert_eq!(engine.subscriptions.recv().await, Some(3));
+    assert_eq!(ticker["instrument"], "BTC-USD");
+    assert_eq!(ticker["trade_count"], 2);
+    assert_eq!(ticker["last_price"], "64260.00");
+    assert_eq!(ticker["best_bid"], "64000.00");
+    assert_eq!(ticker["open"], "63000.00");
+    assert_eq!(ticker["high"], "64260.00");
+    assert_eq!(ticker["low"], "63000.00");
+    assert_eq!(ticker["volume"], "0.04000000");
+    assert_eq!(ticker["quote_volume"], "2557.80");
+    assert_eq!(ticker["vwap"], "63945.00");
+    assert_eq!(ticker["price_change_percent"], "2.00");
+}
+
+#[tokio::test]
+async fn trades_of_a_restarted_engine_are_counted() {
+    let app = spawn_app().await;
+    insert_order(&app.db_pool, 1, "BTC", 1, 6500000).await;
+    insert_order(&app.db_pool, 2, "BTC", 2, 6000000).await;
+    insert_trade(&app.db_pool, 2, "BTC", 6300000, 1_000_000, 2 * HOUR_US).await;
+    // Numbered from 1 again, after the persisted trade
+    let mut engine = spawn_engine(
+        EPOCH + 1,
+        None,
+        vec![trade(EPOCH + 1, 1, 6426000, 1_000_000, HOUR_US)],
+        0,
+    )
+    .await;
+    follow(&app, engine.address.clone());
+
+    let ticker =
+        wait_for_ticker(&app.address, "BTC-USD", |ticker| ticker["trade_count"] == 2).await;
+
+    // After the persisted trade, then from the next event once the epoch
+    // told the engine restarted
+    assert_eq!(engine.subscriptions.recv().await, Some(3));
+    assert_eq!(engine.subscriptions.recv().await, Some(0));
+    assert_eq!(ticker["last_price"], "64260.00");
+    assert_eq!(ticker["best_bid"], serde_json::Value::Null);
+    assert_eq!(ticker["best_ask"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn a_broken_stream_is_resumed_after_the_last_event_seen() {
+    let app = spawn_app().await;
+    let mut engine = spawn_engine(
+        EPOCH,
+        None,
+        vec![
+            trade(EPOCH, 1, 6300000, 1_000_000, 2 * HOUR_US),
+            trade(EPOCH, 2, 6426000, 1_000_000, HOUR_US),
+            WireMessage {
+                payload: Some(Payload::BookTop(book_top(6400000, 6440000))),
+                sequence: 3,
+                epoch: EPOCH,
+                ..Default::default()
+            },
+        ],
+        1,
+    )
+    .await;
+    follow(&app, engine.address.clone());
+
+    let ticker = wait_for_ticker(&app.address, "BTC-USD", |ticker| {
+        ticker["best_ask"] == "64400.00"
+    })
+    .await;
+
+    // Nothing persisted, then after the book top
+    assert_eq!(engine.subscriptions.recv().await, Some(0));
+    assert_eq!(engine.subscriptions.recv().await, Some(4));
+    assert_eq!(ticker["trade_count"], 2);
+}
+
+#[tokio::test]
+async fn an_idle_instrument_keeps_its_last_price() {
+    let app = spawn_app().await;
+    insert_order(&app.db_pool, 1, "ETH", 1, 300000).await;
+    insert_order(&app.db_pool, 2, "ETH", 2, 300000).await;
+    insert_trade(&app.db_pool, 1, "ETH", 300000, 100_000_000, 25 * HOUR_US).await;
+    follow(&app, spawn_engine(EPOCH, None, Vec::new(), 0).await.address);
+
+    let mut tickers = serde_json::Value::Null;
+    for _ in 0..100 {
+        tickers = reqwest::get(format!("{}/ticker", app.address))
+            .await
+            .expect("Failed to execute request")
+            .json()
+            .await
+            .unwrap();
+        if !tickers.as_array().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    let tickers = tickers.as_array().unwrap();
+    assert_eq!(tickers.len(), 1);
+    let ticker = &tickers[0];
+    assert_eq!(ticker["instrument"], "ETH-USD");
+    assert_eq!(ticker["last_price"], "3000.00");
+    assert_eq!(ticker["best_bid"], serde_json::Value::Null);
+    assert_eq!(ticker["open"], serde_json::Value::Null);
+    assert_eq!(ticker["volume"], "0.00000000");
+    assert_eq!(ticker["trade_count"], 0);
+    assert_eq!(ticker["vwap"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn an_unknown_instrument_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/ticker/BTC-DOGE", app.address))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["code"], "unknown_instrument");
+}
@@ -0,0 +1,218 @@
+use crate::helpers::{TEST_USER_ID, spawn_app};
+use sqlx::SqlitePool;
+
+/// 2023-11-14T22:13:20Z, in milliseconds.
+const START_MS: i64 = 1_700_000_000_000;
+const OTHER_USER_ID: i64 = 7;
+
+/// Order 1, a buy of the test user, and order 2, a sell of another user.
+async fn insert_orders(pool: &SqlitePool) {
+    for (order_id, user_id, side) in [(1, TEST_USER_ID as i64, 1), (2, OTHER_USER_ID, 2)] {
+        sqlx::query(
+            "INSERT INTO orders (order_id, user_id, client_order_id, base_currency, quote_currency, side, quantity, price)
+            VALUES ($1, $2, $3, 'BTC', 'USD', $4, 10000000, 6425000)",
+        )
+        .bind(order_id)
+        .bind(user_id)
+        .bind(format!("client-{}", order_id))
+        .bind(side)
+        .execute(pool)
+        .await
+        .expect("Failed to insert order");
+    }
+}
+
+/// A trade of 0.015 BTC at `price` cents, where order 1 took liquidity
+/// from order 2, `second` seconds after `START_MS`.
+async fn insert_trade(pool: &SqlitePool, sequence: i64, price: i64, second: i64) {
+    sqlx::query(
+        "INSERT INTO trades (taker_order_id, maker_order_id, filled_qty, price, aggressor_side, base_currency, quote_currency, sequence, engine_timestamp_us)
+        VALUES (1, 2, 1500000, $1, 1, 'BTC', 'USD', $2, $3)",
+    )
+    .bind(price)
+    .bind(sequence)
+    .bind((START_MS + second * 1000) * 1000)
+    .execute(pool)
+    .await
+    .expect("Failed to insert trade");
+}
+
+async fn get_json(url: String) -> serde_json::Value {
+    let response = reqwest::get(url).await.expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+fn trade_ids(body: &serde_json::Value) -> Vec<i64> {
+    body["trades"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|trade| trade["id"].as_i64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn trades_are_served_newest_first_a_page_at_a_time() {
+    let app = spawn_app().await;
+    insert_orders(&app.db_pool).await;
+    for second in 0..3 {
+        insert_trade(&app.db_pool, 100 + second, 6425000 + second, second).await;
+    }
+
+    let first = get_json(format!("{}/trades/BTC-USD?limit=2", app.address)).await;
+    assert_eq!(first["instrument"], "BTC-USD");
+    assert_eq!(trade_ids(&first), [3, 2]);
+    let trade = &first["trades"][0];
+    assert_eq!(trade["sequence"], 102);
+    assert_eq!(trade["price"], "64250.02");
+    assert_eq!(trade["quantity"], "0.01500000");
+    assert_eq!(trade["aggressor_side"], "buy");
+    assert_eq!(trade["timestamp"], START_MS + 2000);
+    assert_eq!(first["next_before"], 2);
+
+    let second = get_json(format!("{}/trades/BTC-USD?limit=2&before=2", app.address)).await;
+    assert_eq!(trade_ids(&second), [1]);
+    assert_eq!(second["next_before"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn trades_can_be_filtered_by_time() {
+    let app = spawn_app().await;
+    insert_orders(&app.db_pool).await;
+    for second in 0..3 {
+        insert_trade(&app.db_pool, 100 + second, 6425000, second).await;
+    }
+
+    let query = format!("from={}&to={}", START_MS + 1000, START_MS + 2000);
+    let body = get_json(format!("{}/trades/BTC-USD?{}", app.address, query)).await;
+
+    assert_eq!(trade_ids(&body), [2]);
+}
+
+#[tokio::test]
+async fn invalid_trade_parameters_are_reported_per_field() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/trades/BTC-DOGE?before=latest&limit=5000",
+        app.address
+    ))
+    .await
+    .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let codes: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["code"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        codes,
+        ["unknown_instrument", "invalid_cursor", "invalid_limit"]
+    );
+}
+
+#[tokio::test]
+async fn fills_carry_the_role_and_fee_of_the_user() {
+    let app = spawn_app().await;
+    insert_orders(&app.db_pool).await;
+    insert_trade(&app.db_pool, 100, 6425000, 0).await;
+
+    let path = format!("/users/{}/fills", TEST_USER_ID);
+    let response = app
+        .signed_request(reqwest::Method::GET, &path, &serde_json::Value::Null)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let fills = body["fills"].as_array().unwrap();
+    assert_eq!(fills.len(), 1);
+    let fill = &fills[0];
+    assert_eq!(fill["trade_id"], 1);
+    assert_eq!(fill["order_id"], 1);
+    assert_eq!(fill["client_order_id"], "client-1");
+    assert_eq!(fill["instrument"], "BTC-USD");
+    assert_eq!(fill["side"], "buy");
+    assert_eq!(fill["role"], "taker");
+    assert_eq!(fill["price"], "64250.00");
+    assert_eq!(fill["quantity"], "0.01500000");
+    // 20 basis points of 963.75, rounded up
+    assert_eq!(fill["fee"], "1.93");
+    assert_eq!(fill["timestamp"], START_MS);
+    assert_eq!(body["next_before"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn fills_of_another_user_are_forbidden() {
+    let app = spawn_app().await;
+    insert_orders(&app.db_pool).await;
+    insert_trade(&app.db_pool, 100, 6425000, 0).await;
+
+    let path = format!("/users/{}/fills", OTHER_USER_ID);
+    let response = app
+        .signed_request(reqwest::Method::GET, &path, &serde_json::Value::Null)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "forbidden");
+}
+
+fn fill_keys(body: &serde_json::Value) -> Vec<(i64, i64)> {
+    body["fills"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|fill| {
+            let trade_id = fill["trade_id"].as_i64().unwrap();
+            (trade_id, fill["order_id"].as_i64().unwrap())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn both_fills_of_a_self_trade_are_paged_through() {
+    let app = spawn_app().await;
+    insert_orders(&app.db_pool).await;
+    insert_trade(&app.db_pool, 100, 6425000, 0).await;
+    // Order 1 then takes liquidity from order 3, also the test user's
+    sqlx::query(
+        "INSERT INTO orders (order_id, user_id, base_currency, quote_currency, side, quantity, price)
+        VALUES (3, $1, 'BTC', 'USD', 2, 10000000, 6425000)",
+    )
+    .bind(TEST_USER_ID as i64)
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert order");
+    sqlx::query(
+        "INSERT INTO trades (taker_order_id, maker_order_id, filled_qty, price, aggressor_side, base_currency, quote_currency, sequence, engine_timestamp_us)
+        VALUES (1, 3, 1500000, 6425000, 1, 'BTC', 'USD', 101, $1)",
+    )
+    .bind((START_MS + 1000) * 1000)
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert trade");
+
+    let mut path = format!("/users/{}/fills?limit=1", TEST_USER_ID);
+    let mut pages = Vec::new();
+    loop {
+        let response = app
+            .signed_request(reqwest::Method::GET, &path, &serde_json::Value::Null)
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        pages.push(fill_keys(&body));
+        if body["next_before"].is_null() {
+            break;
+        }
+        path = format!(
+            "/users/{}/fills?limit=1&before={}&before_order={}",
+            TEST_USER_ID, body["next_before"], body["next_before_order"]
+        );
+    }
+
+    assert_eq!(pages, [vec![(2, 3)], vec![(2, 1)], vec![(1, 1)], vec![]]);
+}
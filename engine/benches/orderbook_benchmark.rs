@@ -5,8 +5,8 @@ use engine::messages::trading::Side;
 fn setup_book() -> OrderBook {
     let mut book = OrderBook::new();
     for i in 0..1000 {
-        book.add_limit_order(Side::Buy, 9999 - i, 10);
-        book.add_limit_order(Side::Sell, 10001 + i, 10);
+        book.add_limit_order(1, Side::Buy, 9999 - i, 10);
+        book.add_limit_order(1, Side::Sell, 10001 + i, 10);
     }
     book
 }
@@ -14,9 +14,9 @@ fn setup_book() -> OrderBook {
 fn orderbook_benches(c: &mut Criterion) {
     c.bench_function("add_limit_order_no_match", |bencher| {
         bencher.iter_batched(
-            || setup_book(),
+            setup_book,
             |mut book| {
-                book.add_limit_order(Side::Buy, black_box(9000), black_box(10));
+                book.add_limit_order(1, Side::Buy, black_box(9000), black_box(10));
             },
             criterion::BatchSize::PerIteration,
         );
@@ -24,9 +24,9 @@ fn orderbook_benches(c: &mut Criterion) {
 
     c.bench_function("add_limit_order_full_match_one", |bencher| {
         bencher.iter_batched(
-            || setup_book(),
+            setup_book,
             |mut book| {
-                book.add_limit_order(Side::Buy, black_box(10001), black_box(10));
+                book.add_limit_order(1, Side::Buy, black_box(10001), black_box(10));
             },
             criterion::BatchSize::PerIteration,
         );
@@ -34,9 +34,9 @@ fn orderbook_benches(c: &mut Criterion) {
 
     c.bench_function("add_limit_order_walk_the_book", |bencher| {
         bencher.iter_batched(
-            || setup_book(),
+            setup_book,
             |mut book| {
-                book.add_limit_order(Side::Buy, black_box(10005), black_box(50));
+                book.add_limit_order(1, Side::Buy, black_box(10005), black_box(50));
             },
             criterion::BatchSize::PerIteration,
         );
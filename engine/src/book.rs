@@ -10,6 +10,7 @@ use std::{
 type Price = u64;
 type Quantity = u64;
 type OrderId = u64;
+type UserId = u64;
 type OrderHandle = Rc<RefCell<Order>>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,6 +23,8 @@ pub enum OrderStatus {
 #[derive(Debug, Clone)]
 pub struct Order {
     pub id: OrderId,
+    pub user_id: UserId,
+    pub client_order_id: Option<String>,
     pub side: Side,
     pub price: Price,
     pub quantity: Quantity,
@@ -41,10 +44,18 @@ pub struct OrderBook {
     pub bids: BTreeMap<Price, VecDeque<OrderHandle>>,
     pub asks: BTreeMap<Price, VecDeque<OrderHandle>>,
     pub orders: HashMap<OrderId, OrderHandle>,
+    /// Client order ids of live orders, unique per user.
+    pub client_order_ids: HashMap<(UserId, String), OrderId>,
     pub next_order_id: OrderId,
     pub trades_buffer: Vec<Trade>,
 }
 
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // BTC-USD
 impl OrderBook {
     pub fn new() -> Self {
@@ -53,6 +64,7 @@ impl OrderBook {
             asks: BTreeMap::new(),
             trades_buffer: Vec::with_capacity(32),
             orders: HashMap::new(),
+            client_order_ids: HashMap::new(),
             next_order_id: 1,
         }
     }
@@ -65,14 +77,48 @@ impl OrderBook {
 
     pub fn add_limit_order(
         &mut self,
+        user_id: UserId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    ) -> (u64, &Vec<Trade>) {
+        self.insert_limit_order(user_id, None, side, price, quantity)
+    }
+
+    /// Like [`OrderBook::add_limit_order`], tagging the order with a client
+    /// order id that has to be unique among the user's live orders.
+    pub fn add_limit_order_with_client_id(
+        &mut self,
+        user_id: UserId,
+        client_order_id: String,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    ) -> Result<(u64, &Vec<Trade>), &'static str> {
+        if self
+            .client_order_ids
+            .contains_key(&(user_id, client_order_id.clone()))
+        {
+            return Err("Duplicate client order id");
+        }
+
+        Ok(self.insert_limit_order(user_id, Some(client_order_id), side, price, quantity))
+    }
+
+    fn insert_limit_order(
+        &mut self,
+        user_id: UserId,
+        client_order_id: Option<String>,
         side: Side,
         price: Price,
         quantity: Quantity,
     ) -> (u64, &Vec<Trade>) {
         let order_id = self.get_next_order_id();
-        log::debug!("next order id > {}", order_id);
+        tracing::debug!("next order id > {}", order_id);
         let mut order_handle = Rc::new(RefCell::new(Order {
             id: order_id,
+            user_id,
+            client_order_id,
             side,
             price,
             quantity,
@@ -95,12 +141,42 @@ impl OrderBook {
                 .or_insert_with(VecDeque::new)
                 .push_back(Rc::clone(&order_handle));
             self.orders.insert(order_id, Rc::clone(&order_handle));
+            if let Some(client_order_id) = &order.client_order_id {
+                self.client_order_ids
+                    .insert((user_id, client_order_id.clone()), order_id);
+            }
         }
 
         (order_id, &self.trades_buffer)
     }
 
-    pub fn cancel_order(&mut self, order_id: OrderId) -> Result<(), &'static str> {
+    /// Best price of `side` and the quantity resting at it, skipping the
+    /// cancelled orders left in the levels.
+    pub fn best(&self, side: Side) -> Option<(Price, Quantity)> {
+        let open_quantity = |(price, level): (&Price, &VecDeque<OrderHandle>)| {
+            let quantity: Quantity = level
+                .iter()
+                .map(|handle| handle.borrow())
+                .filter(|order| order.status == OrderStatus::Open)
+                .map(|order| order.quantity)
+                .sum();
+            (quantity > 0).then_some((*price, quantity))
+        };
+        match side {
+            Side::Buy => self.bids.iter().rev().find_map(open_quantity),
+            Side::Sell => self.asks.iter().find_map(open_quantity),
+            Side::Unspecified => None,
+        }
+    }
+
+    /// Cancels a resting order, returns the id its owner gave it. Orders owned
+    /// by someone else are reported as not found so callers can't probe for
+    /// other users' order ids.
+    pub fn cancel_order(
+        &mut self,
+        user_id: UserId,
+        order_id: OrderId,
+    ) -> Result<Option<String>, &'static str> {
         let order_handle = match self.orders.get(&order_id) {
             Some(handle) => handle.clone(),
             None => return Err("Order not found"),
@@ -108,30 +184,58 @@ impl OrderBook {
 
         let mut order = order_handle.borrow_mut();
 
+        if order.user_id != user_id {
+            return Err("Order not found");
+        }
+
         if order.status != OrderStatus::Open {
             return Err("Order is not open");
         }
 
         order.status = OrderStatus::Cancelled;
         self.orders.remove(&order_id);
-        Ok(())
+        let client_order_id = order.client_order_id.take();
+        if let Some(client_order_id) = &client_order_id {
+            self.client_order_ids
+                .remove(&(user_id, client_order_id.clone()));
+        }
+        Ok(client_order_id)
+    }
+
+    /// Cancels a resting order by the id its owner gave it, returns the
+    /// engine order id of the cancelled order.
+    pub fn cancel_order_by_client_id(
+        &mut self,
+        user_id: UserId,
+        client_order_id: &str,
+    ) -> Result<OrderId, &'static str> {
+        let order_id = match self
+            .client_order_ids
+            .get(&(user_id, client_order_id.to_string()))
+        {
+            Some(order_id) => *order_id,
+            None => return Err("Order not found"),
+        };
+
+        self.cancel_order(user_id, order_id)?;
+        Ok(order_id)
     }
 
     pub fn match_order(&mut self, taker_order: &mut Order) {
-        log::debug!("matching order # = {}", taker_order.id);
+        tracing::debug!("matching order # = {}", taker_order.id);
         self.trades_buffer.clear();
 
         let book_to_match = match taker_order.side {
             Side::Unspecified => panic!("no side unspecied allowed"),
             Side::Buy => {
-                log::debug!(
+                tracing::debug!(
                     "side is buy, matching order # {} against asks",
                     taker_order.id
                 );
                 &mut self.asks
             }
             Side::Sell => {
-                log::debug!(
+                tracing::debug!(
                     "side is sell, matching order # {} against bids",
                     taker_order.id
                 );
@@ -185,7 +289,7 @@ impl OrderBook {
                     }
 
                     let trade_quantity = taker_order.quantity.min(maker_order.quantity);
-                    log::debug!(
+                    tracing::debug!(
                         "filled qty {} for taker_order # {} and maker_order {}",
                         trade_quantity,
                         taker_order.id,
@@ -205,6 +309,10 @@ impl OrderBook {
                     if maker_order.quantity == 0 {
                         maker_order.status = OrderStatus::Filled;
                         self.orders.remove(&maker_order.id);
+                        if let Some(client_order_id) = maker_order.client_order_id.take() {
+                            self.client_order_ids
+                                .remove(&(maker_order.user_id, client_order_id));
+                        }
                         front_pops_needed += 1;
                         break;
                     }
@@ -229,7 +337,7 @@ impl OrderBook {
                 book_to_match.remove(&best_price);
             }
         }
-        log::debug!(
+        tracing::debug!(
             "no more price levels to go through for order #{}",
             taker_order.id
         );
@@ -237,12 +345,19 @@ impl OrderBook {
 
     /// Adds a new market order to the book.
     /// Market orders are filled immediately and are not added to the book.
-    pub fn add_market_order(&mut self, side: Side, quantity: Quantity) -> &Vec<Trade> {
+    pub fn add_market_order(
+        &mut self,
+        user_id: UserId,
+        side: Side,
+        quantity: Quantity,
+    ) -> &Vec<Trade> {
         let order_id = self.get_next_order_id();
         // A market order doesn't have a price, but we can model it
         // with a dummy price for the struct.
         let mut order = Order {
             id: order_id,
+            user_id,
+            client_order_id: None,
             side,
             price: 0,
             quantity,
@@ -6,6 +6,9 @@ use serde_aux::field_attributes::deserialize_number_from_string;
 pub struct Settings {
     pub application: ApplicationSettings,
     pub amqp: AmqpSettings,
+    pub health: HealthSettings,
+    pub latency: LatencySettings,
+    pub event_log: EventLogSettings,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -14,12 +17,41 @@ pub struct ApplicationSettings {
     pub base_currency: CurrencySettings,
 }
 
+impl ApplicationSettings {
+    /// Name of the traded pair, e.g. `BTC-USD`.
+    pub fn instrument(&self) -> String {
+        format!("{}-{}", self.base_currency.name, self.quote_currency.name)
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct CurrencySettings {
     pub name: String,
     pub scaling_factor: u8,
 }
 
+#[derive(serde::Deserialize, Debug)]
+pub struct LatencySettings {
+    /// How often stage latency percentiles are logged.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub report_interval_secs: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct EventLogSettings {
+    /// Events kept for retransmission, the oldest are evicted first.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: usize,
+}
+
+/// Address of the HTTP listener answering health probes.
+#[derive(serde::Deserialize, Debug)]
+pub struct HealthSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct AmqpSettings {
     pub host: String,
@@ -27,8 +59,19 @@ pub struct AmqpSettings {
     pub port: u16,
     pub username: String,
     pub password: SecretBox<String>,
+    /// Topic exchange events are published to.
+    pub exchange: String,
+    /// Queue of the persistor.
     pub channel: String,
+    /// Routing keys `channel` is bound with.
+    pub bindings: Vec<String>,
     pub consumer_tag: String,
+    /// Where messages rejected from `channel` are routed.
+    pub dead_letter_exchange: String,
+    pub dead_letter_queue: String,
+    /// Events kept until the broker confirms them, see `event_queue`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_unconfirmed: usize,
 }
 
 impl AmqpSettings {
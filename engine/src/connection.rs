@@ -0,0 +1,230 @@
+//! Connections to the engine's TCP listener. Every frame is a big-endian
+//! `u32` length followed by a `WireMessage`.
+//!
+//! Commands are forwarded to the matching engine. A `RetransmitRequest` is
+//! answered on the same connection with the logged events of the range,
+//! followed by a `RetransmitComplete`.
+//!
+//! A `Subscribe` turns the connection into an event stream: `Subscribed`
+//! with the current top of the book, the logged events from the requested
+//! sequence, then live events until the consumer disconnects. Anything it
+//! sends from then on is ignored. A subscription from past the last logged
+//! event starts with that sequence, the live events before it are not sent.
+//!
+//! Only connections that sent a command count as gateway connections in
+//! [`Health`], retransmit and subscribe clients don't.
+use crate::{
+    event_log::SharedEventLog,
+    health::Health,
+    matching_engine::InboundCommand,
+    messages::trading::{
+        RetransmitComplete, RetransmitRequest, Subscribed, WireMessage, wire_message::Payload,
+    },
+    metrics::Metrics,
+};
+use prost::Message;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, mpsc::Sender},
+    time::Instant,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::broadcast::error::RecvError,
+};
+
+#[tracing::instrument(name = "connection", skip_all, fields(%peer))]
+pub async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    command_tx: Sender<InboundCommand>,
+    event_log: SharedEventLog,
+    health: Arc<Health>,
+    metrics: Arc<Metrics>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    tracing::info!("new client connected");
+    let mut order_entry = false;
+
+    loop {
+        match reader.read_u32().await {
+            Ok(len) => {
+                let mut buf = vec![0; len as usize];
+                if let Err(e) = reader.read_exact(&mut buf).await {
+                    tracing::error!(error = %e, "failed to read message payload");
+                } else {
+                    match WireMessage::decode(buf.as_slice()) {
+                        Ok(WireMessage {
+                            payload: Some(Payload::RetransmitRequest(request)),
+                            ..
+                        }) => {
+                            if let Err(e) = retransmit(&mut writer, &event_log, request).await {
+                                tracing::error!(error = %e, "failed to retransmit events");
+                                break;
+                            }
+                        }
+                        Ok(WireMessage {
+                            payload: Some(Payload::Subscribe(request)),
+                            ..
+                        }) => {
+                            if let Err(e) = stream_events(
+                                &mut reader,
+                                &mut writer,
+                                &event_log,
+                                request.from_sequence,
+                            )
+                            .await
+                            {
+                                tracing::error!(error = %e, "subscription ended");
+                            }
+                            break;
+                        }
+                        Ok(message) => {
+                            if !order_entry {
+                                order_entry = true;
+                                health.gateway_connected();
+                            }
+                            let command = InboundCommand {
+                                message,
+                                ingress: Instant::now(),
+                            };
+                            if command_tx.send(command).is_err() {
+                                tracing::error!("matching engine is gone, dropping command");
+                            } else {
+                                metrics.queue_depth.with_label_values(&["commands"]).inc();
+                            }
+                        }
+                        Err(e) => {
+                            tracing::error!(error = %e, "failed to decode WireMessage");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::info!(reason = %e, "client disconnected, closing connection");
+                break;
+            }
+        }
+    }
+    if order_entry {
+        health.gateway_disconnected();
+    }
+}
+
+async fn retransmit<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    event_log: &SharedEventLog,
+    request: RetransmitRequest,
+) -> std::io::Result<()> {
+    let RetransmitRequest {
+        from_sequence,
+        to_sequence,
+    } = request;
+    // Copied out so the lock is not held while writing
+    let retransmission = event_log.lock().unwrap().range(from_sequence, to_sequence);
+    tracing::info!(
+        from_sequence,
+        to_sequence,
+        first_available = retransmission.first_available,
+        events = retransmission.events.len(),
+        "retransmitting events"
+    );
+
+    for event in &retransmission.events {
+        write_frame(writer, event).await?;
+    }
+    let complete = WireMessage {
+        payload: Some(Payload::RetransmitComplete(RetransmitComplete {
+            from_sequence,
+            to_sequence,
+            first_available: retransmission.first_available,
+        })),
+        // Tells a consumer asking about an earlier run that its events are gone
+        epoch: retransmission.epoch,
+        ..Default::default()
+    };
+    write_frame(writer, &complete).await?;
+    writer.flush().await
+}
+
+#[tracing::instrument(name = "subscription", skip(reader, writer, event_log))]
+async fn stream_events<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    event_log: &SharedEventLog,
+    from_sequence: u64,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut subscription = event_log.lock().unwrap().subscribe(from_sequence);
+    let subscribed = WireMessage {
+        payload: Some(Payload::Subscribed(Subscribed {
+            from_sequence: subscription.from,
+            first_available: subscription.backlog.first_available,
+            book_top: subscription.book_top.take(),
+        })),
+        // Tells a consumer resuming an earlier run to start over
+        epoch: subscription.backlog.epoch,
+        ..Default::default()
+    };
+    write_frame(writer, &subscribed).await?;
+    tracing::info!(
+        from_sequence = subscription.from,
+        backlog = subscription.backlog.events.len(),
+        "consumer subscribed"
+    );
+
+    let mut next = subscription.from;
+    let mut discard = [0; 64];
+    loop {
+        for event in subscription.backlog.events.drain(..) {
+            write_frame(writer, &event).await?;
+            next = event.sequence + 1;
+        }
+        writer.flush().await?;
+
+        loop {
+            tokio::select! {
+                received = subscription.live.recv() => match received {
+                    // Subscribed ahead of the log, the events before `from` are not wanted
+                    Ok(event) if event.sequence < next => {}
+                    Ok(event) => {
+                        write_frame(writer, &event).await?;
+                        next = event.sequence + 1;
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, next, "consumer is lagging, catching up from the log");
+                        break;
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                read = reader.read(&mut discard) => {
+                    if read? == 0 {
+                        tracing::info!("consumer unsubscribed");
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        subscription = event_log.lock().unwrap().subscribe(next);
+        if subscription.backlog.first_available > next {
+            return Err(std::io::Error::other(
+                "consumer fell behind the event log, events were evicted",
+            ));
+        }
+    }
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &WireMessage,
+) -> std::io::Result<()> {
+    let buf = message.encode_to_vec();
+    writer.write_u32(buf.len() as u32).await?;
+    writer.write_all(&buf).await
+}
@@ -0,0 +1,128 @@
+//! The last published events, kept in memory so consumers that notice a gap
+//! in the sequence can have the missing range retransmitted, or replay them
+//! before following the live stream.
+use crate::messages::trading::{BookTop, WireMessage, wire_message::Payload};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Live events buffered per subscriber, one falling further behind is
+/// caught up from the log again.
+const LIVE_BUFFER: usize = 4096;
+
+pub type SharedEventLog = Arc<Mutex<EventLog>>;
+
+/// Ring buffer of sequenced events, the oldest are evicted once `capacity`
+/// is reached.
+pub struct EventLog {
+    events: VecDeque<WireMessage>,
+    capacity: usize,
+    /// Of the last `BookTop` appended, kept once it is evicted.
+    book_top: Option<BookTop>,
+    live: broadcast::Sender<WireMessage>,
+}
+
+/// Events of a requested range that are still in the log.
+#[derive(Debug)]
+pub struct Retransmission {
+    pub events: Vec<WireMessage>,
+    /// Oldest sequence still in the log, 0 when it is empty.
+    pub first_available: u64,
+    /// Engine run the sequences count in, 0 when the log is empty.
+    pub epoch: u64,
+}
+
+/// Logged events from the requested sequence, followed without gap or
+/// overlap by the live ones.
+pub struct Subscription {
+    /// First sequence of the subscription, resolved when 0 was asked for.
+    pub from: u64,
+    pub backlog: Retransmission,
+    pub live: broadcast::Receiver<WireMessage>,
+    /// Top of the book as of the last logged event.
+    pub book_top: Option<BookTop>,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        EventLog {
+            events: VecDeque::with_capacity(capacity.min(1 << 16)),
+            capacity,
+            book_top: None,
+            live: broadcast::channel(LIVE_BUFFER).0,
+        }
+    }
+
+    pub fn shared(capacity: usize) -> SharedEventLog {
+        Arc::new(Mutex::new(Self::new(capacity)))
+    }
+
+    /// Appends the next event, whose sequence must follow the last one, and
+    /// sends it to the subscribers.
+    pub fn append(&mut self, event: WireMessage) {
+        debug_assert!(
+            self.events.is_empty() || event.sequence == self.last_sequence() + 1,
+            "event {} does not follow {}",
+            event.sequence,
+            self.last_sequence()
+        );
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        if let Some(Payload::BookTop(top)) = &event.payload {
+            self.book_top = Some(top.clone());
+        }
+        // Only fails when nobody is subscribed
+        let _ = self.live.send(event.clone());
+        self.events.push_back(event);
+    }
+
+    pub fn first_sequence(&self) -> u64 {
+        self.events.front().map_or(0, |event| event.sequence)
+    }
+
+    pub fn last_sequence(&self) -> u64 {
+        self.events.back().map_or(0, |event| event.sequence)
+    }
+
+    /// Engine run of the logged events, 0 when the log is empty.
+    pub fn epoch(&self) -> u64 {
+        self.events.back().map_or(0, |event| event.epoch)
+    }
+
+    /// Events `from` to `to` (inclusive) that are still in the log.
+    pub fn range(&self, from: u64, to: u64) -> Retransmission {
+        let first_available = self.first_sequence();
+        // Also empty when the whole range was evicted
+        let events = if self.events.is_empty() || to < from || to < first_available {
+            Vec::new()
+        } else {
+            let start = from.saturating_sub(first_available);
+            let end = (to.min(self.last_sequence()) + 1).saturating_sub(first_available);
+            self.events
+                .range(start as usize..end.max(start) as usize)
+                .cloned()
+                .collect()
+        };
+
+        Retransmission {
+            events,
+            first_available,
+            epoch: self.epoch(),
+        }
+    }
+
+    /// Subscribes from `from`, or from the next event when it is 0. Taken
+    /// under the same lock as [`EventLog::append`], so the backlog ends
+    /// right where the live stream starts.
+    pub fn subscribe(&self, from: u64) -> Subscription {
+        let last = self.last_sequence();
+        let from = if from == 0 { last + 1 } else { from };
+        Subscription {
+            from,
+            backlog: self.range(from, last),
+            live: self.live.subscribe(),
+            book_top: self.book_top.clone(),
+        }
+    }
+}
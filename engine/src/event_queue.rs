@@ -1,53 +1,284 @@
+//! Publishes engine events to AMQP with publisher confirms.
+//!
+//! Events stay in an outbox until the broker confirms them. A nack, a failed
+//! publish or a lost connection republishes everything unconfirmed on the
+//! next connection, in order, and consumers drop the duplicates by sequence.
+//!
+//! The outbox holds at most `max_unconfirmed` events. While connected the
+//! publisher waits for confirms once it is full. While disconnected it keeps
+//! draining the event channel and drops the oldest events, counted in
+//! `engine_amqp_events_dropped_total`: consumers see the gap in the sequence
+//! and fetch them from the event log.
 use crate::{
     configuration::AmqpSettings,
-    messages::trading::{WireMessage, wire_message::Payload},
+    health::Health,
+    latency::Stage,
+    matching_engine::OutboundEvent,
+    metrics::{Metrics, payload_type},
+    topology,
+};
+use event_bus::backoff::Backoff;
+use lapin::{
+    self, BasicProperties, ConnectionProperties,
+    options::{BasicPublishOptions, ConfirmSelectOptions},
+    publisher_confirm::{Confirmation, PublisherConfirm},
 };
-use lapin::{self, BasicProperties, ConnectionProperties, options::BasicPublishOptions};
 use prost::Message;
-use std::sync::mpsc::Receiver;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::mpsc::{UnboundedReceiver, error::TryRecvError};
+
+/// Delivery mode of messages written to disk by the broker.
+const PERSISTENT: u8 = 2;
+
+// The wrapped error is only read through `Debug` when logging
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum PublishError {
+    Amqp(lapin::Error),
+    /// The broker refused the event with this sequence.
+    Nacked(u64),
+}
+
+impl From<lapin::Error> for PublishError {
+    fn from(e: lapin::Error) -> Self {
+        PublishError::Amqp(e)
+    }
+}
+
+/// Events published but not confirmed yet, oldest first.
+pub struct Outbox<C = PublisherConfirm> {
+    events: VecDeque<OutboundEvent>,
+    /// Confirms of the first `confirms.len()` events, published on the
+    /// current channel. Within a session every event has one.
+    confirms: VecDeque<C>,
+    capacity: usize,
+}
+
+impl<C> Outbox<C> {
+    /// Holds at least one event.
+    pub fn new(capacity: usize) -> Self {
+        Outbox {
+            events: VecDeque::new(),
+            confirms: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn unconfirmed(&self) -> &VecDeque<OutboundEvent> {
+        &self.events
+    }
+
+    fn is_full(&self) -> bool {
+        self.events.len() >= self.capacity
+    }
+
+    /// Queues an event while disconnected, dropping the oldest when full.
+    pub fn push_dropping_oldest(&mut self, event: OutboundEvent, metrics: &Metrics) {
+        if self.is_full()
+            && let Some(dropped) = self.events.pop_front()
+        {
+            tracing::error!(
+                sequence = dropped.sequence,
+                correlation_id = %dropped.correlation_id,
+                "outbox is full, dropping the oldest unconfirmed event"
+            );
+            metrics.amqp_events_dropped.inc();
+        }
+        self.events.push_back(event);
+    }
+}
+
+impl<C: Future<Output = lapin::Result<Confirmation>>> Outbox<C> {
+    /// Waits for the oldest confirm and removes its event once acked.
+    async fn confirm_oldest(&mut self, metrics: &Metrics) -> Result<(), PublishError> {
+        let Some(confirm) = self.confirms.pop_front() else {
+            return Ok(());
+        };
+        let confirmation = confirm.await?;
+        let event = self
+            .events
+            .front()
+            .expect("every confirm has an event in the outbox");
+        if let Confirmation::Nack(_) = confirmation {
+            return Err(PublishError::Nacked(event.sequence));
+        }
+
+        let event = self.events.pop_front().unwrap();
+        tracing::info!(
+            correlation_id = %event.correlation_id,
+            event = payload_type(&event.payload),
+            sequence = event.sequence,
+            "published event"
+        );
+        let stamps = event.stamps;
+        metrics
+            .stage_latency
+            .record(Stage::Publish, stamps.match_end.elapsed());
+        metrics
+            .stage_latency
+            .record(Stage::Total, stamps.ingress.elapsed());
+        Ok(())
+    }
+}
+
+/// Sends events to the broker, the AMQP channel outside of tests.
+pub trait Publish {
+    type Confirm: Future<Output = lapin::Result<Confirmation>> + Send;
 
-pub async fn queue_loop(event_rx: Receiver<Payload>, config: AmqpSettings) -> lapin::Result<()> {
+    fn publish(
+        &self,
+        event: &OutboundEvent,
+    ) -> impl Future<Output = lapin::Result<Self::Confirm>> + Send;
+}
+
+struct ChannelPublisher<'a> {
+    channel: &'a lapin::Channel,
+    config: &'a AmqpSettings,
+    instrument: &'a str,
+}
+
+impl Publish for ChannelPublisher<'_> {
+    type Confirm = PublisherConfirm;
+
+    async fn publish(&self, event: &OutboundEvent) -> lapin::Result<PublisherConfirm> {
+        let buf = event.to_wire_message().encode_to_vec();
+        self.channel
+            .basic_publish(
+                &self.config.exchange,
+                &topology::routing_key(&event.payload, self.instrument),
+                BasicPublishOptions::default(),
+                &buf,
+                BasicProperties::default().with_delivery_mode(PERSISTENT),
+            )
+            .await
+    }
+}
+
+/// Opens a confirming channel and declares the topology.
+async fn connect(config: &AmqpSettings) -> lapin::Result<(lapin::Connection, lapin::Channel)> {
     let conn = lapin::Connection::connect(
         config.connection_string().as_str(),
         ConnectionProperties::default(),
     )
     .await?;
-
-    log::info!("Conncted to amqp: {}", config.host);
+    tracing::info!(host = %config.host, "connected to amqp");
 
     let channel = conn.create_channel().await?;
-    log::info!("Created amqp channel");
-
-    log::info!(
-        "channel: {}, consumer-tag: {}",
-        config.channel,
-        config.consumer_tag
-    );
-
-    let mut wire_message = WireMessage::default();
-    let mut buf = Vec::with_capacity(wire_message.encoded_len());
-
-    for event in event_rx {
-        log::info!("received event from engine: {:?}", event);
-        buf.clear();
-        wire_message.payload = Some(event);
-        if let Ok(_) = wire_message.encode(&mut buf) {
-            if let Ok(_) = channel
-                .basic_publish(
-                    "",
-                    &config.channel,
-                    BasicPublishOptions::default(),
-                    &buf,
-                    BasicProperties::default(),
-                )
-                .await
-            {
-                log::info!("published {:?} to queue", wire_message);
-            } else {
-                log::error!("failed to publish {:?} to queue", wire_message);
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+    topology::declare(&channel, config).await?;
+    Ok((conn, channel))
+}
+
+/// Publishes until the event channel closes, which returns `Ok`, or the
+/// connection fails. Whatever the previous session left unconfirmed is
+/// published again first.
+pub async fn publish_session<P: Publish>(
+    event_rx: &mut UnboundedReceiver<OutboundEvent>,
+    publisher: &P,
+    metrics: &Metrics,
+    outbox: &mut Outbox<P::Confirm>,
+) -> Result<(), PublishError> {
+    // Confirms of the previous channel never resolve
+    outbox.confirms.clear();
+    for event in &outbox.events {
+        outbox.confirms.push_back(publisher.publish(event).await?);
+    }
+
+    let queue_depth = metrics.queue_depth.with_label_values(&["publish"]);
+    loop {
+        // Events wait in the channel until the outbox has room
+        if outbox.is_full() {
+            outbox.confirm_oldest(metrics).await?;
+            continue;
+        }
+        let event = if outbox.confirms.is_empty() {
+            match event_rx.recv().await {
+                Some(event) => event,
+                None => return Ok(()),
+            }
+        } else {
+            match event_rx.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => {
+                    outbox.confirm_oldest(metrics).await?;
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    while !outbox.confirms.is_empty() {
+                        outbox.confirm_oldest(metrics).await?;
+                    }
+                    return Ok(());
+                }
+            }
+        };
+        queue_depth.dec();
+
+        // In the outbox before publishing, so a failed session republishes it
+        outbox.events.push_back(event);
+        let event = outbox.events.back().unwrap();
+        let confirm = publisher.publish(event).await?;
+        outbox.confirms.push_back(confirm);
+    }
+}
+
+/// Publishes events until the event channel closes, reconnecting with
+/// backoff whenever the broker cannot be reached.
+pub async fn queue_loop(
+    mut event_rx: UnboundedReceiver<OutboundEvent>,
+    config: AmqpSettings,
+    instrument: String,
+    health: Arc<Health>,
+    metrics: Arc<Metrics>,
+) {
+    let mut outbox = Outbox::new(config.max_unconfirmed);
+    let queue_depth = metrics.queue_depth.with_label_values(&["publish"]);
+    let mut backoff = Backoff::new();
+
+    loop {
+        match connect(&config).await {
+            Ok((_conn, channel)) => {
+                health.set_amqp_connected(true);
+                backoff.reset();
+                tracing::info!(
+                    exchange = %config.exchange,
+                    unconfirmed = outbox.events.len(),
+                    "ready to publish events"
+                );
+
+                let publisher = ChannelPublisher {
+                    channel: &channel,
+                    config: &config,
+                    instrument: &instrument,
+                };
+                match publish_session(&mut event_rx, &publisher, &metrics, &mut outbox).await {
+                    Ok(()) => break,
+                    Err(e) => {
+                        tracing::error!(
+                            error = ?e,
+                            unconfirmed = outbox.events.len(),
+                            "amqp publisher failed, reconnecting"
+                        );
+                        metrics.amqp_publish_failures.inc();
+                    }
+                }
+                health.set_amqp_connected(false);
             }
+            Err(e) => {
+                let backoff_delay = backoff.delay();
+                tracing::error!(error = %e, ?backoff_delay, "failed to connect to amqp, retrying");
+                backoff.wait().await;
+            }
+        }
+
+        while let Ok(event) = event_rx.try_recv() {
+            queue_depth.dec();
+            outbox.push_dropping_oldest(event, &metrics);
         }
     }
 
-    Ok(())
+    tracing::info!("event channel closed, publisher stopped");
+    health.set_amqp_connected(false);
 }
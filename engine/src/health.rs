@@ -0,0 +1,53 @@
+//! Liveness and readiness of the engine, served by
+//! [`observability::probes::serve_probes`].
+use observability::probes::Readiness;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[derive(Default)]
+pub struct Health {
+    matching_engine_running: AtomicBool,
+    amqp_connected: AtomicBool,
+    gateway_connections: AtomicUsize,
+}
+
+impl Health {
+    pub fn set_matching_engine_running(&self, running: bool) {
+        self.matching_engine_running
+            .store(running, Ordering::Relaxed);
+    }
+
+    /// Set by the event queue, false until it connects and again whenever a
+    /// publish fails.
+    pub fn set_amqp_connected(&self, connected: bool) {
+        self.amqp_connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn gateway_connected(&self) {
+        self.gateway_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn gateway_disconnected(&self) {
+        self.gateway_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.matching_engine_running.load(Ordering::Relaxed)
+            && self.amqp_connected.load(Ordering::Relaxed)
+    }
+
+    fn readiness_json(&self) -> String {
+        format!(
+            r#"{{"ready":{},"matching_engine_running":{},"amqp_connected":{},"gateway_connections":{}}}"#,
+            self.is_ready(),
+            self.matching_engine_running.load(Ordering::Relaxed),
+            self.amqp_connected.load(Ordering::Relaxed),
+            self.gateway_connections.load(Ordering::Relaxed),
+        )
+    }
+}
+
+impl Readiness for Health {
+    async fn readiness(&self) -> (bool, String) {
+        (self.is_ready(), self.readiness_json())
+    }
+}
@@ -0,0 +1,134 @@
+//! Time spent by commands in each stage of the engine:
+//!
+//! ```text
+//! ingress ──queue──▶ match start ──match──▶ match end ──publish──▶ confirmed
+//! ```
+//!
+//! `ingress` is when `handle_connection` decoded the command. Every stage
+//! keeps an HDR histogram in nanoseconds, [`report_loop`] logs their
+//! percentiles and starts over.
+use hdrhistogram::Histogram;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// When a command went through the engine, carried by each of its events.
+#[derive(Clone, Copy, Debug)]
+pub struct Stamps {
+    pub ingress: Instant,
+    pub match_start: Instant,
+    pub match_end: Instant,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Ingress to match start, the hop through the command channel.
+    Queue,
+    /// Match start to match end.
+    Match,
+    /// Match end to the broker confirming the event, through the
+    /// distributor.
+    Publish,
+    /// Ingress to the broker confirming the event.
+    Total,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Queue, Stage::Match, Stage::Publish, Stage::Total];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Queue => "queue",
+            Stage::Match => "match",
+            Stage::Publish => "publish",
+            Stage::Total => "total",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentiles {
+    pub count: u64,
+    pub p50_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub p999_ns: u64,
+    pub max_ns: u64,
+}
+
+pub struct StageLatency {
+    histograms: Mutex<Vec<Histogram<u64>>>,
+}
+
+impl Default for StageLatency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StageLatency {
+    pub fn new() -> Self {
+        // 1ns to 60s at 3 significant figures
+        let histogram = Histogram::new_with_bounds(1, 60_000_000_000, 3).unwrap();
+        StageLatency {
+            histograms: Mutex::new(vec![histogram; Stage::ALL.len()]),
+        }
+    }
+
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        let mut histograms = self.histograms.lock().unwrap();
+        histograms[stage as usize].saturating_record(elapsed.as_nanos() as u64);
+    }
+
+    /// Percentiles of every stage since the last reset.
+    pub fn percentiles(&self) -> Vec<(Stage, Percentiles)> {
+        let histograms = self.histograms.lock().unwrap();
+        Stage::ALL
+            .iter()
+            .map(|stage| {
+                let histogram = &histograms[*stage as usize];
+                let percentiles = Percentiles {
+                    count: histogram.len(),
+                    p50_ns: histogram.value_at_quantile(0.5),
+                    p90_ns: histogram.value_at_quantile(0.9),
+                    p99_ns: histogram.value_at_quantile(0.99),
+                    p999_ns: histogram.value_at_quantile(0.999),
+                    max_ns: histogram.max(),
+                };
+                (*stage, percentiles)
+            })
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        for histogram in self.histograms.lock().unwrap().iter_mut() {
+            histogram.reset();
+        }
+    }
+}
+
+/// Logs the percentiles of every stage each `interval`, then resets them so
+/// each report covers one interval.
+pub async fn report_loop(latency: &StageLatency, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        for (stage, p) in latency.percentiles() {
+            if p.count == 0 {
+                continue;
+            }
+            tracing::info!(
+                stage = stage.as_str(),
+                count = p.count,
+                p50_ns = p.p50_ns,
+                p90_ns = p.p90_ns,
+                p99_ns = p.p99_ns,
+                p999_ns = p.p999_ns,
+                max_ns = p.max_ns,
+                "stage latency"
+            );
+        }
+        latency.reset();
+    }
+}
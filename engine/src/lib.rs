@@ -1,5 +1,11 @@
 pub mod book;
 pub mod configuration;
+pub mod connection;
+pub mod event_log;
 pub mod event_queue;
+pub mod health;
+pub mod latency;
 pub mod matching_engine;
 pub mod messages;
+pub mod metrics;
+pub mod topology;
@@ -4,108 +4,153 @@ use engine::{
     self,
     book::OrderBook,
     configuration::get_configuration,
+    connection::handle_connection,
+    event_log::{EventLog, SharedEventLog},
     event_queue::queue_loop,
-    matching_engine::matching_engine_loop,
+    health::Health,
+    latency::report_loop,
+    matching_engine::{InboundCommand, OutboundEvent, matching_engine_loop},
     messages::trading::{WireMessage, wire_message::Payload},
+    metrics::{Metrics, payload_type},
 };
 use futures_lite::stream::StreamExt;
+use observability::probes::serve_probes;
+use observability::telemetry::{get_subscriber, init_subscriber};
 
-use log;
 use prost::Message;
 use std::{
     collections::{BTreeMap, VecDeque},
-    sync::mpsc::{Receiver, Sender},
+    net::SocketAddr,
+    sync::{
+        Arc,
+        mpsc::{Receiver, Sender},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
     io::{AsyncReadExt, BufReader},
     net::{TcpListener, TcpStream},
 };
 
-use tracing;
-
-fn event_distributor_loop(event_rx: Receiver<Payload>, consumers: Vec<Sender<Payload>>) {
+fn event_distributor_loop(
+    event_rx: Receiver<OutboundEvent>,
+    consumers: Vec<tokio::sync::mpsc::UnboundedSender<OutboundEvent>>,
+    event_log: SharedEventLog,
+    metrics: Arc<Metrics>,
+) {
+    let publish_queue_depth = metrics.queue_depth.with_label_values(&["publish"]);
     for event in event_rx {
-        log::info!(
-            "broadcasting event: {:?} to {} consumers",
-            event,
-            consumers.len()
+        // Logged before it is published, so a consumer seeing it can always
+        // ask for what came before
+        event_log.lock().unwrap().append(event.to_wire_message());
+        metrics
+            .events
+            .with_label_values(&[payload_type(&event.payload)])
+            .inc();
+        tracing::debug!(
+            correlation_id = %event.correlation_id,
+            event = payload_type(&event.payload),
+            consumers = consumers.len(),
+            "broadcasting event"
         );
         for consumer_tx in &consumers {
             consumer_tx.send(event.clone()).unwrap();
-        }
-    }
-}
-
-async fn handle_connection(stream: TcpStream, command_tx: Sender<Payload>) {
-    let mut reader = BufReader::new(stream);
-    log::info!("new client connected");
-
-    loop {
-        match reader.read_u32().await {
-            Ok(len) => {
-                let mut buf = vec![0; len as usize];
-                if let Err(e) = reader.read_exact(&mut buf).await {
-                    log::error!("failed to read message payload {:?}", e);
-                } else {
-                    match WireMessage::decode(buf.as_slice()) {
-                        Ok(msg) => {
-                            let payload = msg.payload.unwrap();
-                            if command_tx.send(payload).is_err() {
-                                log::error!("failed to send to engine");
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("failed to decode WireMessage, {:?}", e);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                log::error!("client disconnected {:?}, closing connection", e);
-                break;
-            }
+            publish_queue_depth.inc();
         }
     }
 }
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+    let subscriber = get_subscriber("engine".into(), "info".into(), std::io::stdout);
+    init_subscriber(subscriber);
 
     let config = get_configuration().expect("Failed to read config file");
 
-    let (command_tx, command_rx) = std::sync::mpsc::channel::<Payload>();
-    let (event_tx, event_rx) = std::sync::mpsc::channel::<Payload>();
-    let (event_queue_tx, event_queue_rx) = std::sync::mpsc::channel::<Payload>();
-
+    let (command_tx, command_rx) = std::sync::mpsc::channel::<InboundCommand>();
+    let (event_tx, event_rx) = std::sync::mpsc::channel::<OutboundEvent>();
+    let (event_queue_tx, event_queue_rx) = tokio::sync::mpsc::unbounded_channel::<OutboundEvent>();
+
+    let health = Arc::new(Health::default());
+    let metrics = Arc::new(Metrics::new());
+    let event_log = EventLog::shared(config.event_log.capacity);
+
+    let epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the Unix epoch")
+        .as_micros() as u64;
+    tracing::info!(epoch, "starting a new engine run");
+    let instrument = config.application.instrument();
+    let engine_health = health.clone();
+    let engine_metrics = metrics.clone();
     let engine_handle = std::thread::spawn(move || {
-        log::info!("starting matching engine");
-        matching_engine_loop(command_rx, event_tx, config.application);
+        tracing::info!("starting matching engine");
+        engine_health.set_matching_engine_running(true);
+        matching_engine_loop(
+            command_rx,
+            event_tx,
+            config.application,
+            epoch,
+            engine_metrics,
+        );
+        engine_health.set_matching_engine_running(false);
     });
 
+    let distributor_log = event_log.clone();
+    let distributor_metrics = metrics.clone();
     let distributor_handle = std::thread::spawn(move || {
-        event_distributor_loop(event_rx, vec![event_queue_tx]);
+        event_distributor_loop(
+            event_rx,
+            vec![event_queue_tx],
+            distributor_log,
+            distributor_metrics,
+        );
     });
 
+    let queue_health = health.clone();
+    let queue_metrics = metrics.clone();
     let event_queue_handle = tokio::spawn(async move {
-        queue_loop(event_queue_rx, config.amqp).await;
+        queue_loop(
+            event_queue_rx,
+            config.amqp,
+            instrument,
+            queue_health,
+            queue_metrics,
+        )
+        .await;
     });
 
+    let report_metrics = metrics.clone();
+    let report_interval = Duration::from_secs(config.latency.report_interval_secs);
+    tokio::spawn(async move {
+        report_loop(&report_metrics.stage_latency, report_interval).await;
+    });
+
+    let health_listener =
+        TcpListener::bind(format!("{}:{}", config.health.host, config.health.port)).await?;
+    tracing::info!(address = %health_listener.local_addr()?, "serving health probes");
+    tokio::spawn(serve_probes(
+        health_listener,
+        health.clone(),
+        metrics.clone(),
+    ));
+
     let listener = TcpListener::bind("127.0.0.1:4000").await?;
-    log::info!("started async io listener on main thread");
+    tracing::info!("started async io listener on main thread");
     loop {
         // Accept a new connection.
-        let (socket, _addr) = listener.accept().await?;
+        let (socket, addr) = listener.accept().await?;
 
         // Clone the sender for the new connection handler.
         let command_tx_clone = command_tx.clone();
+        let event_log = event_log.clone();
+        let health = health.clone();
+        let metrics = metrics.clone();
 
         // Spawn a new Tokio task to handle this specific connection.
         // This allows us to handle thousands of connections concurrently.
         tokio::spawn(async move {
-            handle_connection(socket, command_tx_clone).await;
+            handle_connection(socket, addr, command_tx_clone, event_log, health, metrics).await;
         });
     }
 
@@ -1,66 +1,347 @@
+use std::sync::Arc;
 use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use crate::{
     book::OrderBook,
     configuration::ApplicationSettings,
-    messages::trading::{OrderAccepted, OrderCancelled, TradeOccurred, wire_message::Payload},
+    latency::{Stage, Stamps},
+    messages::trading::{
+        BatchCommand, BatchExecuted, BatchItemResult, BookTop, CancelOrder, OrderAccepted,
+        OrderCancelled, OrderRejected, PlaceLimitOrder, Side, TradeOccurred, WireMessage,
+        batch_item::Command, wire_message::Payload,
+    },
+    metrics::{Metrics, payload_type},
 };
 
+/// A command as read off a gateway connection.
+#[derive(Debug)]
+pub struct InboundCommand {
+    pub message: WireMessage,
+    /// When `handle_connection` decoded it.
+    pub ingress: Instant,
+}
+
+/// An event of the matching engine, stamped once its command is done.
+#[derive(Debug, Clone)]
+pub struct OutboundEvent {
+    pub payload: Payload,
+    /// Position in the event stream, gapless from 1.
+    pub sequence: u64,
+    /// Run of the engine `sequence` counts in, see [`matching_engine_loop`].
+    pub epoch: u64,
+    /// Unix time in microseconds at which matching of the command ended.
+    pub engine_timestamp_us: u64,
+    /// Correlation id of the command the event results from.
+    pub correlation_id: String,
+    pub stamps: Stamps,
+}
+
+impl OutboundEvent {
+    /// The event as published to consumers.
+    pub fn to_wire_message(&self) -> WireMessage {
+        WireMessage {
+            payload: Some(self.payload.clone()),
+            engine_timestamp_us: self.engine_timestamp_us,
+            correlation_id: self.correlation_id.clone(),
+            sequence: self.sequence,
+            epoch: self.epoch,
+            ..Default::default()
+        }
+    }
+}
+
+/// The gateway validates orders already, but the engine is the last line of
+/// defence: anything that would panic the book or land on the wrong
+/// instrument is dropped here.
+fn validate_order(order: &PlaceLimitOrder, config: &ApplicationSettings) -> Result<(), String> {
+    if order.side() == Side::Unspecified {
+        return Err(format!("invalid side {}", order.side));
+    }
+    if order.quantity == 0 || order.price == 0 {
+        return Err("price and quantity must be positive".into());
+    }
+    if order.base_currency != config.base_currency.name
+        || order.quote_currency != config.quote_currency.name
+    {
+        return Err(format!(
+            "instrument {}-{} is not traded here",
+            order.base_currency, order.quote_currency
+        ));
+    }
+    Ok(())
+}
+
+fn reject_order(events: &mut Vec<Payload>, order: &PlaceLimitOrder, reason: String) {
+    tracing::warn!(user_id = order.user_id, %reason, "rejecting order");
+    events.push(Payload::OrderRejected(OrderRejected {
+        user_id: order.user_id,
+        client_order_id: order.client_order_id.clone(),
+        reason,
+    }));
+}
+
+/// Places an order and queues its events, returns the engine order id.
+fn place_limit_order(
+    book: &mut OrderBook,
+    events: &mut Vec<Payload>,
+    config: &ApplicationSettings,
+    order: &PlaceLimitOrder,
+) -> Result<u64, String> {
+    if let Err(err) = validate_order(order, config) {
+        reject_order(events, order, err.clone());
+        return Err(err);
+    }
+
+    let placed = if order.client_order_id.is_empty() {
+        Ok(book.add_limit_order(order.user_id, order.side(), order.price, order.quantity))
+    } else {
+        book.add_limit_order_with_client_id(
+            order.user_id,
+            order.client_order_id.clone(),
+            order.side(),
+            order.price,
+            order.quantity,
+        )
+    };
+    let (order_id, trades) = match placed {
+        Ok(placed) => placed,
+        Err(err) => {
+            reject_order(events, order, err.to_string());
+            return Err(err.to_string());
+        }
+    };
+
+    events.push(Payload::OrderAccepted(OrderAccepted {
+        order_id,
+        user_id: order.user_id,
+        side: order.side,
+        price: order.price,
+        quantity: order.quantity,
+        base_currency: config.base_currency.name.clone(),
+        quote_currency: config.quote_currency.name.clone(),
+        client_order_id: order.client_order_id.clone(),
+    }));
+
+    for trade in trades {
+        events.push(Payload::TradeOccurred(TradeOccurred {
+            taker_order_id: trade.taker_order_id,
+            maker_order_id: trade.maker_order_id,
+            price: trade.price,
+            quantity: trade.quantity,
+            taker_side: order.side,
+            base_currency: config.base_currency.name.clone(),
+            quote_currency: config.quote_currency.name.clone(),
+        }));
+    }
+
+    Ok(order_id)
+}
+
+/// Cancels an order and queues the cancellation, returns the engine order
+/// id and the client order id of the cancelled order.
+fn cancel_order(
+    book: &mut OrderBook,
+    events: &mut Vec<Payload>,
+    request: &CancelOrder,
+) -> Result<(u64, String), String> {
+    let cancelled = if request.order_id == 0 {
+        book.cancel_order_by_client_id(request.user_id, &request.client_order_id)
+            .map(|order_id| (order_id, request.client_order_id.clone()))
+    } else {
+        // The request only names the order, its client id is the one it was placed with
+        book.cancel_order(request.user_id, request.order_id)
+            .map(|client_order_id| (request.order_id, client_order_id.unwrap_or_default()))
+    };
+
+    match cancelled {
+        Ok((order_id, client_order_id)) => {
+            events.push(Payload::OrderCancelled(OrderCancelled {
+                order_id,
+                user_id: request.user_id,
+                client_order_id: client_order_id.clone(),
+            }));
+            Ok((order_id, client_order_id))
+        }
+        Err(err) => {
+            tracing::warn!(
+                user_id = request.user_id,
+                order_id = request.order_id,
+                client_order_id = %request.client_order_id,
+                reason = err,
+                "failed to cancel order"
+            );
+            // send an error event upstream?
+            Err(err.to_string())
+        }
+    }
+}
+
+/// Applies every item of a batch in order and queues the per-item results.
+fn execute_batch(
+    book: &mut OrderBook,
+    events: &mut Vec<Payload>,
+    config: &ApplicationSettings,
+    batch: BatchCommand,
+) {
+    let results = batch
+        .items
+        .iter()
+        .map(|item| {
+            let (result, client_order_id) = match &item.command {
+                Some(Command::PlaceLimitOrder(order)) => (
+                    place_limit_order(book, events, config, order)
+                        .map(|order_id| (order_id, order.client_order_id.clone())),
+                    &order.client_order_id,
+                ),
+                Some(Command::CancelOrder(request)) => (
+                    cancel_order(book, events, request),
+                    &request.client_order_id,
+                ),
+                None => (Err("empty batch item".to_string()), &String::new()),
+            };
+
+            match result {
+                Ok((order_id, client_order_id)) => BatchItemResult {
+                    order_id,
+                    client_order_id,
+                    success: true,
+                    reason: String::new(),
+                },
+                Err(reason) => BatchItemResult {
+                    order_id: 0,
+                    client_order_id: client_order_id.clone(),
+                    success: false,
+                    reason,
+                },
+            }
+        })
+        .collect();
+
+    events.push(Payload::BatchExecuted(BatchExecuted {
+        batch_id: batch.batch_id,
+        user_id: batch.user_id,
+        results,
+    }));
+}
+
+fn book_top(book: &OrderBook, config: &ApplicationSettings) -> BookTop {
+    let (best_bid, bid_quantity) = book.best(Side::Buy).unwrap_or_default();
+    let (best_ask, ask_quantity) = book.best(Side::Sell).unwrap_or_default();
+    BookTop {
+        base_currency: config.base_currency.name.clone(),
+        quote_currency: config.quote_currency.name.clone(),
+        best_bid,
+        bid_quantity,
+        best_ask,
+        ask_quantity,
+    }
+}
+
+fn record_book(book: &OrderBook, metrics: &Metrics) {
+    metrics
+        .book_price_levels
+        .with_label_values(&["buy"])
+        .set(book.bids.len() as i64);
+    metrics
+        .book_price_levels
+        .with_label_values(&["sell"])
+        .set(book.asks.len() as i64);
+    metrics.resting_orders.set(book.orders.len() as i64);
+}
+
+/// Matches commands until the command channel closes.
+///
+/// Sequences restart from 1 with every run, told apart by `epoch`, the
+/// startup time in Unix microseconds. Order ids start right after the
+/// epoch, so they keep growing across restarts unless a run accepts more
+/// orders than microseconds pass before the next one starts.
+///
+/// A command that changed the top of the book ends with a `BookTop` event.
 pub fn matching_engine_loop(
-    command_rx: Receiver<Payload>,
-    event_tx: Sender<Payload>,
+    command_rx: Receiver<InboundCommand>,
+    event_tx: Sender<OutboundEvent>,
     config: ApplicationSettings,
+    epoch: u64,
+    metrics: Arc<Metrics>,
 ) {
     let mut book = OrderBook::new();
-    log::info!("matching engine started, ready to receive commands");
-    for command in command_rx {
-        log::info!("Matching engine received event {:?}", command);
+    book.next_order_id = epoch + 1;
+    let mut top = book_top(&book, &config);
+    let mut events = Vec::new();
+    let mut last_sequence = 0;
+    let command_queue_depth = metrics.queue_depth.with_label_values(&["commands"]);
+    tracing::info!("matching engine started, ready to receive commands");
+    for InboundCommand { message, ingress } in command_rx {
+        let match_start = Instant::now();
+        command_queue_depth.dec();
+        let Some(command) = message.payload else {
+            continue;
+        };
+        let span = tracing::info_span!(
+            "command",
+            correlation_id = %message.correlation_id,
+            command = payload_type(&command)
+        );
+        let _guard = span.enter();
+        metrics
+            .commands
+            .with_label_values(&[payload_type(&command)])
+            .inc();
 
         match command {
             Payload::PlaceLimitOrder(order) => {
-                let (order_id, trades) =
-                    book.add_limit_order(order.side(), order.price, order.quantity);
-
-                event_tx
-                    .send(Payload::OrderAccepted(OrderAccepted {
-                        order_id,
-                        user_id: order.user_id,
-                        side: order.side,
-                        price: order.price,
-                        quantity: order.quantity,
-                        base_currency: config.base_currency.name.clone(),
-                        quote_currency: config.quote_currency.name.clone(),
-                    }))
-                    .unwrap(); // TODO: handle the error
-
-                for trade in trades {
-                    event_tx
-                        .send(Payload::TradeOccurred(TradeOccurred {
-                            taker_order_id: trade.taker_order_id,
-                            maker_order_id: trade.maker_order_id,
-                            price: trade.price,
-                            quantity: trade.quantity,
-                        }))
-                        .unwrap(); // TODO: handle the error
-                }
+                let _ = place_limit_order(&mut book, &mut events, &config, &order);
+            }
+            Payload::CancelOrder(request) => {
+                let _ = cancel_order(&mut book, &mut events, &request);
+            }
+            Payload::BatchCommand(batch) => {
+                execute_batch(&mut book, &mut events, &config, batch);
             }
-            Payload::CancelOrder(request) => match book.cancel_order(request.order_id) {
-                Ok(_) => {
-                    event_tx
-                        .send(Payload::OrderCancelled(OrderCancelled {
-                            order_id: request.order_id,
-                        }))
-                        .unwrap();
-                }
-                Err(err) => {
-                    log::error!("failed to cancel order {}: {}", request.order_id, err);
-                    // send an error event upstream?
-                }
-            },
 
             _ => {
                 // This will only handle input messages
             }
         };
+        let new_top = book_top(&book, &config);
+        if new_top != top {
+            top = new_top;
+            events.push(Payload::BookTop(top.clone()));
+        }
+
+        let match_end = Instant::now();
+        let stamps = Stamps {
+            ingress,
+            match_start,
+            match_end,
+        };
+        let engine_timestamp_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        for payload in events.drain(..) {
+            last_sequence += 1;
+            let event = OutboundEvent {
+                payload,
+                sequence: last_sequence,
+                epoch,
+                engine_timestamp_us,
+                correlation_id: message.correlation_id.clone(),
+                stamps,
+            };
+            if event_tx.send(event).is_err() {
+                tracing::error!("event channel is closed, dropping event");
+            }
+        }
+
+        metrics
+            .stage_latency
+            .record(Stage::Queue, match_start - ingress);
+        metrics
+            .stage_latency
+            .record(Stage::Match, match_end - match_start);
+        record_book(&book, &metrics);
+        metrics.observe_end_to_end(message.gateway_received_at_us);
     }
 }
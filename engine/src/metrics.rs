@@ -0,0 +1,166 @@
+//! Prometheus metrics of the engine, scraped from `GET /metrics` on the
+//! health listener.
+use crate::latency::StageLatency;
+use crate::messages::trading::wire_message::Payload;
+use observability::probes::RenderMetrics;
+use prometheus::{
+    Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Metrics {
+    registry: Registry,
+    /// Commands taken off the command channel, by payload type.
+    pub commands: IntCounterVec,
+    /// Events emitted by the matching engine, by payload type. Trades and
+    /// rejects are the `trade_occurred` and `order_rejected` series.
+    pub events: IntCounterVec,
+    /// Price levels per side. Cancels are lazy, so a level only holding
+    /// cancelled orders is counted until a taker sweeps it.
+    pub book_price_levels: IntGaugeVec,
+    pub resting_orders: IntGauge,
+    /// Messages waiting in the in-process channels, by channel.
+    pub queue_depth: IntGaugeVec,
+    pub amqp_publish_failures: IntCounter,
+    /// Unconfirmed events dropped from the full outbox while AMQP was down.
+    pub amqp_events_dropped: IntCounter,
+    /// From the gateway receiving the request to the engine having emitted
+    /// every event of the command.
+    pub end_to_end_latency: Histogram,
+    /// Internal stage latencies, logged rather than exported.
+    pub stage_latency: StageLatency,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let commands = IntCounterVec::new(
+            Opts::new("engine_commands_total", "Commands received, by type"),
+            &["type"],
+        )
+        .unwrap();
+        let events = IntCounterVec::new(
+            Opts::new("engine_events_total", "Events emitted, by type"),
+            &["type"],
+        )
+        .unwrap();
+        let book_price_levels = IntGaugeVec::new(
+            Opts::new(
+                "engine_book_price_levels",
+                "Price levels in the book, by side",
+            ),
+            &["side"],
+        )
+        .unwrap();
+        let resting_orders =
+            IntGauge::new("engine_resting_orders", "Open orders resting in the book").unwrap();
+        let queue_depth = IntGaugeVec::new(
+            Opts::new("engine_queue_depth", "Messages waiting in a channel"),
+            &["queue"],
+        )
+        .unwrap();
+        let amqp_publish_failures = IntCounter::new(
+            "engine_amqp_publish_failures_total",
+            "Failed AMQP publisher sessions, each followed by a reconnect",
+        )
+        .unwrap();
+        let amqp_events_dropped = IntCounter::new(
+            "engine_amqp_events_dropped_total",
+            "Unconfirmed events dropped from the full outbox",
+        )
+        .unwrap();
+        let end_to_end_latency = Histogram::with_opts(
+            HistogramOpts::new(
+                "engine_end_to_end_latency_seconds",
+                "Time from the gateway receiving a command to the engine acknowledging it",
+            )
+            .buckets(prometheus::exponential_buckets(0.00005, 2.0, 16).unwrap()),
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(commands.clone())).unwrap();
+        registry.register(Box::new(events.clone())).unwrap();
+        registry
+            .register(Box::new(book_price_levels.clone()))
+            .unwrap();
+        registry.register(Box::new(resting_orders.clone())).unwrap();
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+        registry
+            .register(Box::new(amqp_publish_failures.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(amqp_events_dropped.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(end_to_end_latency.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            commands,
+            events,
+            book_price_levels,
+            resting_orders,
+            queue_depth,
+            amqp_publish_failures,
+            amqp_events_dropped,
+            end_to_end_latency,
+            stage_latency: StageLatency::new(),
+        }
+    }
+
+    /// Records the end-to-end latency of a command the gateway stamped at
+    /// `gateway_received_at_us`. Unstamped commands (0) are skipped.
+    pub fn observe_end_to_end(&self, gateway_received_at_us: u64) {
+        if gateway_received_at_us == 0 {
+            return;
+        }
+        let now_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        // The gateway clock may be ahead of ours
+        let elapsed_us = now_us.saturating_sub(gateway_received_at_us);
+        self.end_to_end_latency
+            .observe(elapsed_us as f64 / 1_000_000.0);
+    }
+
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+impl RenderMetrics for Metrics {
+    fn render(&self) -> String {
+        Metrics::render(self)
+    }
+}
+
+/// Label of a payload in the `commands` and `events` series.
+pub fn payload_type(payload: &Payload) -> &'static str {
+    match payload {
+        Payload::PlaceLimitOrder(_) => "place_limit_order",
+        Payload::CancelOrder(_) => "cancel_order",
+        Payload::BatchCommand(_) => "batch_command",
+        Payload::RetransmitRequest(_) => "retransmit_request",
+        Payload::Subscribe(_) => "subscribe",
+        Payload::OrderAccepted(_) => "order_accepted",
+        Payload::TradeOccurred(_) => "trade_occurred",
+        Payload::OrderCancelled(_) => "order_cancelled",
+        Payload::OrderRejected(_) => "order_rejected",
+        Payload::BatchExecuted(_) => "batch_executed",
+        Payload::BookTop(_) => "book_top",
+        Payload::RetransmitComplete(_) => "retransmit_complete",
+        Payload::Subscribed(_) => "subscribed",
+        Payload::Candle(_) => "candle",
+    }
+}
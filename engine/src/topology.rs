@@ -0,0 +1,41 @@
+//! The event stream as the engine sees it: the shared
+//! [`event_bus::topology`] it declares at startup, and the routing keys it
+//! publishes events with.
+use crate::configuration::AmqpSettings;
+use crate::messages::trading::wire_message::Payload;
+use event_bus::topology::{self, StreamTopology};
+use lapin::Channel;
+
+/// Declares the event stream the engine publishes to.
+pub async fn declare(channel: &Channel, config: &AmqpSettings) -> lapin::Result<()> {
+    let stream = StreamTopology {
+        exchange: &config.exchange,
+        channel: &config.channel,
+        bindings: &config.bindings,
+        dead_letter_exchange: &config.dead_letter_exchange,
+        dead_letter_queue: &config.dead_letter_queue,
+    };
+    topology::declare(channel, &stream).await
+}
+
+/// Routing key of an event of `instrument`, e.g. `BTC-USD`.
+pub fn routing_key(payload: &Payload, instrument: &str) -> String {
+    let kind = match payload {
+        Payload::TradeOccurred(_) => "trade",
+        Payload::OrderAccepted(_) => "order.accepted",
+        Payload::OrderCancelled(_) => "order.cancelled",
+        Payload::OrderRejected(_) => "order.rejected",
+        Payload::BatchExecuted(_) => "batch.executed",
+        Payload::BookTop(_) => "book.top",
+        // Commands, replies and market data never reach the publisher
+        Payload::PlaceLimitOrder(_)
+        | Payload::CancelOrder(_)
+        | Payload::BatchCommand(_)
+        | Payload::RetransmitRequest(_)
+        | Payload::Subscribe(_)
+        | Payload::RetransmitComplete(_)
+        | Payload::Subscribed(_)
+        | Payload::Candle(_) => "unroutable",
+    };
+    format!("{}.{}", kind, instrument)
+}
@@ -0,0 +1,218 @@
+use engine::connection::handle_connection;
+use engine::event_log::{EventLog, SharedEventLog};
+use engine::health::Health;
+use engine::messages::trading::{
+    BookTop, OrderCancelled, RetransmitRequest, Subscribe, WireMessage, wire_message::Payload,
+};
+use engine::metrics::Metrics;
+use prost::Message;
+use std::sync::{Arc, mpsc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const EPOCH: u64 = 1_700_000_000_000_000;
+
+fn event(sequence: u64) -> WireMessage {
+    WireMessage {
+        payload: Some(Payload::OrderCancelled(OrderCancelled {
+            order_id: sequence,
+            ..Default::default()
+        })),
+        sequence,
+        epoch: EPOCH,
+        ..Default::default()
+    }
+}
+
+fn log_with(capacity: usize, last_sequence: u64) -> EventLog {
+    let mut log = EventLog::new(capacity);
+    for sequence in 1..=last_sequence {
+        log.append(event(sequence));
+    }
+    log
+}
+
+fn sequences(events: &[WireMessage]) -> Vec<u64> {
+    events.iter().map(|event| event.sequence).collect()
+}
+
+#[test]
+fn range_is_inclusive_and_clamped_to_the_log() {
+    let log = log_with(10, 5);
+
+    assert_eq!(sequences(&log.range(2, 4).events), [2, 3, 4]);
+    assert_eq!(sequences(&log.range(4, 9).events), [4, 5]);
+    assert!(log.range(6, 9).events.is_empty());
+    assert!(log.range(3, 2).events.is_empty());
+}
+
+#[test]
+fn oldest_events_are_evicted_at_capacity() {
+    let log = log_with(3, 5);
+
+    let retransmission = log.range(1, 5);
+    assert_eq!(retransmission.first_available, 3);
+    assert_eq!(sequences(&retransmission.events), [3, 4, 5]);
+    assert_eq!(log.last_sequence(), 5);
+}
+
+#[test]
+fn a_fully_evicted_range_is_empty() {
+    let log = log_with(3, 5);
+
+    let retransmission = log.range(1, 2);
+    assert_eq!(retransmission.first_available, 3);
+    assert!(retransmission.events.is_empty());
+    assert!(log.range(1, 1).events.is_empty());
+}
+
+async fn spawn_engine_listener(event_log: SharedEventLog) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let (stream, peer) = listener.accept().await.unwrap();
+        // Retransmissions never reach the matching engine
+        let (command_tx, _command_rx) = mpsc::channel();
+        handle_connection(
+            stream,
+            peer,
+            command_tx,
+            event_log,
+            Arc::new(Health::default()),
+            Arc::new(Metrics::new()),
+        )
+        .await;
+    });
+    address
+}
+
+async fn read_frame(stream: &mut TcpStream) -> WireMessage {
+    let len = stream.read_u32().await.unwrap();
+    let mut buf = vec![0; len as usize];
+    stream.read_exact(&mut buf).await.unwrap();
+    WireMessage::decode(buf.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn retransmits_a_range_over_the_engine_connection() {
+    let event_log = Arc::new(std::sync::Mutex::new(log_with(4, 6)));
+    let address = spawn_engine_listener(event_log).await;
+    let mut stream = TcpStream::connect(address).await.unwrap();
+
+    let request = WireMessage {
+        payload: Some(Payload::RetransmitRequest(RetransmitRequest {
+            from_sequence: 2,
+            to_sequence: 4,
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    stream.write_u32(request.len() as u32).await.unwrap();
+    stream.write_all(&request).await.unwrap();
+
+    let mut received = Vec::new();
+    let complete = loop {
+        match read_frame(&mut stream).await {
+            WireMessage {
+                payload: Some(Payload::RetransmitComplete(complete)),
+                ..
+            } => break complete,
+            event => received.push(event),
+        }
+    };
+    // 1 and 2 were evicted
+    assert_eq!(sequences(&received), [3, 4]);
+    assert_eq!(complete.from_sequence, 2);
+    assert_eq!(complete.to_sequence, 4);
+    assert_eq!(complete.first_available, 3);
+}
+
+async fn send(stream: &mut TcpStream, payload: Payload) {
+    let request = WireMessage {
+        payload: Some(payload),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    stream.write_u32(request.len() as u32).await.unwrap();
+    stream.write_all(&request).await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribers_replay_the_log_then_follow_live_events() {
+    let event_log = Arc::new(std::sync::Mutex::new(log_with(10, 3)));
+    let address = spawn_engine_listener(event_log.clone()).await;
+    let mut stream = TcpStream::connect(address).await.unwrap();
+
+    send(
+        &mut stream,
+        Payload::Subscribe(Subscribe { from_sequence: 2 }),
+    )
+    .await;
+
+    let frame = read_frame(&mut stream).await;
+    let Some(Payload::Subscribed(subscribed)) = frame.payload else {
+        panic!("expected Subscribed first");
+    };
+    assert_eq!(frame.epoch, EPOCH);
+    assert_eq!(subscribed.from_sequence, 2);
+    assert_eq!(subscribed.first_available, 1);
+    assert_eq!(read_frame(&mut stream).await.sequence, 2);
+    assert_eq!(read_frame(&mut stream).await.sequence, 3);
+
+    event_log.lock().unwrap().append(event(4));
+    assert_eq!(read_frame(&mut stream).await.sequence, 4);
+}
+
+#[test]
+fn subscriptions_start_from_the_last_book_top() {
+    let mut log = log_with(2, 3);
+    assert_eq!(log.subscribe(0).book_top, None);
+    let top = BookTop {
+        best_bid: 100,
+        bid_quantity: 10,
+        ..Default::default()
+    };
+    log.append(WireMessage {
+        payload: Some(Payload::BookTop(top.clone())),
+        sequence: 4,
+        ..Default::default()
+    });
+    log.append(event(5));
+    // Evicted, but still the top of the book
+    log.append(event(6));
+
+    assert_eq!(log.subscribe(0).book_top, Some(top));
+}
+
+#[test]
+fn subscribing_from_zero_starts_with_the_next_event() {
+    let log = log_with(10, 3);
+
+    let subscription = log.subscribe(0);
+
+    assert_eq!(subscription.from, 4);
+    assert!(subscription.backlog.events.is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_ahead_of_the_log_only_get_events_from_their_sequence() {
+    let event_log = Arc::new(std::sync::Mutex::new(log_with(10, 3)));
+    let address = spawn_engine_listener(event_log.clone()).await;
+    let mut stream = TcpStream::connect(address).await.unwrap();
+
+    send(
+        &mut stream,
+        Payload::Subscribe(Subscribe { from_sequence: 6 }),
+    )
+    .await;
+
+    let Some(Payload::Subscribed(subscribed)) = read_frame(&mut stream).await.payload else {
+        panic!("expected Subscribed first");
+    };
+    assert_eq!(subscribed.from_sequence, 6);
+    for sequence in 4..=7 {
+        event_log.lock().unwrap().append(event(sequence));
+    }
+    assert_eq!(read_frame(&mut stream).await.sequence, 6);
+    assert_eq!(read_frame(&mut stream).await.sequence, 7);
+}
@@ -0,0 +1,161 @@
+use engine::event_queue::{Outbox, Publish, PublishError, publish_session};
+use engine::latency::Stamps;
+use engine::matching_engine::OutboundEvent;
+use engine::messages::trading::{OrderCancelled, wire_message::Payload};
+use engine::metrics::Metrics;
+use lapin::ErrorKind;
+use lapin::publisher_confirm::Confirmation;
+use std::future::{Ready, ready};
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// Acks every event but the one with sequence `nack`, and fails to publish
+/// the one with sequence `fail`, remembering what was published.
+#[derive(Default)]
+struct Broker {
+    nack: Option<u64>,
+    fail: Option<u64>,
+    published: Mutex<Vec<u64>>,
+}
+
+impl Publish for Broker {
+    type Confirm = Ready<lapin::Result<Confirmation>>;
+
+    async fn publish(&self, event: &OutboundEvent) -> lapin::Result<Self::Confirm> {
+        if self.fail == Some(event.sequence) {
+            return Err(ErrorKind::ChannelsLimitReached.into());
+        }
+        self.published.lock().unwrap().push(event.sequence);
+        let confirmation = if self.nack == Some(event.sequence) {
+            Confirmation::Nack(None)
+        } else {
+            Confirmation::Ack(None)
+        };
+        Ok(ready(Ok(confirmation)))
+    }
+}
+
+fn event(sequence: u64) -> OutboundEvent {
+    let now = Instant::now();
+    OutboundEvent {
+        payload: Payload::OrderCancelled(OrderCancelled::default()),
+        sequence,
+        epoch: 1,
+        engine_timestamp_us: 0,
+        correlation_id: String::new(),
+        stamps: Stamps {
+            ingress: now,
+            match_start: now,
+            match_end: now,
+        },
+    }
+}
+
+/// A closed event channel holding `sequences`.
+fn events(sequences: &[u64]) -> mpsc::UnboundedReceiver<OutboundEvent> {
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    for &sequence in sequences {
+        event_tx.send(event(sequence)).unwrap();
+    }
+    event_rx
+}
+
+fn unconfirmed<C>(outbox: &Outbox<C>) -> Vec<u64> {
+    outbox.unconfirmed().iter().map(|e| e.sequence).collect()
+}
+
+#[tokio::test]
+async fn confirmed_events_leave_the_outbox() {
+    let broker = Broker::default();
+    let mut outbox = Outbox::new(2);
+
+    let result = publish_session(
+        &mut events(&[1, 2, 3]),
+        &broker,
+        &Metrics::new(),
+        &mut outbox,
+    )
+    .await;
+
+    assert!(result.is_ok());
+    assert_eq!(*broker.published.lock().unwrap(), [1, 2, 3]);
+    assert!(unconfirmed(&outbox).is_empty());
+}
+
+#[tokio::test]
+async fn nacked_events_are_published_again_on_the_next_session() {
+    let metrics = Metrics::new();
+    let mut event_rx = events(&[1, 2, 3]);
+    let mut outbox = Outbox::new(10);
+    let nacking = Broker {
+        nack: Some(2),
+        ..Default::default()
+    };
+
+    let result = publish_session(&mut event_rx, &nacking, &metrics, &mut outbox).await;
+
+    assert!(matches!(result, Err(PublishError::Nacked(2))));
+    assert_eq!(unconfirmed(&outbox), [2, 3]);
+
+    let broker = Broker::default();
+    let result = publish_session(&mut event_rx, &broker, &metrics, &mut outbox).await;
+
+    assert!(result.is_ok());
+    assert_eq!(*broker.published.lock().unwrap(), [2, 3]);
+    assert!(unconfirmed(&outbox).is_empty());
+}
+
+#[tokio::test]
+async fn an_event_failing_to_publish_is_published_on_the_next_session() {
+    let metrics = Metrics::new();
+    let mut event_rx = events(&[1, 2, 3]);
+    let mut outbox = Outbox::new(10);
+    let failing = Broker {
+        fail: Some(2),
+        ..Default::default()
+    };
+
+    let result = publish_session(&mut event_rx, &failing, &metrics, &mut outbox).await;
+
+    assert!(matches!(result, Err(PublishError::Amqp(_))));
+    assert_eq!(unconfirmed(&outbox), [1, 2]);
+
+    let broker = Broker::default();
+    let result = publish_session(&mut event_rx, &broker, &metrics, &mut outbox).await;
+
+    assert!(result.is_ok());
+    assert_eq!(*broker.published.lock().unwrap(), [1, 2, 3]);
+    assert!(unconfirmed(&outbox).is_empty());
+}
+
+#[tokio::test]
+async fn a_full_outbox_keeps_events_in_the_channel() {
+    let metrics = Metrics::new();
+    let mut event_rx = events(&[1, 2, 3]);
+    let mut outbox = Outbox::new(1);
+    let nacking = Broker {
+        nack: Some(1),
+        ..Default::default()
+    };
+
+    let result = publish_session(&mut event_rx, &nacking, &metrics, &mut outbox).await;
+
+    // 2 was left in the channel while 1 waited for its confirm
+    assert!(matches!(result, Err(PublishError::Nacked(1))));
+    assert_eq!(unconfirmed(&outbox), [1]);
+    assert_eq!(event_rx.len(), 2);
+}
+
+#[test]
+fn a_full_outbox_drops_its_oldest_events() {
+    let metrics = Metrics::new();
+    let mut outbox = Outbox::<Ready<lapin::Result<Confirmation>>>::new(2);
+
+    for sequence in 1..=3 {
+        outbox.push_dropping_oldest(event(sequence), &metrics);
+    }
+
+    assert_eq!(unconfirmed(&outbox), [2, 3]);
+    assert_eq!(metrics.amqp_events_dropped.get(), 1);
+}
@@ -0,0 +1,125 @@
+use engine::connection::handle_connection;
+use engine::event_log::EventLog;
+use engine::health::Health;
+use engine::matching_engine::InboundCommand;
+use engine::messages::trading::{
+    CancelOrder, RetransmitRequest, WireMessage, wire_message::Payload,
+};
+use engine::metrics::Metrics;
+use observability::probes::serve_probes;
+use prost::Message;
+use std::sync::{Arc, mpsc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn spawn_health() -> (String, Arc<Health>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let health = Arc::new(Health::default());
+    tokio::spawn(serve_probes(
+        listener,
+        health.clone(),
+        Arc::new(Metrics::new()),
+    ));
+    (address, health)
+}
+
+async fn get(address: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream
+        .write_all(format!("GET {} HTTP/1.1\r\nHost: engine\r\n\r\n", path).as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn ready_once_the_engine_runs_and_amqp_is_connected() {
+    let (address, health) = spawn_health().await;
+
+    assert!(
+        get(&address, "/health/live")
+            .await
+            .starts_with("HTTP/1.1 200")
+    );
+    let response = get(&address, "/health/ready").await;
+    assert!(response.starts_with("HTTP/1.1 503"));
+    assert!(response.contains(r#""amqp_connected":false"#));
+
+    health.set_matching_engine_running(true);
+    health.set_amqp_connected(true);
+    assert!(
+        get(&address, "/health/ready")
+            .await
+            .starts_with("HTTP/1.1 200")
+    );
+}
+
+async fn spawn_engine_listener(health: Arc<Health>) -> (String, mpsc::Receiver<InboundCommand>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (command_tx, command_rx) = mpsc::channel();
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = listener.accept().await.unwrap();
+            tokio::spawn(handle_connection(
+                stream,
+                peer,
+                command_tx.clone(),
+                EventLog::shared(16),
+                health.clone(),
+                Arc::new(Metrics::new()),
+            ));
+        }
+    });
+    (address, command_rx)
+}
+
+async fn write_frame(stream: &mut TcpStream, message: WireMessage) {
+    let buf = message.encode_to_vec();
+    stream.write_u32(buf.len() as u32).await.unwrap();
+    stream.write_all(&buf).await.unwrap();
+}
+
+async fn wait_for_gateway_connections(address: &str, count: usize) {
+    let expected = format!(r#""gateway_connections":{}"#, count);
+    for _ in 0..100 {
+        if get(address, "/health/ready").await.contains(&expected) {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("The engine never counted {} gateway connections", count);
+}
+
+#[tokio::test]
+async fn only_connections_sending_commands_count_as_gateways() {
+    let (probe_address, health) = spawn_health().await;
+    let (engine_address, _command_rx) = spawn_engine_listener(health).await;
+
+    let mut retransmit = TcpStream::connect(&engine_address).await.unwrap();
+    let request = WireMessage {
+        payload: Some(Payload::RetransmitRequest(RetransmitRequest {
+            from_sequence: 1,
+            to_sequence: 1,
+        })),
+        ..Default::default()
+    };
+    write_frame(&mut retransmit, request).await;
+    // Answered once the request was handled
+    retransmit.read_u32().await.unwrap();
+    wait_for_gateway_connections(&probe_address, 0).await;
+
+    let mut gateway = TcpStream::connect(&engine_address).await.unwrap();
+    let command = WireMessage {
+        payload: Some(Payload::CancelOrder(CancelOrder::default())),
+        ..Default::default()
+    };
+    write_frame(&mut gateway, command).await;
+    wait_for_gateway_connections(&probe_address, 1).await;
+
+    drop(gateway);
+    wait_for_gateway_connections(&probe_address, 0).await;
+}
@@ -0,0 +1,282 @@
+use engine::configuration::{ApplicationSettings, CurrencySettings};
+use engine::latency::Stage;
+use engine::matching_engine::{InboundCommand, OutboundEvent, matching_engine_loop};
+use engine::messages::trading::{
+    BatchCommand, BatchItem, BookTop, CancelOrder, PlaceLimitOrder, Side, WireMessage,
+    batch_item::Command, wire_message::Payload,
+};
+use engine::metrics::Metrics;
+use std::sync::{Arc, mpsc};
+use std::time::Instant;
+
+fn config() -> ApplicationSettings {
+    ApplicationSettings {
+        base_currency: CurrencySettings {
+            name: "BTC".into(),
+            scaling_factor: 8,
+        },
+        quote_currency: CurrencySettings {
+            name: "USD".into(),
+            scaling_factor: 2,
+        },
+    }
+}
+
+fn limit_order(client_order_id: &str, price: u64) -> Command {
+    Command::PlaceLimitOrder(PlaceLimitOrder {
+        user_id: 1,
+        side: Side::Buy.into(),
+        price,
+        quantity: 10,
+        base_currency: "BTC".into(),
+        quote_currency: "USD".into(),
+        client_order_id: client_order_id.into(),
+    })
+}
+
+/// Runs the engine over `commands` until they are exhausted and returns
+/// every event it emitted.
+fn run(commands: Vec<Payload>) -> Vec<Payload> {
+    run_with_metrics(commands, Arc::new(Metrics::new()))
+        .into_iter()
+        .map(|event| event.payload)
+        .collect()
+}
+
+fn run_with_metrics(commands: Vec<Payload>, metrics: Arc<Metrics>) -> Vec<OutboundEvent> {
+    run_in_epoch(commands, 0, metrics)
+}
+
+fn run_in_epoch(commands: Vec<Payload>, epoch: u64, metrics: Arc<Metrics>) -> Vec<OutboundEvent> {
+    let (command_tx, command_rx) = mpsc::channel();
+    let (event_tx, event_rx) = mpsc::channel();
+    for (i, command) in commands.into_iter().enumerate() {
+        command_tx
+            .send(InboundCommand {
+                message: WireMessage {
+                    payload: Some(command),
+                    correlation_id: format!("correlation-{}", i),
+                    ..Default::default()
+                },
+                ingress: Instant::now(),
+            })
+            .unwrap();
+    }
+    drop(command_tx);
+
+    matching_engine_loop(command_rx, event_tx, config(), epoch, metrics);
+    event_rx.into_iter().collect()
+}
+
+#[test]
+fn batch_items_are_applied_in_order() {
+    let items = vec![
+        limit_order("a", 100),
+        limit_order("a", 101),
+        Command::CancelOrder(CancelOrder {
+            user_id: 1,
+            order_id: 0,
+            client_order_id: "a".into(),
+        }),
+        limit_order("a", 102),
+    ];
+    let batch = BatchCommand {
+        batch_id: "batch-1".into(),
+        user_id: 1,
+        items: items
+            .into_iter()
+            .map(|command| BatchItem {
+                command: Some(command),
+            })
+            .collect(),
+    };
+
+    let events = run(vec![Payload::BatchCommand(batch)]);
+
+    let executed = events
+        .into_iter()
+        .find_map(|event| match event {
+            Payload::BatchExecuted(executed) => Some(executed),
+            _ => None,
+        })
+        .expect("no BatchExecuted event");
+    assert_eq!(executed.batch_id, "batch-1");
+    let outcomes: Vec<bool> = executed.results.iter().map(|r| r.success).collect();
+    // The duplicate client id only becomes free again once the first order is cancelled.
+    assert_eq!(outcomes, [true, false, true, true]);
+    assert_eq!(executed.results[2].order_id, executed.results[0].order_id);
+    assert_ne!(executed.results[3].order_id, executed.results[0].order_id);
+}
+
+#[test]
+fn metrics_count_commands_and_track_the_book() {
+    let metrics = Arc::new(Metrics::new());
+    let commands = [100, 101, 101]
+        .into_iter()
+        .map(|price| match limit_order("", price) {
+            Command::PlaceLimitOrder(order) => Payload::PlaceLimitOrder(order),
+            other => panic!("unexpected command {:?}", other),
+        })
+        .collect();
+
+    run_with_metrics(commands, metrics.clone());
+
+    assert_eq!(
+        metrics
+            .commands
+            .with_label_values(&["place_limit_order"])
+            .get(),
+        3
+    );
+    assert_eq!(metrics.resting_orders.get(), 3);
+    assert_eq!(
+        metrics.book_price_levels.with_label_values(&["buy"]).get(),
+        2
+    );
+    assert!(
+        metrics
+            .render()
+            .contains("engine_book_price_levels{side=\"buy\"} 2")
+    );
+}
+
+#[test]
+fn events_are_stamped_by_the_engine() {
+    let metrics = Arc::new(Metrics::new());
+    let Command::PlaceLimitOrder(order) = limit_order("", 100) else {
+        unreachable!()
+    };
+
+    let events = run_with_metrics(vec![Payload::PlaceLimitOrder(order)], metrics.clone());
+
+    // Accepted, and the new top of the book
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].correlation_id, "correlation-0");
+    assert!(events[0].engine_timestamp_us > 0);
+    assert!(events[0].stamps.ingress <= events[0].stamps.match_start);
+    assert!(events[0].stamps.match_start <= events[0].stamps.match_end);
+    let percentiles = metrics.stage_latency.percentiles();
+    let (_, matched) = percentiles
+        .iter()
+        .find(|(stage, _)| *stage == Stage::Match)
+        .unwrap();
+    assert_eq!(matched.count, 1);
+}
+
+#[test]
+fn events_are_sequenced_without_gaps() {
+    let commands = [100, 100, 101]
+        .into_iter()
+        .map(|price| match limit_order("", price) {
+            Command::PlaceLimitOrder(order) => Payload::PlaceLimitOrder(order),
+            other => panic!("unexpected command {:?}", other),
+        })
+        .collect();
+
+    let events = run_with_metrics(commands, Arc::new(Metrics::new()));
+
+    let sequences: Vec<u64> = events.iter().map(|event| event.sequence).collect();
+    assert_eq!(sequences, (1..=events.len() as u64).collect::<Vec<_>>());
+    assert_eq!(events[1].to_wire_message().sequence, 2);
+}
+
+#[test]
+fn a_cancel_by_order_id_carries_the_client_order_id() {
+    let Command::PlaceLimitOrder(order) = limit_order("mine", 100) else {
+        unreachable!()
+    };
+
+    let events = run(vec![
+        Payload::PlaceLimitOrder(order),
+        Payload::CancelOrder(CancelOrder {
+            user_id: 1,
+            order_id: 1,
+            client_order_id: String::new(),
+        }),
+    ]);
+
+    match &events[2] {
+        Payload::OrderCancelled(cancelled) => {
+            assert_eq!(cancelled.order_id, 1);
+            assert_eq!(cancelled.client_order_id, "mine");
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+}
+
+#[test]
+fn events_carry_the_epoch_and_order_ids_follow_it() {
+    let epoch = 1_700_000_000_000_000;
+    let Command::PlaceLimitOrder(order) = limit_order("a", 6425000) else {
+        unreachable!();
+    };
+
+    let events = run_in_epoch(
+        vec![Payload::PlaceLimitOrder(order)],
+        epoch,
+        Arc::new(Metrics::new()),
+    );
+
+    assert_eq!(events[0].sequence, 1);
+    assert_eq!(events[0].to_wire_message().epoch, epoch);
+    let Payload::OrderAccepted(accepted) = &events[0].payload else {
+        panic!("expected OrderAccepted, got {:?}", events[0].payload);
+    };
+    assert_eq!(accepted.order_id, epoch + 1);
+}
+
+fn top(best_bid: u64, bid_quantity: u64, best_ask: u64, ask_quantity: u64) -> Payload {
+    Payload::BookTop(BookTop {
+        base_currency: "BTC".into(),
+        quote_currency: "USD".into(),
+        best_bid,
+        bid_quantity,
+        best_ask,
+        ask_quantity,
+    })
+}
+
+#[test]
+fn commands_changing_the_top_of_the_book_end_with_it() {
+    let place = |side: Side, price| {
+        Payload::PlaceLimitOrder(PlaceLimitOrder {
+            user_id: 1,
+            side: side.into(),
+            price,
+            quantity: 10,
+            base_currency: "BTC".into(),
+            quote_currency: "USD".into(),
+            client_order_id: String::new(),
+        })
+    };
+
+    let events = run(vec![
+        place(Side::Buy, 100),
+        place(Side::Buy, 100),
+        // Behind the best bid
+        place(Side::Buy, 99),
+        place(Side::Sell, 105),
+        place(Side::Sell, 100),
+        Payload::CancelOrder(CancelOrder {
+            user_id: 1,
+            order_id: 2,
+            client_order_id: String::new(),
+        }),
+    ]);
+
+    let tops: Vec<Payload> = events
+        .into_iter()
+        .filter(|event| matches!(event, Payload::BookTop(_)))
+        .collect();
+    assert_eq!(
+        tops,
+        [
+            top(100, 10, 0, 0),
+            top(100, 20, 0, 0),
+            top(100, 20, 105, 10),
+            top(100, 10, 105, 10),
+            // The cancelled order's level is left empty
+            top(99, 10, 105, 10),
+        ]
+    );
+}
@@ -4,8 +4,8 @@ use engine::messages::trading::Side;
 fn setup_book() -> OrderBook {
     let mut book = OrderBook::new();
     for i in 0..1000 {
-        book.add_limit_order(Side::Buy, 9999 - i, 10);
-        book.add_limit_order(Side::Sell, 10001 + i, 10);
+        book.add_limit_order(1, Side::Buy, 9999 - i, 10);
+        book.add_limit_order(1, Side::Sell, 10001 + i, 10);
     }
     book
 }
@@ -13,30 +13,30 @@ fn setup_book() -> OrderBook {
 #[test]
 fn add_limit_order_no_match() {
     let mut book = setup_book();
-    let (_, trades) = book.add_limit_order(Side::Buy, 9000, 10);
+    let (_, trades) = book.add_limit_order(1, Side::Buy, 9000, 10);
     assert_eq!(trades.len(), 0);
 }
 
 #[test]
 fn add_limit_order_full_match_one() {
     let mut book = setup_book();
-    let (_, trades) = book.add_limit_order(Side::Buy, 10001, 10);
+    let (_, trades) = book.add_limit_order(1, Side::Buy, 10001, 10);
     assert_eq!(trades.len(), 1);
 }
 
 #[test]
 fn add_limit_order_walk_the_book() {
     let mut book = setup_book();
-    let (_, trades) = book.add_limit_order(Side::Buy, 10005, 50);
+    let (_, trades) = book.add_limit_order(1, Side::Buy, 10005, 50);
     assert_eq!(trades.len(), 5);
 }
 
 #[test]
 fn add_limit_order_two_fills_same_level() {
     let mut book = OrderBook::new();
-    book.add_limit_order(Side::Sell, 10000, 5);
-    book.add_limit_order(Side::Sell, 10000, 5);
-    let (_, trades) = book.add_limit_order(Side::Buy, 10000, 10);
+    book.add_limit_order(1, Side::Sell, 10000, 5);
+    book.add_limit_order(1, Side::Sell, 10000, 5);
+    let (_, trades) = book.add_limit_order(1, Side::Buy, 10000, 10);
     assert_eq!(trades.len(), 2);
     assert_eq!(book.bids.len(), 0);
     assert_eq!(book.asks.len(), 0);
@@ -45,7 +45,55 @@ fn add_limit_order_two_fills_same_level() {
 #[test]
 fn cancel_limit_order() {
     let mut book = OrderBook::new();
-    let (order_id, _) = book.add_limit_order(Side::Sell, 10000, 5);
-    book.cancel_order(order_id).unwrap();
+    let (order_id, _) = book.add_limit_order(1, Side::Sell, 10000, 5);
+    book.cancel_order(1, order_id).unwrap();
     assert_eq!(book.orders.len(), 0);
 }
+
+#[test]
+fn cancel_limit_order_of_another_user_is_rejected() {
+    let mut book = OrderBook::new();
+    let (order_id, _) = book.add_limit_order(1, Side::Sell, 10000, 5);
+    assert!(book.cancel_order(2, order_id).is_err());
+    assert_eq!(book.orders.len(), 1);
+}
+
+#[test]
+fn duplicate_client_order_id_is_rejected_while_live() {
+    let mut book = OrderBook::new();
+    book.add_limit_order_with_client_id(1, "a".into(), Side::Sell, 10000, 5)
+        .unwrap();
+    assert!(
+        book.add_limit_order_with_client_id(1, "a".into(), Side::Sell, 10001, 5)
+            .is_err()
+    );
+    // client order ids are scoped to the user
+    assert!(
+        book.add_limit_order_with_client_id(2, "a".into(), Side::Sell, 10001, 5)
+            .is_ok()
+    );
+}
+
+#[test]
+fn client_order_id_is_free_again_once_filled() {
+    let mut book = OrderBook::new();
+    book.add_limit_order_with_client_id(1, "a".into(), Side::Sell, 10000, 5)
+        .unwrap();
+    book.add_limit_order(2, Side::Buy, 10000, 5);
+    assert!(
+        book.add_limit_order_with_client_id(1, "a".into(), Side::Sell, 10000, 5)
+            .is_ok()
+    );
+}
+
+#[test]
+fn cancel_by_client_order_id() {
+    let mut book = OrderBook::new();
+    let (order_id, _) = book
+        .add_limit_order_with_client_id(1, "a".into(), Side::Sell, 10000, 5)
+        .unwrap();
+    assert!(book.cancel_order_by_client_id(2, "a").is_err());
+    assert_eq!(book.cancel_order_by_client_id(1, "a"), Ok(order_id));
+    assert_eq!(book.orders.len(), 0);
+    assert_eq!(book.client_order_ids.len(), 0);
+}
@@ -0,0 +1,17 @@
+use engine::messages::trading::{OrderCancelled, TradeOccurred, wire_message::Payload};
+use engine::topology::routing_key;
+
+#[test]
+fn routing_keys_carry_the_event_type_and_instrument() {
+    assert_eq!(
+        routing_key(&Payload::TradeOccurred(TradeOccurred::default()), "BTC-USD"),
+        "trade.BTC-USD"
+    );
+    assert_eq!(
+        routing_key(
+            &Payload::OrderCancelled(OrderCancelled::default()),
+            "BTC-USD"
+        ),
+        "order.cancelled.BTC-USD"
+    );
+}
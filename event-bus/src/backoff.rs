@@ -0,0 +1,43 @@
+//! Delays between attempts to reach a broker that is down, doubling from
+//! 100ms up to 30s, with jitter so clients don't reconnect in lockstep.
+use rand::Rng;
+use std::time::Duration;
+
+const INITIAL: Duration = Duration::from_millis(100);
+const MAX: Duration = Duration::from_secs(30);
+/// Added to every delay, at most.
+pub const MAX_JITTER: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff { delay: INITIAL }
+    }
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long the next [`Backoff::wait`] sleeps.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Sleeps for the current delay, then doubles it.
+    pub async fn wait(&mut self) {
+        tokio::time::sleep(self.delay).await;
+        let jitter = rand::rng().random_range(0..MAX_JITTER.as_millis() as u64);
+        self.delay = (self.delay * 2).min(MAX) + Duration::from_millis(jitter);
+    }
+
+    /// Back to the initial delay, once connected.
+    pub fn reset(&mut self) {
+        self.delay = INITIAL;
+    }
+}
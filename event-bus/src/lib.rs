@@ -0,0 +1,3 @@
+//! AMQP plumbing shared by the engine and the persistor.
+pub mod backoff;
+pub mod topology;
@@ -0,0 +1,99 @@
+//! AMQP topology of the event stream, declared by the engine and the
+//! persistor alike so neither depends on the other having started first.
+//! Declarations are idempotent as long as both agree on the arguments.
+//!
+//! ```text
+//! {exchange} ──{bindings}──▶ {channel} ──rejected──▶ {dead_letter_exchange} ──▶ {dead_letter_queue}
+//! ```
+//!
+//! Events are published to the topic exchange with routing keys such as
+//! `trade.BTC-USD` or `order.cancelled.BTC-USD`. Other consumers declare
+//! their own queue, bound to the keys they need.
+use lapin::{
+    Channel, ExchangeKind,
+    options::{ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions},
+    types::{AMQPValue, FieldTable},
+};
+
+/// Names of the exchanges and queues of the event stream.
+pub struct StreamTopology<'a> {
+    pub exchange: &'a str,
+    /// The persistor's queue.
+    pub channel: &'a str,
+    /// Routing keys `channel` is bound to `exchange` with.
+    pub bindings: &'a [String],
+    pub dead_letter_exchange: &'a str,
+    pub dead_letter_queue: &'a str,
+}
+
+pub async fn declare(channel: &Channel, topology: &StreamTopology<'_>) -> lapin::Result<()> {
+    channel
+        .exchange_declare(
+            topology.exchange,
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+    channel
+        .exchange_declare(
+            topology.dead_letter_exchange,
+            ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+    channel
+        .queue_declare(
+            topology.dead_letter_queue,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+    channel
+        .queue_bind(
+            topology.dead_letter_queue,
+            topology.dead_letter_exchange,
+            "",
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    let mut arguments = FieldTable::default();
+    arguments.insert(
+        "x-dead-letter-exchange".into(),
+        AMQPValue::LongString(topology.dead_letter_exchange.into()),
+    );
+    channel
+        .queue_declare(
+            topology.channel,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            arguments,
+        )
+        .await?;
+    for binding in topology.bindings {
+        channel
+            .queue_bind(
+                topology.channel,
+                topology.exchange,
+                binding,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use event_bus::backoff::{Backoff, MAX_JITTER};
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[tokio::test(start_paused = true)]
+async fn waits_for_the_delay_then_doubles_it() {
+    let mut backoff = Backoff::new();
+    assert_eq!(backoff.delay(), Duration::from_millis(100));
+
+    for _ in 0..5 {
+        let delay = backoff.delay();
+        let started = Instant::now();
+        backoff.wait().await;
+
+        assert_eq!(started.elapsed(), delay);
+        assert!(backoff.delay() >= delay * 2);
+        assert!(backoff.delay() < delay * 2 + MAX_JITTER);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_delay_is_capped_and_reset_once_connected() {
+    let mut backoff = Backoff::new();
+
+    for _ in 0..20 {
+        backoff.wait().await;
+    }
+    assert!(backoff.delay() >= Duration::from_secs(30));
+    assert!(backoff.delay() < Duration::from_secs(30) + MAX_JITTER);
+
+    backoff.reset();
+    assert_eq!(backoff.delay(), Duration::from_millis(100));
+}
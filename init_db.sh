@@ -1,4 +1,7 @@
 
+# The persistor applies pending migrations at startup, and
+# `message-persistor --migrate-only` applies them without sqlx-cli. This
+# script remains for creating the database by hand.
 set -x
 set -eo pipefail
 if ![ -x "$(command -v sqlx)"]; then
@@ -1,78 +1,736 @@
 use crate::{
-    configuration::AmqpSettings,
+    candles::{self, Candle, CandlePublisher},
+    configuration::{AmqpSettings, BatchSettings},
+    health::Health,
     messages::trading::{WireMessage, wire_message::Payload},
+    metrics::Metrics,
+    persistence::{self, EventMeta, NewOrder, NewTrade},
+    quarantine::NewQuarantined,
+    retransmit::RetransmitClient,
+    retry::{Retrier, Retry, is_redriven, retry_count},
+    sequence::{Position, SequenceCheck, SequenceTracker},
+    storage::{EventTx, Storage},
+    topology,
 };
+use event_bus::backoff::Backoff;
 use futures_lite::stream::StreamExt;
 use lapin::{
-    self, ConnectionProperties,
-    options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions},
+    self, BasicProperties, ConnectionProperties,
+    message::Delivery,
+    options::{
+        BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions,
+        ConfirmSelectOptions,
+    },
     types::FieldTable,
 };
 use prost::Message;
-use sqlx::SqlitePool;
+use sqlx::error::ErrorKind;
 
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+// The wrapped errors are only read through `Debug` when logging
+#[allow(dead_code)]
 #[derive(Debug)]
 enum HandleError {
-    Decode(prost::DecodeError),
     Database(sqlx::Error),
+    /// Price times quantity of a trade does not fit the notional columns.
+    NotionalOverflow,
     UnexpectedPayload,
     MissingPayload,
 }
 
-async fn handle_payload(pool: &SqlitePool, bytes: &[u8]) -> Result<(), HandleError> {
-    let wire_message = WireMessage::decode(bytes).map_err(HandleError::Decode)?;
+impl HandleError {
+    /// Whether trying again later may succeed. Constraint violations will
+    /// fail the same way every time.
+    fn is_transient(&self) -> bool {
+        match self {
+            HandleError::Database(sqlx::Error::Database(e)) => !matches!(
+                e.kind(),
+                ErrorKind::UniqueViolation
+                    | ErrorKind::ForeignKeyViolation
+                    | ErrorKind::NotNullViolation
+                    | ErrorKind::CheckViolation
+            ),
+            HandleError::Database(_) => true,
+            HandleError::NotionalOverflow
+            | HandleError::UnexpectedPayload
+            | HandleError::MissingPayload => false,
+        }
+    }
+}
 
-    match wire_message.payload {
-        Some(Payload::OrderAccepted(order)) => {
-            let new_order = NewOrder {
-                order_id: order.order_id as i32,
-                base_currency: order.base_currency,
-                quote_currency: order.quote_currency,
-                side: order.side,
-                quantity: order.quantity as i32,
-                price: order.price as i32,
-            };
+/// Persists an event in `tx`, returns false when it was applied already.
+/// The caller commits, then publishes the candles it added to `candles`.
+///
+/// Re-driven events skip the checkpoint, which is past them, and count on
+/// the inserts being keyed on their epoch and sequence.
+async fn handle_payload(
+    tx: &mut impl EventTx,
+    metrics: &Metrics,
+    wire_message: WireMessage,
+    redriven: bool,
+    candles: &mut Vec<Candle>,
+) -> Result<bool, HandleError> {
+    let span = tracing::Span::current();
+    let meta = EventMeta::from_envelope(&wire_message);
+    let position = Position {
+        epoch: wire_message.epoch,
+        sequence: wire_message.sequence,
+    };
+    let started = Instant::now();
 
-            insert_order(&pool, new_order)
-                .await
-                .map_err(HandleError::Database)?;
+    if !redriven
+        && persistence::is_applied(tx, position.epoch, position.sequence)
+            .await
+            .map_err(HandleError::Database)?
+    {
+        return Ok(false);
+    }
+    let (table, result) = match wire_message.payload {
+        Some(Payload::OrderAccepted(order)) => {
+            span.record("event", "order_accepted");
+            let new_order = NewOrder::new(order, meta);
+            ("orders", tx.insert_order(new_order).await)
         }
         Some(Payload::TradeOccurred(trade)) => {
-            let new_trade = NewTrade {
-                maker_order_id: trade.taker_order_id as i32,
-                taker_order_id: trade.maker_order_id as i32,
-                filled_qty: trade.quantity as i32,
+            span.record("event", "trade_occurred");
+            let new_trade = NewTrade::new(trade, meta);
+            if new_trade.notional().is_none() {
+                tracing::error!(
+                    price = new_trade.price,
+                    quantity = new_trade.filled_qty,
+                    "trade notional overflows"
+                );
+                return Err(HandleError::NotionalOverflow);
+            }
+            let result = match tx.insert_trade(new_trade.clone()).await {
+                // A redelivered trade is in its candles already
+                Ok(true) => candles::record_trade(tx, &new_trade).await.map(|updated| {
+                    candles.extend(updated);
+                    true
+                }),
+                other => other,
             };
-
-            insert_trade(&pool, new_trade)
-                .await
-                .map_err(HandleError::Database)?;
+            ("trades", result)
+        }
+        Some(Payload::OrderCancelled(cancelled)) => {
+            span.record("event", "order_cancelled");
+            ("orders", tx.cancel_order(cancelled, meta).await)
+        }
+        Some(Payload::OrderRejected(rejected)) => {
+            span.record("event", "order_rejected");
+            let result = tx.record_rejection(rejected, meta).await;
+            ("order_events", result)
+        }
+        // Part of the sequence, but not persisted: every order in the batch
+        // has its own event
+        Some(Payload::BatchExecuted(_)) => {
+            span.record("event", "batch_executed");
+            tracing::debug!("event is not persisted, skipping");
+            ("persistor_checkpoint", Ok(true))
+        }
+        // Market data for live consumers, the orders have it already
+        Some(Payload::BookTop(_)) => {
+            span.record("event", "book_top");
+            tracing::debug!("event is not persisted, skipping");
+            ("persistor_checkpoint", Ok(true))
         }
         Some(_) => {
-            log::error!("Received a valid payload, but unexpected payload type");
+            tracing::error!("received a valid payload, but unexpected payload type");
             return Err(HandleError::UnexpectedPayload);
         }
         None => {
-            log::error!("Received a message with no payload");
+            tracing::error!("received a message with no payload");
             return Err(HandleError::MissingPayload);
         }
+    };
+    let applied = match result {
+        Ok(applied) => applied,
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to execute query");
+            return Err(HandleError::Database(e));
+        }
+    };
+    if !redriven && position.sequence != 0 {
+        tx.set_checkpoint(position)
+            .await
+            .map_err(HandleError::Database)?;
+    }
+    metrics
+        .db_insert_duration
+        .with_label_values(&[table])
+        .observe(started.elapsed().as_secs_f64());
+    Ok(applied)
+}
+
+/// Persists an event in a transaction of its own, adding the candles it
+/// updated to `candles` once committed.
+async fn persist_alone(
+    storage: &impl Storage,
+    metrics: &Metrics,
+    wire_message: WireMessage,
+    candles: &mut Vec<Candle>,
+) -> Result<bool, HandleError> {
+    let mut tx = storage.begin().await.map_err(HandleError::Database)?;
+    let mut updated = Vec::new();
+    let applied = handle_payload(&mut tx, metrics, wire_message, false, &mut updated).await?;
+    tx.commit().await.map_err(HandleError::Database)?;
+    candles.extend(updated);
+    Ok(applied)
+}
+
+/// Fetches the events `from` to `to` of engine run `epoch` from the engine
+/// and persists them in order, adding the candles they updated to
+/// `candles`. Returns whether the tracker reached `to`.
+///
+/// The gap is held when the engine cannot be reached, when its log is
+/// empty or ends before `to`, and when one of the events hits a transient
+/// database error: the tracker stays on the last event persisted and the
+/// delivery that revealed the gap is retried, asking for the rest again.
+/// Events failing for good are quarantined.
+///
+/// Events evicted from the engine's log are counted as lost and skipped:
+/// holding the queue back would not bring them back. So is the whole gap
+/// when the engine restarted since, its log only holds the new run.
+#[allow(clippy::too_many_arguments)]
+pub async fn recover_gap(
+    storage: &impl Storage,
+    metrics: &Metrics,
+    sequence: &mut SequenceTracker,
+    engine: &RetransmitClient,
+    epoch: u64,
+    from: u64,
+    to: u64,
+    candles: &mut Vec<Candle>,
+) -> bool {
+    tracing::warn!(
+        epoch,
+        from,
+        to,
+        "gap in the event sequence, asking for a retransmission"
+    );
+    let retransmission = match engine.fetch(from, to).await {
+        Ok(retransmission) => retransmission,
+        Err(e) => {
+            tracing::error!(error = %e, from, to, "retransmission failed, holding the gap");
+            return false;
+        }
+    };
+    if retransmission.first_available == 0 {
+        // Nothing was evicted, the engine has not logged the events (yet)
+        tracing::error!(from, to, "the engine's event log is empty, holding the gap");
+        return false;
+    }
+    if retransmission.epoch < epoch {
+        // The engine that sent the event has not logged it yet
+        tracing::error!(
+            epoch,
+            engine_epoch = retransmission.epoch,
+            "the engine's event log is from an earlier run, holding the gap"
+        );
+        return false;
+    }
+    let first_available = if retransmission.epoch > epoch {
+        to + 1
+    } else {
+        retransmission.first_available
+    };
+    if first_available > from {
+        let lost_to = to.min(first_available - 1);
+        tracing::error!(
+            epoch,
+            from,
+            to = lost_to,
+            "events were evicted from the engine's event log and are lost"
+        );
+        metrics
+            .missed_events
+            .with_label_values(&["lost"])
+            .inc_by(lost_to - from + 1);
+        sequence.advance(epoch, lost_to);
+    }
+
+    for event in retransmission.events {
+        let event_sequence = event.sequence;
+        if event.epoch != epoch
+            || event_sequence > to
+            || sequence.check(epoch, event_sequence) != SequenceCheck::Next
+        {
+            // Only an unbroken run of the gap moves the tracker
+            break;
+        }
+        let span = tracing::info_span!(
+            "persist retransmitted",
+            sequence = event_sequence,
+            correlation_id = %event.correlation_id,
+            event = tracing::field::Empty
+        );
+        let payload = event.encode_to_vec();
+        let correlation_id = Some(event.correlation_id.clone()).filter(|id| !id.is_empty());
+        match persist_alone(storage, metrics, event, candles)
+            .instrument(span)
+            .await
+        {
+            Ok(_) => {}
+            Err(err) if err.is_transient() => {
+                tracing::error!(error = ?err, sequence = event_sequence, "failed to persist retransmitted event");
+                return false;
+            }
+            Err(err) => {
+                let quarantined = NewQuarantined {
+                    payload,
+                    reason: format!("{:?}", err),
+                    retries: 0,
+                    sequence: Some(event_sequence),
+                    correlation_id,
+                };
+                if let Err(e) = storage.quarantine(quarantined).await {
+                    tracing::error!(error = ?e, sequence = event_sequence, "failed to quarantine retransmitted event");
+                    return false;
+                }
+            }
+        }
+        metrics
+            .missed_events
+            .with_label_values(&["recovered"])
+            .inc();
+        sequence.advance(epoch, event_sequence);
+    }
+    if sequence.last()
+        < Some(Position {
+            epoch,
+            sequence: to,
+        })
+    {
+        tracing::error!(
+            epoch,
+            from,
+            to,
+            last_sequence = ?sequence.last(),
+            "the engine returned only part of the gap, holding the rest"
+        );
+        return false;
+    }
+    true
+}
+
+/// A delivery to persist, acked or nacked once it is settled. Implemented
+/// by lapin's [`Delivery`], and by stand-ins driving a [`Batch`] in tests.
+pub trait Settle: Send + Sync {
+    fn data(&self) -> &[u8];
+
+    fn properties(&self) -> &BasicProperties;
+
+    fn delivery_tag(&self) -> u64;
+
+    fn ack(&self, options: BasicAckOptions) -> impl Future<Output = lapin::Result<bool>> + Send;
+
+    fn nack(&self, options: BasicNackOptions) -> impl Future<Output = lapin::Result<bool>> + Send;
+}
+
+impl Settle for Delivery {
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn properties(&self) -> &BasicProperties {
+        &self.properties
+    }
+
+    fn delivery_tag(&self) -> u64 {
+        self.delivery_tag
+    }
+
+    async fn ack(&self, options: BasicAckOptions) -> lapin::Result<bool> {
+        self.acker.ack(options).await
+    }
+
+    async fn nack(&self, options: BasicNackOptions) -> lapin::Result<bool> {
+        self.acker.nack(options).await
+    }
+}
+
+/// Acks or nacks a delivery, `outcome` being its `deliveries` label.
+async fn settle(delivery: &impl Settle, metrics: &Metrics, outcome: &str) {
+    metrics.deliveries.with_label_values(&[outcome]).inc();
+    let result = match outcome {
+        // Without requeue the broker dead-letters it
+        "requeue" | "dead_letter" => {
+            delivery
+                .nack(BasicNackOptions {
+                    requeue: outcome == "requeue",
+                    ..Default::default()
+                })
+                .await
+        }
+        _ => delivery.ack(BasicAckOptions::default()).await,
+    };
+    if let Err(e) = result {
+        tracing::error!(error = %e, outcome, "failed to settle message");
+    }
+}
+
+/// Schedules a delayed retry of a delivery that failed, or quarantines it
+/// once it is out of retries or when retrying cannot help. Should neither
+/// work, it is dead-lettered rather than lost.
+async fn fail(
+    storage: &impl Storage,
+    metrics: &Metrics,
+    retrier: &impl Retry,
+    delivery: &impl Settle,
+    reason: String,
+    transient: bool,
+) {
+    if transient && retrier.can_retry(delivery.properties()) {
+        match retrier
+            .schedule(delivery.data(), delivery.properties())
+            .await
+        {
+            Ok(true) => settle(delivery, metrics, "retry").await,
+            Ok(false) => {
+                tracing::error!("broker refused the retry, requeueing");
+                settle(delivery, metrics, "requeue").await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to schedule a retry, requeueing");
+                settle(delivery, metrics, "requeue").await;
+            }
+        }
+        return;
+    }
+
+    let decoded = WireMessage::decode(delivery.data()).ok();
+    let quarantined = NewQuarantined {
+        payload: delivery.data().to_vec(),
+        reason,
+        retries: retry_count(delivery.properties()),
+        sequence: decoded.as_ref().map(|message| message.sequence),
+        correlation_id: decoded
+            .map(|message| message.correlation_id)
+            .filter(|id| !id.is_empty()),
+    };
+    match storage.quarantine(quarantined).await {
+        Ok(id) => {
+            tracing::error!(quarantine_id = id, "message quarantined");
+            settle(delivery, metrics, "quarantine").await;
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to quarantine message, dead-lettering it");
+            settle(delivery, metrics, "dead_letter").await;
+        }
+    }
+}
+
+/// Acks deliveries in one go, each counted under its `deliveries` outcome.
+/// Every delivery before the last one must be settled already or in
+/// `acked`: the broker acks them all.
+async fn ack_all(mut acked: Vec<(impl Settle, &'static str)>, metrics: &Metrics) {
+    acked.sort_by_key(|(delivery, _)| delivery.delivery_tag());
+    for (_, outcome) in &acked {
+        metrics.deliveries.with_label_values(&[*outcome]).inc();
+    }
+    let Some((last, _)) = acked.last() else {
+        return;
+    };
+    let options = BasicAckOptions { multiple: true };
+    if let Err(e) = last.ack(options).await {
+        tracing::error!(error = %e, count = acked.len(), "failed to ack messages");
+    }
+}
+
+/// Consecutive deliveries persisted in one transaction, settled once it
+/// commits.
+pub struct Batch<S: Storage, D: Settle> {
+    tx: Option<S::Tx>,
+    /// The tracker as of the last commit, restored if the next one fails.
+    committed: SequenceTracker,
+    /// Written in `tx`, with their `deliveries` outcome.
+    written: Vec<(D, &'static str)>,
+    /// Acked whatever happens to `tx`.
+    skipped: Vec<D>,
+    /// Retried or quarantined, see [`fail`], with the reason and whether
+    /// it is transient.
+    failed: Vec<(D, String, bool)>,
+    /// Updated in `tx`.
+    candles: Vec<Candle>,
+    /// Updated in committed transactions, published once the batch is
+    /// settled.
+    committed_candles: Vec<Candle>,
+}
+
+impl<S: Storage, D: Settle> Batch<S, D> {
+    pub fn new(sequence: SequenceTracker) -> Self {
+        Batch {
+            tx: None,
+            committed: sequence,
+            written: Vec::new(),
+            skipped: Vec::new(),
+            failed: Vec::new(),
+            candles: Vec::new(),
+            committed_candles: Vec::new(),
+        }
+    }
+
+    /// Commits, then settles every delivery staged so far.
+    pub async fn flush(
+        &mut self,
+        storage: &S,
+        metrics: &Metrics,
+        sequence: &mut SequenceTracker,
+        retrier: &impl Retry,
+    ) {
+        if let Some(tx) = self.tx.take() {
+            let timer = metrics.db_commit_duration.start_timer();
+            match tx.commit().await {
+                Ok(()) => {
+                    timer.observe_duration();
+                    metrics.batch_size.observe(self.written.len() as f64);
+                    self.committed_candles.append(&mut self.candles);
+                }
+                Err(e) => {
+                    self.candles.clear();
+                    tracing::error!(error = ?e, events = self.written.len(), "failed to commit a batch");
+                    *sequence = self.committed;
+                    let reason = format!("failed to commit: {:?}", e);
+                    for (delivery, _) in self.written.drain(..) {
+                        self.failed.push((delivery, reason.clone(), true));
+                    }
+                }
+            }
+        }
+        self.committed = *sequence;
+
+        // Settled one by one before the acks, which cover every delivery
+        // before the last one
+        for (delivery, reason, transient) in self.failed.drain(..) {
+            fail(storage, metrics, retrier, &delivery, reason, transient).await;
+        }
+        let mut acked: Vec<_> = self.written.drain(..).collect();
+        acked.extend(
+            self.skipped
+                .drain(..)
+                .map(|delivery| (delivery, "duplicate")),
+        );
+        ack_all(acked, metrics).await;
+    }
+
+    /// Persists a delivery in the batch transaction, in a savepoint so a
+    /// failing event does not take the others down with it.
+    ///
+    /// Redelivered events are skipped. When events were skipped, the batch
+    /// is flushed and they are fetched from the engine and persisted first.
+    #[tracing::instrument(
+        name = "persist",
+        skip_all,
+        fields(
+            correlation_id = tracing::field::Empty,
+            event = tracing::field::Empty,
+            sequence = tracing::field::Empty,
+            retries = retry_count(delivery.properties())
+        )
+    )]
+    pub async fn stage(
+        &mut self,
+        storage: &S,
+        metrics: &Metrics,
+        sequence: &mut SequenceTracker,
+        engine: &RetransmitClient,
+        retrier: &impl Retry,
+        delivery: D,
+    ) {
+        let wire_message = match WireMessage::decode(delivery.data()) {
+            Ok(wire_message) => wire_message,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to decode message");
+                let reason = format!("failed to decode: {}", e);
+                self.failed.push((delivery, reason, false));
+                return;
+            }
+        };
+        let span = tracing::Span::current();
+        span.record("correlation_id", wire_message.correlation_id.as_str());
+        span.record("sequence", wire_message.sequence);
+        let (epoch, event_sequence) = (wire_message.epoch, wire_message.sequence);
+        // Behind the stream, it neither moves the tracker nor gets checked
+        let redriven = is_redriven(delivery.properties());
+        let check = if redriven {
+            SequenceCheck::Untracked
+        } else {
+            sequence.check(epoch, event_sequence)
+        };
+
+        match check {
+            SequenceCheck::Duplicate => {
+                tracing::info!("event was already persisted, skipping");
+                self.skipped.push(delivery);
+                return;
+            }
+            SequenceCheck::Gap { from, to } => {
+                // The recovered events are written outside the batch, after
+                // the events before them
+                self.flush(storage, metrics, sequence, retrier).await;
+                let recovered = recover_gap(
+                    storage,
+                    metrics,
+                    sequence,
+                    engine,
+                    epoch,
+                    from,
+                    to,
+                    &mut self.committed_candles,
+                )
+                .await;
+                self.committed = *sequence;
+                if !recovered {
+                    let reason = format!("failed to recover events {} to {}", from, to);
+                    self.failed.push((delivery, reason, true));
+                    return;
+                }
+            }
+            SequenceCheck::Next | SequenceCheck::Untracked => {}
+        }
+
+        let result = match self.tx.as_mut() {
+            Some(tx) => persist_in(tx, metrics, wire_message, redriven, &mut self.candles).await,
+            None => match storage.begin().await {
+                Ok(tx) => {
+                    let tx = self.tx.insert(tx);
+                    persist_in(tx, metrics, wire_message, redriven, &mut self.candles).await
+                }
+                Err(e) => Err(HandleError::Database(e)),
+            },
+        };
+        match result {
+            Ok(applied) => {
+                if !redriven {
+                    sequence.advance(epoch, event_sequence);
+                }
+                if applied {
+                    tracing::info!("message processed and persisted");
+                    self.written.push((delivery, "ack"));
+                } else {
+                    tracing::info!("event was already persisted, skipping");
+                    self.written.push((delivery, "duplicate"));
+                }
+            }
+            Err(err) => {
+                tracing::error!(error = ?err, "failed to handle payload");
+                let transient = err.is_transient();
+                if !transient && !redriven {
+                    // Quarantined, the stream moves on without it
+                    sequence.advance(epoch, event_sequence);
+                }
+                self.failed
+                    .push((delivery, format!("{:?}", err), transient));
+            }
+        }
     }
-    Ok(())
 }
 
-pub async fn amqp_receiver(pool: SqlitePool, config: AmqpSettings) -> lapin::Result<()> {
+/// Persists an event in a savepoint of `tx`, rolled back along with its
+/// `candles` if it fails.
+async fn persist_in(
+    tx: &mut impl EventTx,
+    metrics: &Metrics,
+    wire_message: WireMessage,
+    redriven: bool,
+    candles: &mut Vec<Candle>,
+) -> Result<bool, HandleError> {
+    let staged = candles.len();
+    tx.savepoint().await.map_err(HandleError::Database)?;
+    match handle_payload(tx, metrics, wire_message, redriven, candles).await {
+        Ok(applied) => {
+            tx.release_savepoint()
+                .await
+                .map_err(HandleError::Database)?;
+            Ok(applied)
+        }
+        Err(err) => {
+            candles.truncate(staged);
+            if let Err(e) = tx.rollback_to_savepoint().await {
+                tracing::error!(error = ?e, "failed to roll back to the savepoint");
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Persists deliveries in as few transactions as possible, then acks them
+/// together and publishes the candles they updated. Failures are retried
+/// after a delay, then quarantined, see [`fail`].
+async fn process_batch<S: Storage>(
+    storage: &S,
+    metrics: &Metrics,
+    sequence: &mut SequenceTracker,
+    engine: &RetransmitClient,
+    retrier: &Retrier,
+    publisher: &CandlePublisher,
+    deliveries: Vec<Delivery>,
+) {
+    let mut batch = Batch::<S, Delivery>::new(*sequence);
+    for delivery in deliveries {
+        batch
+            .stage(storage, metrics, sequence, engine, retrier, delivery)
+            .await;
+    }
+    batch.flush(storage, metrics, sequence, retrier).await;
+    publisher.publish(batch.committed_candles).await;
+}
+
+/// Waits for a delivery, then takes the ones following it for up to
+/// `max_wait_ms`, or until there are `max_events`. Returns `None` once the
+/// consumer stopped.
+async fn next_batch(
+    consumer: &mut lapin::Consumer,
+    config: &BatchSettings,
+) -> Option<Vec<Delivery>> {
+    let first = match consumer.next().await? {
+        Ok(delivery) => delivery,
+        Err(e) => {
+            tracing::error!(error = %e, "failed to receive message from amqp");
+            return None;
+        }
+    };
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(config.max_wait_ms);
+    let mut deliveries = vec![first];
+    while deliveries.len() < config.max_events {
+        match tokio::time::timeout_at(deadline, consumer.next()).await {
+            Ok(Some(Ok(delivery))) => deliveries.push(delivery),
+            Ok(Some(Err(e))) => {
+                // The consumer ends with the next call
+                tracing::error!(error = %e, "failed to receive message from amqp");
+                break;
+            }
+            Ok(None) | Err(_) => break,
+        }
+    }
+    Some(deliveries)
+}
+
+/// Declares the topology and starts consuming `config.channel`.
+async fn connect(
+    config: &AmqpSettings,
+) -> lapin::Result<(lapin::Connection, lapin::Channel, lapin::Consumer)> {
     let conn = lapin::Connection::connect(
         config.connection_string().as_str(),
         ConnectionProperties::default(),
     )
     .await?;
-
-    log::info!("Conncted to amqp: {}", config.host);
+    tracing::info!(host = %config.host, "connected to amqp");
 
     let channel = conn.create_channel().await?;
-
-    log::info!("Created amqp channel");
-    let mut consumer = channel
+    // Retries are only acked once the broker confirmed them
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+    topology::declare(&channel, config).await?;
+    // Enough unacked deliveries in flight to fill a batch
+    channel
+        .basic_qos(config.prefetch, BasicQosOptions::default())
+        .await?;
+    let consumer = channel
         .basic_consume(
             &config.channel,
             &config.consumer_tag,
@@ -83,97 +741,67 @@ pub async fn amqp_receiver(pool: SqlitePool, config: AmqpSettings) -> lapin::Res
             FieldTable::default(),
         )
         .await?;
-    log::info!(
-        "ready to receive messages, will consume. channel = {}, consumer_tag = {}",
-        config.channel,
-        config.consumer_tag
-    );
+    Ok((conn, channel, consumer))
+}
 
-    while let Some(delivery_result) = consumer.next().await {
-        let delivery = match delivery_result {
-            Ok(d) => d,
-            Err(e) => {
-                log::error!("failed to receive message from amqp {}", e);
-                continue;
-            }
-        };
+/// Consumes events forever, reconnecting with backoff whenever the broker
+/// cannot be reached or the consumer stops.
+pub async fn amqp_receiver<S: Storage>(
+    storage: S,
+    config: AmqpSettings,
+    batch: BatchSettings,
+    engine: RetransmitClient,
+    health: Arc<Health<S>>,
+    metrics: Arc<Metrics>,
+) {
+    // Resumes gap detection where the previous run stopped
+    let mut sequence = match storage.checkpoint().await {
+        Ok(last) => {
+            tracing::info!(last = ?last, "loaded the persistor checkpoint");
+            SequenceTracker::resuming_from(last)
+        }
+        Err(e) => {
+            // The checkpoint is checked again with every event
+            tracing::error!(error = ?e, "failed to load the persistor checkpoint");
+            SequenceTracker::new()
+        }
+    };
+    let mut backoff = Backoff::new();
 
-        match handle_payload(&pool, &delivery.data).await {
-            Ok(_) => {
-                log::info!("message processed and persisted");
-                if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
-                    log::error!("failed to ack message: {}", e);
+    loop {
+        match connect(&config).await {
+            Ok((_conn, channel, mut consumer)) => {
+                let publisher = CandlePublisher::new(channel.clone(), &config);
+                let retrier = Retrier::new(channel, &config);
+                health.set_amqp_connected(true);
+                backoff.reset();
+                tracing::info!(
+                    channel = %config.channel,
+                    consumer_tag = %config.consumer_tag,
+                    "ready to receive messages"
+                );
+
+                while let Some(deliveries) = next_batch(&mut consumer, &batch).await {
+                    process_batch(
+                        &storage,
+                        &metrics,
+                        &mut sequence,
+                        &engine,
+                        &retrier,
+                        &publisher,
+                        deliveries,
+                    )
+                    .await;
                 }
+
+                tracing::warn!("amqp consumer stopped, reconnecting");
+                health.set_amqp_connected(false);
             }
-            Err(err) => {
-                log::error!("failed to handle payload: {:?}, nacking", err);
-                let requeue = matches!(err, HandleError::Database(_));
-
-                if let Err(e) = delivery
-                    .nack(BasicNackOptions {
-                        requeue,
-                        ..Default::default()
-                    })
-                    .await
-                {
-                    log::error!("failed to NACK message {}", e);
-                }
+            Err(e) => {
+                let backoff_delay = backoff.delay();
+                tracing::error!(error = %e, ?backoff_delay, "failed to connect to amqp, retrying");
+                backoff.wait().await;
             }
         }
     }
-
-    Ok(())
-}
-
-#[derive(Debug, Clone)]
-pub struct NewTrade {
-    maker_order_id: i32,
-    taker_order_id: i32,
-    filled_qty: i32,
-}
-
-pub async fn insert_trade(pool: &SqlitePool, new_trade: NewTrade) -> Result<(), sqlx::Error> {
-    log::info!("inserting trade into database {:?}", new_trade);
-    sqlx::query!(
-        r#"INSERT INTO trades (maker_order_id, taker_order_id, filled_qty) VALUES ($1, $2, $3)"#,
-        new_trade.maker_order_id,
-        new_trade.taker_order_id,
-        new_trade.filled_qty
-    )
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        log::error!("failed to execute query: {:?}", e);
-        e
-    })?;
-
-    Ok(())
-}
-
-#[derive(Debug, Clone)]
-pub struct NewOrder {
-    order_id: i32,
-    base_currency: String,
-    quote_currency: String,
-    side: i32,
-    quantity: i32,
-    price: i32,
-}
-
-pub async fn insert_order(pool: &SqlitePool, new_order: NewOrder) -> Result<(), sqlx::Error> {
-    log::info!("inserting order into database {:?}", new_order);
-    sqlx::query!(
-        r#"INSERT INTO orders (order_id, base_currency, quote_currency, side, quantity, price) VALUES ($1, $2, $3, $4, $5, $6)"#,
-        new_order.order_id,
-        new_order.base_currency,
-        new_order.quote_currency,
-        new_order.side,
-        new_order.quantity,
-        new_order.price,
-    ).execute(pool).await.map_err(|e| {
-        log::error!("failed to execute query: {:?}", e);
-        e
-    })?;
-
-    Ok(())
 }
@@ -0,0 +1,165 @@
+//! Inspects and re-drives the messages the persistor quarantined.
+use lapin::{ConnectionProperties, options::ConfirmSelectOptions};
+use message_persistor::configuration::{DatabaseBackend, Settings, get_configuration};
+use message_persistor::messages::trading::WireMessage;
+use message_persistor::quarantine::Quarantined;
+use message_persistor::retry::{publish_to_queue, redrive_properties};
+use message_persistor::storage::{PostgresStorage, SqliteStorage, Storage};
+use message_persistor::topology;
+use prost::Message;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: quarantine <command>
+
+Commands:
+  list [--all]        messages waiting in quarantine, --all includes re-driven ones
+  show <id>           a message with its decoded payload
+  redrive <id>...     publishes messages to the persistor's queue again
+  redrive --pending   re-drives every message not re-driven yet
+  delete <id>...      removes messages for good";
+
+type Error = Box<dyn std::error::Error>;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let configuration = get_configuration().expect("failed to get configuration from file");
+    let result = match configuration.database.backend {
+        DatabaseBackend::Sqlite => {
+            let pool =
+                SqlitePoolOptions::new().connect_lazy_with(configuration.database.get_config());
+            run(&args, &configuration, &SqliteStorage::new(pool)).await
+        }
+        DatabaseBackend::Postgres => {
+            let pool = PgPoolOptions::new()
+                .connect_lazy_with(configuration.database.postgres.connect_options());
+            run(&args, &configuration, &PostgresStorage::new(pool)).await
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(
+    args: &[String],
+    configuration: &Settings,
+    storage: &impl Storage,
+) -> Result<(), Error> {
+    let command = args.first().map(String::as_str);
+    let rest = args.get(1..).unwrap_or_default();
+    match (command, rest) {
+        (Some("list"), []) => list(storage, false).await,
+        (Some("list"), [flag]) if flag == "--all" => list(storage, true).await,
+        (Some("show"), [id]) => show(storage, id.parse()?).await,
+        (Some("redrive"), [flag]) if flag == "--pending" => {
+            let ids = storage
+                .list_quarantined(false)
+                .await?
+                .into_iter()
+                .map(|message| message.id)
+                .collect();
+            redrive(configuration, storage, ids).await
+        }
+        (Some("redrive"), ids) if !ids.is_empty() => {
+            redrive(configuration, storage, parse_ids(ids)?).await
+        }
+        (Some("delete"), ids) if !ids.is_empty() => {
+            for id in parse_ids(ids)? {
+                if !storage.delete_quarantined(id).await? {
+                    return Err(format!("no quarantined message {}", id).into());
+                }
+                println!("deleted {}", id);
+            }
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn parse_ids(ids: &[String]) -> Result<Vec<i64>, Error> {
+    ids.iter()
+        .map(|id| id.parse().map_err(|_| format!("invalid id {}", id).into()))
+        .collect()
+}
+
+fn summary(message: &Quarantined) -> String {
+    format!(
+        "{}\tquarantined_at={}\tretries={}\tsequence={}\tcorrelation_id={}\tredriven_at={}\t{}",
+        message.id,
+        message.quarantined_at,
+        message.retries,
+        message.sequence.map_or("-".into(), |s| s.to_string()),
+        message.correlation_id.as_deref().unwrap_or("-"),
+        message.redriven_at.map_or("-".into(), |t| t.to_string()),
+        message.reason,
+    )
+}
+
+async fn list(storage: &impl Storage, include_redriven: bool) -> Result<(), Error> {
+    for message in storage.list_quarantined(include_redriven).await? {
+        println!("{}", summary(&message));
+    }
+    Ok(())
+}
+
+async fn show(storage: &impl Storage, id: i64) -> Result<(), Error> {
+    let message = storage
+        .get_quarantined(id)
+        .await?
+        .ok_or_else(|| format!("no quarantined message {}", id))?;
+    println!("{}", summary(&message));
+    match WireMessage::decode(message.payload.as_slice()) {
+        Ok(decoded) => println!("{:#?}", decoded),
+        Err(e) => println!("undecodable payload ({}): {:02x?}", e, message.payload),
+    }
+    Ok(())
+}
+
+/// Publishes the messages straight to the persistor's queue, with their
+/// retry count reset, and marks them re-driven once the broker confirmed.
+async fn redrive(
+    configuration: &Settings,
+    storage: &impl Storage,
+    ids: Vec<i64>,
+) -> Result<(), Error> {
+    let config = &configuration.amqp;
+    let conn = lapin::Connection::connect(
+        config.connection_string().as_str(),
+        ConnectionProperties::default(),
+    )
+    .await?;
+    let channel = conn.create_channel().await?;
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+    topology::declare(&channel, config).await?;
+
+    for id in ids {
+        let message = storage
+            .get_quarantined(id)
+            .await?
+            .ok_or_else(|| format!("no quarantined message {}", id))?;
+        if !publish_to_queue(
+            &channel,
+            &config.channel,
+            &message.payload,
+            redrive_properties(),
+        )
+        .await?
+        {
+            return Err(format!("the broker refused message {}", id).into());
+        }
+        storage.mark_redriven(id).await?;
+        println!("re-drove {}", id);
+    }
+    Ok(())
+}
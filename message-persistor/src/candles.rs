@@ -0,0 +1,296 @@
+//! OHLCV candles per instrument, built from trades.
+//!
+//! A trade updates the candle it falls in for every [`Interval`], in the
+//! transaction persisting it, so a redelivered trade is counted once.
+//! Candles open at multiples of their interval since the Unix epoch, going
+//! by the engine's timestamp of the trade, and intervals without trades
+//! have none.
+//!
+//! Once their transaction commits, the updated candles are published on
+//! the market data exchange with routing keys such as `candle.1m.BTC-USD`,
+//! see [`CandlePublisher`]. [`backfill`] rebuilds them all from the
+//! `trades` table.
+use crate::configuration::AmqpSettings;
+use crate::messages::trading::{self, WireMessage, wire_message::Payload};
+use crate::persistence::NewTrade;
+use crate::storage::{EventTx, Storage};
+use lapin::{BasicProperties, Channel, options::BasicPublishOptions};
+use prost::Message;
+use std::collections::{BTreeMap, HashSet, btree_map::Entry};
+use std::fmt;
+
+/// Trades read per query by [`backfill`].
+const BACKFILL_PAGE: i64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Interval {
+    Second,
+    Minute,
+    FiveMinutes,
+    Hour,
+    Day,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 5] = [
+        Interval::Second,
+        Interval::Minute,
+        Interval::FiveMinutes,
+        Interval::Hour,
+        Interval::Day,
+    ];
+
+    pub fn seconds(self) -> i64 {
+        match self {
+            Interval::Second => 1,
+            Interval::Minute => 60,
+            Interval::FiveMinutes => 300,
+            Interval::Hour => 3600,
+            Interval::Day => 86_400,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Interval::Second => "1s",
+            Interval::Minute => "1m",
+            Interval::FiveMinutes => "5m",
+            Interval::Hour => "1h",
+            Interval::Day => "1d",
+        }
+    }
+
+    /// Start of the candle `timestamp_us` falls in.
+    pub fn open_time_us(self, timestamp_us: i64) -> i64 {
+        let length_us = self.seconds() * 1_000_000;
+        timestamp_us - timestamp_us.rem_euclid(length_us)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candle {
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub interval: Interval,
+    pub open_time_us: i64,
+    pub open: i64,
+    pub high: i64,
+    pub low: i64,
+    pub close: i64,
+    /// Traded quantity, in the base currency.
+    pub volume: i64,
+    /// Sum of price * quantity, like `orders.filled_notional`.
+    pub notional: i64,
+    pub trade_count: i64,
+}
+
+/// The sums of a candle do not fit its 64 bit columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+#[derive(Debug)]
+pub enum BackfillError {
+    Database(sqlx::Error),
+    Overflow(CandleKey),
+}
+
+impl fmt::Display for BackfillError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackfillError::Database(e) => write!(f, "{}", e),
+            BackfillError::Overflow(key) => write!(f, "the candle {:?} overflows", key),
+        }
+    }
+}
+
+impl std::error::Error for BackfillError {}
+
+impl From<sqlx::Error> for BackfillError {
+    fn from(e: sqlx::Error) -> Self {
+        BackfillError::Database(e)
+    }
+}
+
+/// Identifies a candle: instrument, interval and open time.
+pub type CandleKey = (String, String, Interval, i64);
+
+impl Candle {
+    /// The candle of a single trade, None when the engine did not stamp it
+    /// or its notional overflows.
+    pub fn of_trade(trade: &NewTrade, interval: Interval) -> Option<Candle> {
+        let timestamp_us = trade.meta.engine_timestamp_us?;
+        Some(Candle {
+            base_currency: trade.base_currency.clone(),
+            quote_currency: trade.quote_currency.clone(),
+            interval,
+            open_time_us: interval.open_time_us(timestamp_us),
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.filled_qty,
+            notional: trade.notional()?,
+            trade_count: 1,
+        })
+    }
+
+    /// Folds the trades of `later`, a candle with the same key, into this
+    /// one. Left as is when its volume or notional would overflow.
+    pub fn merge(&mut self, later: &Candle) -> Result<(), Overflow> {
+        let volume = self.volume.checked_add(later.volume).ok_or(Overflow)?;
+        let notional = self.notional.checked_add(later.notional).ok_or(Overflow)?;
+        self.high = self.high.max(later.high);
+        self.low = self.low.min(later.low);
+        self.close = later.close;
+        self.volume = volume;
+        self.notional = notional;
+        self.trade_count += later.trade_count;
+        Ok(())
+    }
+
+    pub fn key(&self) -> CandleKey {
+        (
+            self.base_currency.clone(),
+            self.quote_currency.clone(),
+            self.interval,
+            self.open_time_us,
+        )
+    }
+
+    /// Routing key on the market data exchange, e.g. `candle.1m.BTC-USD`.
+    pub fn routing_key(&self) -> String {
+        format!(
+            "candle.{}.{}-{}",
+            self.interval.as_str(),
+            self.base_currency,
+            self.quote_currency
+        )
+    }
+
+    pub fn to_message(&self) -> WireMessage {
+        WireMessage {
+            payload: Some(Payload::Candle(trading::Candle {
+                base_currency: self.base_currency.clone(),
+                quote_currency: self.quote_currency.clone(),
+                interval: self.interval.as_str().into(),
+                open_time_us: self.open_time_us,
+                open: self.open as u64,
+                high: self.high as u64,
+                low: self.low as u64,
+                close: self.close as u64,
+                volume: self.volume as u64,
+                notional: self.notional as u64,
+                trade_count: self.trade_count as u64,
+            })),
+            ..Default::default()
+        }
+    }
+}
+
+/// Adds `trade` to its candles, returns them as they now stand.
+pub async fn record_trade(
+    tx: &mut impl EventTx,
+    trade: &NewTrade,
+) -> Result<Vec<Candle>, sqlx::Error> {
+    let mut candles = Vec::with_capacity(Interval::ALL.len());
+    for interval in Interval::ALL {
+        if let Some(candle) = Candle::of_trade(trade, interval) {
+            candles.push(tx.upsert_candle(&candle).await?);
+        }
+    }
+    Ok(candles)
+}
+
+/// Replaces every candle with ones rebuilt from the `trades` table, in a
+/// single transaction. Meant to run while the persistor is stopped.
+/// Returns how many trades went into them.
+#[tracing::instrument(name = "backfill candles", skip_all)]
+pub async fn backfill(storage: &impl Storage) -> Result<u64, BackfillError> {
+    let mut tx = storage.begin().await?;
+    let mut candles: BTreeMap<CandleKey, Candle> = BTreeMap::new();
+    let mut after_id = 0;
+    let mut trades = 0;
+    loop {
+        let page = tx.trades_after(after_id, BACKFILL_PAGE).await?;
+        let Some((last_id, _)) = page.last() else {
+            break;
+        };
+        after_id = *last_id;
+        for (_, trade) in &page {
+            trades += 1;
+            for interval in Interval::ALL {
+                let Some(candle) = Candle::of_trade(trade, interval) else {
+                    continue;
+                };
+                match candles.entry(candle.key()) {
+                    Entry::Occupied(mut existing) => existing
+                        .get_mut()
+                        .merge(&candle)
+                        .map_err(|Overflow| BackfillError::Overflow(candle.key()))?,
+                    Entry::Vacant(entry) => {
+                        entry.insert(candle);
+                    }
+                }
+            }
+        }
+    }
+
+    let removed = tx.delete_candles().await?;
+    for candle in candles.values() {
+        tx.upsert_candle(candle).await?;
+    }
+    tx.commit().await?;
+    tracing::info!(
+        trades,
+        removed,
+        candles = candles.len(),
+        "rebuilt the candles"
+    );
+    Ok(trades)
+}
+
+/// Publishes candles on the market data exchange.
+pub struct CandlePublisher {
+    channel: Channel,
+    exchange: String,
+}
+
+impl CandlePublisher {
+    pub fn new(channel: Channel, config: &AmqpSettings) -> Self {
+        CandlePublisher {
+            channel,
+            exchange: config.market_data_exchange.clone(),
+        }
+    }
+
+    /// Publishes the last state of each candle in `candles`, oldest
+    /// updates first. Failures are only logged: the candles are in the
+    /// database, and the next trade publishes them again.
+    pub async fn publish(&self, candles: Vec<Candle>) {
+        let mut seen = HashSet::new();
+        let mut latest: Vec<Candle> = candles
+            .into_iter()
+            .rev()
+            .filter(|candle| seen.insert(candle.key()))
+            .collect();
+        latest.reverse();
+
+        for candle in latest {
+            let payload = candle.to_message().encode_to_vec();
+            let published = self
+                .channel
+                .basic_publish(
+                    &self.exchange,
+                    &candle.routing_key(),
+                    BasicPublishOptions::default(),
+                    &payload,
+                    BasicProperties::default(),
+                )
+                .await;
+            if let Err(e) = published {
+                tracing::error!(error = %e, routing_key = %candle.routing_key(), "failed to publish a candle");
+                return;
+            }
+        }
+    }
+}
@@ -1,12 +1,45 @@
 use config;
 use secrecy::{ExposeSecret, SecretBox};
 use serde_aux::field_attributes::deserialize_number_from_string;
-use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
+use std::str::FromStr;
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Settings {
     pub amqp: AmqpSettings,
+    pub health: HealthSettings,
     pub database: DatabaseSettings,
+    pub engine: EngineSettings,
+    pub batch: BatchSettings,
+}
+
+/// How many events the persistor writes per transaction. A batch closes
+/// once it has `max_events`, or `max_wait_ms` after its first event.
+#[derive(serde::Deserialize, Debug)]
+pub struct BatchSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_events: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_wait_ms: u64,
+}
+
+/// Where the engine listens, to ask it for missed events.
+#[derive(serde::Deserialize, Debug)]
+pub struct EngineSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retransmit_timeout_ms: u64,
+}
+
+/// Address of the HTTP listener answering health probes.
+#[derive(serde::Deserialize, Debug)]
+pub struct HealthSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -16,20 +49,101 @@ pub struct AmqpSettings {
     pub port: u16,
     pub username: String,
     pub password: SecretBox<String>,
+    /// Topic exchange events are published to.
+    pub exchange: String,
+    /// Topic exchange the candles are published to, see
+    /// [`crate::candles`].
+    pub market_data_exchange: String,
+    /// Queue of the persistor.
     pub channel: String,
+    /// Routing keys `channel` is bound with.
+    pub bindings: Vec<String>,
     pub consumer_tag: String,
+    /// Unacked deliveries the broker sends ahead, at least
+    /// `batch.max_events` for batches to fill up.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub prefetch: u16,
+    /// Where messages rejected from `channel` are routed.
+    pub dead_letter_exchange: String,
+    pub dead_letter_queue: String,
+    /// Holds failed messages for `retry_delay_ms` before they return to
+    /// `channel`.
+    pub retry_queue: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retry_delay_ms: u32,
+    /// Delayed retries before a message is quarantined.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_retries: u32,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBackend {
+    Sqlite,
+    Postgres,
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct DatabaseSettings {
+    pub backend: DatabaseBackend,
+    /// SQLite database file, used with the `sqlite` backend.
     pub file: String,
+    /// `off`, `normal`, `full` or `extra`. With `normal` a power loss can
+    /// undo the last commits, the persistor sees the gap in the sequence
+    /// and fetches them from the engine again.
+    #[serde(deserialize_with = "deserialize_synchronous")]
+    pub synchronous: SqliteSynchronous,
+    /// Used with the `postgres` backend.
+    pub postgres: PostgresSettings,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PostgresSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: String,
+    pub password: SecretBox<String>,
+    pub database_name: String,
+    pub require_ssl: bool,
+}
+
+impl PostgresSettings {
+    pub fn connect_options(&self) -> PgConnectOptions {
+        let ssl_mode = if self.require_ssl {
+            PgSslMode::Require
+        } else {
+            PgSslMode::Prefer
+        };
+        PgConnectOptions::new()
+            .host(&self.host)
+            .port(self.port)
+            .username(&self.username)
+            .password(self.password.expose_secret())
+            .database(&self.database_name)
+            .ssl_mode(ssl_mode)
+    }
+}
+
+fn deserialize_synchronous<'de, D>(deserializer: D) -> Result<SqliteSynchronous, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+    SqliteSynchronous::from_str(&value).map_err(serde::de::Error::custom)
 }
 
 impl DatabaseSettings {
     pub fn get_config(&self) -> SqliteConnectOptions {
         let base_path = std::env::current_dir().expect("Failed to determine the current directory");
         let db_file = base_path.join(&self.file);
-        return SqliteConnectOptions::default().filename(db_file);
+        // WAL lets readers, like the health probes, carry on while a batch
+        // is written, and commits only append to the log
+        SqliteConnectOptions::default()
+            .filename(db_file)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(self.synchronous)
     }
 }
 
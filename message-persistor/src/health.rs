@@ -0,0 +1,37 @@
+//! Liveness and readiness of the persistor, served by
+//! [`observability::probes::serve_probes`].
+use crate::storage::Storage;
+use observability::probes::Readiness;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub struct Health<S> {
+    amqp_connected: AtomicBool,
+    storage: S,
+}
+
+impl<S: Storage> Health<S> {
+    pub fn new(storage: S) -> Self {
+        Health {
+            amqp_connected: AtomicBool::new(false),
+            storage,
+        }
+    }
+
+    /// Set by the receiver, true while its consumer is attached to the queue.
+    pub fn set_amqp_connected(&self, connected: bool) {
+        self.amqp_connected.store(connected, Ordering::Relaxed);
+    }
+}
+
+impl<S: Storage> Readiness for Health<S> {
+    async fn readiness(&self) -> (bool, String) {
+        let amqp_connected = self.amqp_connected.load(Ordering::Relaxed);
+        let database_reachable = self.storage.ping().await;
+        let ready = amqp_connected && database_reachable;
+        let body = format!(
+            r#"{{"ready":{},"amqp_connected":{},"database_reachable":{}}}"#,
+            ready, amqp_connected, database_reachable,
+        );
+        (ready, body)
+    }
+}
@@ -1,3 +1,14 @@
 pub mod amqp_receiver;
+pub mod candles;
 pub mod configuration;
+pub mod health;
 pub mod messages;
+pub mod metrics;
+pub mod persistence;
+pub mod quarantine;
+pub mod retransmit;
+pub mod retry;
+pub mod schema;
+pub mod sequence;
+pub mod storage;
+pub mod topology;
@@ -1,19 +1,107 @@
 use message_persistor::amqp_receiver;
+use message_persistor::candles;
+use message_persistor::configuration::{DatabaseBackend, Settings};
+use message_persistor::health::Health;
+use message_persistor::metrics::Metrics;
+use message_persistor::retransmit::RetransmitClient;
+use message_persistor::schema;
+use message_persistor::storage::{PostgresStorage, SqliteStorage, Storage};
+use observability::probes::serve_probes;
+use observability::telemetry::{get_subscriber, init_subscriber};
+use sqlx::postgres::PgPoolOptions;
 use sqlx::sqlite::SqlitePoolOptions;
+use std::sync::Arc;
+
+const USAGE: &str = "\
+Usage: message-persistor [--migrate-only | --backfill-candles]
+
+Options:
+  --migrate-only       applies pending migrations and exits
+  --backfill-candles   rebuilds the candles from the trades and exits, run it
+                       while the persistor is stopped";
+
+enum Mode {
+    Persist,
+    MigrateOnly,
+    BackfillCandles,
+}
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mode = match args.as_slice() {
+        [] => Mode::Persist,
+        [flag] if flag == "--migrate-only" => Mode::MigrateOnly,
+        [flag] if flag == "--backfill-candles" => Mode::BackfillCandles,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let subscriber = get_subscriber("message-persistor".into(), "info".into(), std::io::stdout);
+    init_subscriber(subscriber);
     let configuration = message_persistor::configuration::get_configuration()
         .expect("failed to get configuration from file");
 
-    let connection_pool =
-        SqlitePoolOptions::new().connect_lazy_with(configuration.database.get_config());
-    amqp_receiver::amqp_receiver(connection_pool, configuration.amqp)
-        .await
-        .expect("failed to establish queue loop");
+    match configuration.database.backend {
+        DatabaseBackend::Sqlite => {
+            let pool =
+                SqlitePoolOptions::new().connect_lazy_with(configuration.database.get_config());
+            run(SqliteStorage::new(pool), configuration, mode).await
+        }
+        DatabaseBackend::Postgres => {
+            let pool = PgPoolOptions::new()
+                .connect_lazy_with(configuration.database.postgres.connect_options());
+            run(PostgresStorage::new(pool), configuration, mode).await
+        }
+    }
+}
+
+async fn run<S: Storage>(storage: S, configuration: Settings, mode: Mode) -> std::io::Result<()> {
+    // Also refuses a database migrated by a newer persistor
+    if let Err(e) = schema::migrate(&storage).await {
+        tracing::error!(error = %e, "failed to prepare the database schema");
+        return Err(std::io::Error::other(e));
+    }
+    match mode {
+        Mode::Persist => {}
+        Mode::MigrateOnly => return Ok(()),
+        Mode::BackfillCandles => {
+            return match candles::backfill(&storage).await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    tracing::error!(error = ?e, "failed to backfill the candles");
+                    Err(std::io::Error::other(e))
+                }
+            };
+        }
+    }
+
+    let health = Arc::new(Health::new(storage.clone()));
+    let metrics = Arc::new(Metrics::new());
+    let health_listener = tokio::net::TcpListener::bind(format!(
+        "{}:{}",
+        configuration.health.host, configuration.health.port
+    ))
+    .await?;
+    tracing::info!(address = %health_listener.local_addr()?, "serving health probes");
+    tokio::spawn(serve_probes(
+        health_listener,
+        health.clone(),
+        metrics.clone(),
+    ));
+
+    let engine = RetransmitClient::new(&configuration.engine);
+    amqp_receiver::amqp_receiver(
+        storage,
+        configuration.amqp,
+        configuration.batch,
+        engine,
+        health,
+        metrics,
+    )
+    .await;
 
     Ok(())
 }
@@ -0,0 +1,102 @@
+//! Prometheus metrics of the persistor, scraped from `GET /metrics` on the
+//! health listener.
+use observability::probes::RenderMetrics;
+use prometheus::{
+    Histogram, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+pub struct Metrics {
+    registry: Registry,
+    /// Deliveries handled, by outcome (`ack`, `duplicate`, `retry`,
+    /// `quarantine`, `requeue`, `dead_letter`).
+    pub deliveries: IntCounterVec,
+    /// Events missing from the sequence, by whether the engine could
+    /// retransmit them (`recovered`, `lost`).
+    pub missed_events: IntCounterVec,
+    pub db_insert_duration: HistogramVec,
+    /// Events written per committed transaction.
+    pub batch_size: Histogram,
+    pub db_commit_duration: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let deliveries = IntCounterVec::new(
+            Opts::new("persistor_deliveries_total", "AMQP deliveries, by outcome"),
+            &["outcome"],
+        )
+        .unwrap();
+        let missed_events = IntCounterVec::new(
+            Opts::new(
+                "persistor_missed_events_total",
+                "Events skipped in the sequence, by outcome",
+            ),
+            &["outcome"],
+        )
+        .unwrap();
+        let db_insert_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "persistor_db_insert_duration_seconds",
+                "Time spent persisting an event, by the table it lands in",
+            )
+            .buckets(prometheus::exponential_buckets(0.00005, 2.0, 16).unwrap()),
+            &["table"],
+        )
+        .unwrap();
+
+        let batch_size = Histogram::with_opts(
+            HistogramOpts::new(
+                "persistor_batch_size",
+                "Events written per committed transaction",
+            )
+            .buckets(prometheus::exponential_buckets(1.0, 2.0, 12).unwrap()),
+        )
+        .unwrap();
+        let db_commit_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "persistor_db_commit_duration_seconds",
+                "Time spent committing a batch",
+            )
+            .buckets(prometheus::exponential_buckets(0.00005, 2.0, 16).unwrap()),
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(deliveries.clone())).unwrap();
+        registry.register(Box::new(missed_events.clone())).unwrap();
+        registry
+            .register(Box::new(db_insert_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(batch_size.clone())).unwrap();
+        registry
+            .register(Box::new(db_commit_duration.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            deliveries,
+            missed_events,
+            db_insert_duration,
+            batch_size,
+            db_commit_duration,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+impl RenderMetrics for Metrics {
+    fn render(&self) -> String {
+        Metrics::render(self)
+    }
+}
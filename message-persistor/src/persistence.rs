@@ -0,0 +1,123 @@
+//! Writes engine events to the database so it mirrors what the engine
+//! knows about every order.
+//!
+//! Each event is applied in one transaction: the `orders` row is created or
+//! updated and its change appended to `order_events`. A trade updates both
+//! of its orders:
+//!
+//! ```text
+//! accepted ──▶ open ──fill──▶ partially_filled ──fill──▶ filled
+//!               │                  │
+//!               └──────cancel──────┴──▶ cancelled
+//! ```
+//!
+//! Rejected orders never get an engine id, they are only recorded in
+//! `order_events`.
+//!
+//! Applying an event twice changes nothing: the `persistor_checkpoint` row
+//! holds the position of the last event applied, and each insert is keyed
+//! on the engine epoch and sequence or on the order id, so a redelivery is
+//! always safe. Sequences restart at 1 with every engine run, the epoch
+//! tells the runs apart; order ids start past the epoch, so they never
+//! repeat across runs.
+//!
+//! The queries live with each backend, see [`crate::storage`].
+use crate::messages::trading::{OrderAccepted, TradeOccurred, WireMessage};
+use crate::sequence::{SequenceCheck, SequenceTracker};
+use crate::storage::EventTx;
+
+/// Envelope fields stored with every event.
+#[derive(Debug, Clone, Default)]
+pub struct EventMeta {
+    /// Unset for events the engine did not sequence or stamp.
+    pub sequence: Option<i64>,
+    /// Engine run `sequence` counts in, 0 from engines predating epochs.
+    pub epoch: i64,
+    pub engine_timestamp_us: Option<i64>,
+    pub correlation_id: Option<String>,
+}
+
+impl EventMeta {
+    pub fn from_envelope(message: &WireMessage) -> Self {
+        EventMeta {
+            sequence: Some(message.sequence as i64).filter(|s| *s != 0),
+            epoch: message.epoch as i64,
+            engine_timestamp_us: Some(message.engine_timestamp_us as i64).filter(|t| *t != 0),
+            correlation_id: Some(message.correlation_id.clone()).filter(|id| !id.is_empty()),
+        }
+    }
+}
+
+/// Whether the event with `sequence` in engine run `epoch` was applied
+/// already, going by the checkpoint. Unsequenced events never are.
+pub async fn is_applied(
+    tx: &mut impl EventTx,
+    epoch: u64,
+    sequence: u64,
+) -> Result<bool, sqlx::Error> {
+    let tracker = SequenceTracker::resuming_from(tx.checkpoint().await?);
+    Ok(tracker.check(epoch, sequence) == SequenceCheck::Duplicate)
+}
+
+#[derive(Debug, Clone)]
+pub struct NewOrder {
+    pub order_id: i64,
+    pub user_id: i64,
+    pub client_order_id: Option<String>,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub side: i32,
+    pub quantity: i64,
+    pub price: i64,
+    pub meta: EventMeta,
+}
+
+impl NewOrder {
+    pub fn new(order: OrderAccepted, meta: EventMeta) -> Self {
+        NewOrder {
+            order_id: order.order_id as i64,
+            user_id: order.user_id as i64,
+            client_order_id: Some(order.client_order_id).filter(|id| !id.is_empty()),
+            base_currency: order.base_currency,
+            quote_currency: order.quote_currency,
+            side: order.side,
+            quantity: order.quantity as i64,
+            price: order.price as i64,
+            meta,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewTrade {
+    pub maker_order_id: i64,
+    pub taker_order_id: i64,
+    pub filled_qty: i64,
+    pub price: i64,
+    /// Side of the taker.
+    pub aggressor_side: i32,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub meta: EventMeta,
+}
+
+impl NewTrade {
+    pub fn new(trade: TradeOccurred, meta: EventMeta) -> Self {
+        NewTrade {
+            maker_order_id: trade.maker_order_id as i64,
+            taker_order_id: trade.taker_order_id as i64,
+            filled_qty: trade.quantity as i64,
+            price: trade.price as i64,
+            aggressor_side: trade.taker_side,
+            base_currency: trade.base_currency,
+            quote_currency: trade.quote_currency,
+            meta,
+        }
+    }
+
+    /// Price times quantity, None when it does not fit the 64 bit notional
+    /// columns.
+    pub fn notional(&self) -> Option<i64> {
+        i64::try_from(self.price as i128 * self.filled_qty as i128).ok()
+    }
+}
@@ -0,0 +1,27 @@
+//! Messages that could not be persisted, either because they will never be
+//! (they do not decode, or carry a payload the persistor does not know) or
+//! because they kept failing after `max_retries` delayed retries.
+//!
+//! They stay in the `quarantine` table until an operator re-drives them
+//! with the `quarantine` CLI, which publishes them to the persistor's queue
+//! again.
+#[derive(Debug, Clone)]
+pub struct NewQuarantined {
+    pub payload: Vec<u8>,
+    pub reason: String,
+    pub retries: u32,
+    pub sequence: Option<u64>,
+    pub correlation_id: Option<String>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Quarantined {
+    pub id: i64,
+    pub payload: Vec<u8>,
+    pub reason: String,
+    pub retries: i64,
+    pub sequence: Option<i64>,
+    pub correlation_id: Option<String>,
+    pub quarantined_at: i64,
+    pub redriven_at: Option<i64>,
+}
@@ -0,0 +1,76 @@
+//! Client for the engine's retransmission service: asks for a range of
+//! sequenced events over the engine's TCP listener and reads them back.
+use crate::configuration::EngineSettings;
+use crate::messages::trading::{RetransmitRequest, WireMessage, wire_message::Payload};
+use prost::Message;
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Events of a requested range the engine still had.
+#[derive(Debug)]
+pub struct Retransmission {
+    pub events: Vec<WireMessage>,
+    /// Oldest sequence left in the engine's event log, anything before it
+    /// is lost.
+    pub first_available: u64,
+    /// Engine run the log belongs to. Events of an older run are all lost.
+    pub epoch: u64,
+}
+
+pub struct RetransmitClient {
+    address: String,
+    timeout: Duration,
+}
+
+impl RetransmitClient {
+    pub fn new(config: &EngineSettings) -> Self {
+        RetransmitClient {
+            address: format!("{}:{}", config.host, config.port),
+            timeout: Duration::from_millis(config.retransmit_timeout_ms),
+        }
+    }
+
+    /// Fetches events `from` to `to` (inclusive), in sequence order.
+    #[tracing::instrument(name = "retransmit", skip(self), fields(address = %self.address))]
+    pub async fn fetch(&self, from: u64, to: u64) -> std::io::Result<Retransmission> {
+        tokio::time::timeout(self.timeout, self.request(from, to))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "retransmission timed out"))?
+    }
+
+    async fn request(&self, from: u64, to: u64) -> std::io::Result<Retransmission> {
+        let mut stream = BufReader::new(TcpStream::connect(&self.address).await?);
+        let request = WireMessage {
+            payload: Some(Payload::RetransmitRequest(RetransmitRequest {
+                from_sequence: from,
+                to_sequence: to,
+            })),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        stream.write_u32(request.len() as u32).await?;
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+
+        let mut events = Vec::new();
+        loop {
+            let len = stream.read_u32().await?;
+            let mut buf = vec![0; len as usize];
+            stream.read_exact(&mut buf).await?;
+            let message = WireMessage::decode(buf.as_slice())
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            match message.payload {
+                Some(Payload::RetransmitComplete(complete)) => {
+                    return Ok(Retransmission {
+                        events,
+                        first_available: complete.first_available,
+                        epoch: message.epoch,
+                    });
+                }
+                _ => events.push(message),
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Delayed retries of deliveries that failed to persist.
+//!
+//! A failed delivery is published to the retry queue with its retry count
+//! in the `x-retry-count` header, and acked. The retry queue holds it for
+//! `retry_delay_ms` before dead-lettering it back to the persistor's queue,
+//! so the persistor keeps consuming meanwhile instead of hot-looping on a
+//! requeue.
+use crate::configuration::AmqpSettings;
+use lapin::{
+    BasicProperties, Channel,
+    options::BasicPublishOptions,
+    publisher_confirm::Confirmation,
+    types::{AMQPValue, FieldTable},
+};
+
+pub const RETRY_COUNT_HEADER: &str = "x-retry-count";
+/// Set on messages re-driven from quarantine. Their sequence is behind the
+/// checkpoint, so they skip the sequence check.
+pub const REDRIVEN_HEADER: &str = "x-redriven";
+/// Delivery mode of messages written to disk by the broker.
+const PERSISTENT: u8 = 2;
+
+/// Times a delivery was retried already, 0 on its first attempt.
+pub fn retry_count(properties: &BasicProperties) -> u32 {
+    let value = properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(RETRY_COUNT_HEADER).cloned());
+    match value {
+        Some(AMQPValue::LongUInt(count)) => count,
+        Some(AMQPValue::LongInt(count)) => count.max(0) as u32,
+        Some(AMQPValue::LongLongInt(count)) => count.clamp(0, u32::MAX as i64) as u32,
+        _ => 0,
+    }
+}
+
+/// `properties` with the retry count set, keeping the other headers.
+pub fn with_retry_count(properties: BasicProperties, retries: u32) -> BasicProperties {
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    headers.insert(RETRY_COUNT_HEADER.into(), AMQPValue::LongUInt(retries));
+    properties
+        .with_headers(headers)
+        .with_delivery_mode(PERSISTENT)
+}
+
+/// Publishes `payload` to `queue` through the default exchange and waits for
+/// the broker to confirm it, the channel must be in confirm mode.
+pub async fn publish_to_queue(
+    channel: &Channel,
+    queue: &str,
+    payload: &[u8],
+    properties: BasicProperties,
+) -> lapin::Result<bool> {
+    let confirmation = channel
+        .basic_publish(
+            "",
+            queue,
+            BasicPublishOptions::default(),
+            payload,
+            properties,
+        )
+        .await?
+        .await?;
+    Ok(!matches!(confirmation, Confirmation::Nack(_)))
+}
+
+/// Schedules failed deliveries for another attempt, see [`Retrier`].
+pub trait Retry: Sync {
+    /// Whether a delivery with `properties` has retries left.
+    fn can_retry(&self, properties: &BasicProperties) -> bool;
+
+    /// Schedules another attempt of a delivery, which can then be acked.
+    /// Returns false when the broker refused it.
+    fn schedule(
+        &self,
+        payload: &[u8],
+        properties: &BasicProperties,
+    ) -> impl Future<Output = lapin::Result<bool>> + Send;
+}
+
+/// Publishes retries to the retry queue.
+pub struct Retrier {
+    channel: Channel,
+    queue: String,
+    max_retries: u32,
+}
+
+impl Retrier {
+    pub fn new(channel: Channel, config: &AmqpSettings) -> Self {
+        Retrier {
+            channel,
+            queue: config.retry_queue.clone(),
+            max_retries: config.max_retries,
+        }
+    }
+}
+
+impl Retry for Retrier {
+    fn can_retry(&self, properties: &BasicProperties) -> bool {
+        retry_count(properties) < self.max_retries
+    }
+
+    async fn schedule(&self, payload: &[u8], properties: &BasicProperties) -> lapin::Result<bool> {
+        let retries = retry_count(properties) + 1;
+        tracing::warn!(
+            retries,
+            max_retries = self.max_retries,
+            "scheduling a retry"
+        );
+        let properties = with_retry_count(properties.clone(), retries);
+        publish_to_queue(&self.channel, &self.queue, payload, properties).await
+    }
+}
+
+/// Properties of a re-driven message: its retry count starts over.
+pub fn redrive_properties() -> BasicProperties {
+    let mut headers = FieldTable::default();
+    headers.insert(REDRIVEN_HEADER.into(), AMQPValue::Boolean(true));
+    with_retry_count(BasicProperties::default().with_headers(headers), 0)
+}
+
+pub fn is_redriven(properties: &BasicProperties) -> bool {
+    properties.headers().as_ref().is_some_and(|headers| {
+        matches!(
+            headers.inner().get(REDRIVEN_HEADER),
+            Some(AMQPValue::Boolean(true))
+        )
+    })
+}
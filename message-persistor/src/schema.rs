@@ -0,0 +1,89 @@
+//! Keeps the database schema in step with the persistor.
+//!
+//! The migrations of each backend are embedded in the binary and applied
+//! at startup. A database migrated by a newer persistor is refused rather
+//! than written to with a schema this build does not know.
+use crate::storage::Storage;
+use sqlx::migrate::{MigrateError, Migrator};
+
+#[derive(Debug)]
+pub enum SchemaError {
+    /// The database has migrations this build does not.
+    Newer {
+        database: i64,
+        supported: i64,
+    },
+    Migrate(MigrateError),
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::Newer {
+                database,
+                supported,
+            } => write!(
+                f,
+                "the database schema is at version {}, this build supports up to {}",
+                database, supported
+            ),
+            SchemaError::Migrate(e) => write!(f, "failed to migrate the database: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SchemaError::Newer { .. } => None,
+            SchemaError::Migrate(e) => Some(e),
+        }
+    }
+}
+
+impl From<MigrateError> for SchemaError {
+    fn from(e: MigrateError) -> Self {
+        SchemaError::Migrate(e)
+    }
+}
+
+/// Latest migration embedded in `migrator`.
+pub fn supported_version(migrator: &Migrator) -> i64 {
+    migrator
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Latest migration applied, None on a database never migrated.
+pub async fn version(storage: &impl Storage) -> Result<Option<i64>, MigrateError> {
+    Ok(storage.applied_migrations().await?.into_iter().max())
+}
+
+/// Fails when the database was migrated past what this build supports,
+/// returns its version otherwise.
+pub async fn check_version(storage: &impl Storage) -> Result<Option<i64>, SchemaError> {
+    let supported = supported_version(storage.migrator());
+    match version(storage).await? {
+        Some(database) if database > supported => Err(SchemaError::Newer {
+            database,
+            supported,
+        }),
+        current => Ok(current),
+    }
+}
+
+/// Applies the pending migrations once the version is checked.
+#[tracing::instrument(name = "migrate database", skip_all)]
+pub async fn migrate(storage: &impl Storage) -> Result<(), SchemaError> {
+    let current = check_version(storage).await?;
+    storage.migrate().await?;
+    tracing::info!(
+        from = ?current,
+        to = supported_version(storage.migrator()),
+        "database schema up to date"
+    );
+    Ok(())
+}
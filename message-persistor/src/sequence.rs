@@ -0,0 +1,81 @@
+//! Gap detection over the engine's event sequence numbers.
+//!
+//! Every event carries its position in the engine's event stream, gapless
+//! from 1 within a run of the engine, its epoch. The tracker remembers the
+//! last event persisted and tells whether the next one follows it, was
+//! already seen, or skips some.
+
+/// Position of an event in the stream, ordered by epoch then sequence.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub epoch: u64,
+    pub sequence: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceCheck {
+    /// The next event, or the first one seen since startup.
+    Next,
+    /// Already persisted, typically a redelivery.
+    Duplicate,
+    /// Events `from` to `to` (inclusive) of the event's epoch are missing
+    /// before it.
+    Gap { from: u64, to: u64 },
+    /// Unsequenced, from an engine predating sequence numbers, or from an
+    /// earlier run than the last event persisted. Its inserts are keyed on
+    /// its position, it does not move the tracker.
+    Untracked,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SequenceTracker {
+    last: Option<Position>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resumes after `last`, the last event persisted before a restart.
+    pub fn resuming_from(last: Option<Position>) -> Self {
+        SequenceTracker { last }
+    }
+
+    pub fn last(&self) -> Option<Position> {
+        self.last
+    }
+
+    pub fn check(&self, epoch: u64, sequence: u64) -> SequenceCheck {
+        if sequence == 0 {
+            return SequenceCheck::Untracked;
+        }
+        let Some(last) = self.last else {
+            return SequenceCheck::Next;
+        };
+        // A new run of the engine numbers its events from 1 again
+        let next = match epoch.cmp(&last.epoch) {
+            std::cmp::Ordering::Less => return SequenceCheck::Untracked,
+            std::cmp::Ordering::Equal => last.sequence + 1,
+            std::cmp::Ordering::Greater => 1,
+        };
+        match sequence {
+            s if s < next => SequenceCheck::Duplicate,
+            s if s == next => SequenceCheck::Next,
+            s => SequenceCheck::Gap {
+                from: next,
+                to: s - 1,
+            },
+        }
+    }
+
+    /// Records the event at `sequence` of `epoch` as handled, it must have
+    /// been checked as [`SequenceCheck::Next`] or be the end of a recovered
+    /// gap. The tracker never moves back.
+    pub fn advance(&mut self, epoch: u64, sequence: u64) {
+        let position = Position { epoch, sequence };
+        if sequence != 0 && self.last < Some(position) {
+            self.last = Some(position);
+        }
+    }
+}
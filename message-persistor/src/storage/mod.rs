@@ -0,0 +1,135 @@
+//! Where the persistor writes, SQLite or Postgres as set by
+//! `database.backend`.
+//!
+//! Events are applied through an [`EventTx`], quarantined messages go
+//! straight through the [`Storage`]. Both backends hold the same schema,
+//! each with its own migrations.
+pub mod postgres;
+pub mod sqlite;
+
+use crate::candles::Candle;
+use crate::messages::trading::{OrderCancelled, OrderRejected};
+use crate::persistence::{EventMeta, NewOrder, NewTrade};
+use crate::quarantine::{NewQuarantined, Quarantined};
+use crate::sequence::Position;
+use sqlx::migrate::{MigrateError, Migrator};
+use std::future::Future;
+
+pub use postgres::PostgresStorage;
+pub use sqlite::SqliteStorage;
+
+pub trait Storage: Clone + Send + Sync + 'static {
+    type Tx: EventTx;
+
+    /// Opens a transaction to apply events in.
+    fn begin(&self) -> impl Future<Output = Result<Self::Tx, sqlx::Error>> + Send;
+
+    /// Migrations embedded for this backend.
+    fn migrator(&self) -> &'static Migrator;
+
+    /// Versions of the migrations applied, see [`crate::schema`].
+    fn applied_migrations(&self) -> impl Future<Output = Result<Vec<i64>, MigrateError>> + Send;
+
+    /// Applies the pending migrations.
+    fn migrate(&self) -> impl Future<Output = Result<(), MigrateError>> + Send;
+
+    /// Whether the database answers, for the readiness probe.
+    fn ping(&self) -> impl Future<Output = bool> + Send;
+
+    /// Position of the last event applied, see [`EventTx::set_checkpoint`].
+    fn checkpoint(&self) -> impl Future<Output = Result<Option<Position>, sqlx::Error>> + Send;
+
+    /// Returns the id of the quarantined message.
+    fn quarantine(
+        &self,
+        message: NewQuarantined,
+    ) -> impl Future<Output = Result<i64, sqlx::Error>> + Send;
+
+    /// Quarantined messages, oldest first, optionally including re-driven
+    /// ones.
+    fn list_quarantined(
+        &self,
+        include_redriven: bool,
+    ) -> impl Future<Output = Result<Vec<Quarantined>, sqlx::Error>> + Send;
+
+    fn get_quarantined(
+        &self,
+        id: i64,
+    ) -> impl Future<Output = Result<Option<Quarantined>, sqlx::Error>> + Send;
+
+    fn mark_redriven(&self, id: i64) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    /// Returns whether the message existed.
+    fn delete_quarantined(&self, id: i64)
+    -> impl Future<Output = Result<bool, sqlx::Error>> + Send;
+}
+
+/// A transaction applying events, rolled back when dropped uncommitted.
+///
+/// Applying an event twice changes nothing, the insert methods return
+/// false when it was applied already.
+pub trait EventTx: Send {
+    /// Position of the last event applied, if any.
+    fn checkpoint(&mut self) -> impl Future<Output = Result<Option<Position>, sqlx::Error>> + Send;
+
+    /// Records `position` as the last applied. The checkpoint never moves
+    /// back: a position behind it, from an older engine run or a
+    /// redelivery, leaves it as is.
+    fn set_checkpoint(
+        &mut self,
+        position: Position,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    fn insert_order(
+        &mut self,
+        new_order: NewOrder,
+    ) -> impl Future<Output = Result<bool, sqlx::Error>> + Send;
+
+    /// Inserts the trade and fills both of its orders.
+    fn insert_trade(
+        &mut self,
+        new_trade: NewTrade,
+    ) -> impl Future<Output = Result<bool, sqlx::Error>> + Send;
+
+    /// Returns false when the order is cancelled already, or unknown.
+    fn cancel_order(
+        &mut self,
+        cancelled: OrderCancelled,
+        meta: EventMeta,
+    ) -> impl Future<Output = Result<bool, sqlx::Error>> + Send;
+
+    fn record_rejection(
+        &mut self,
+        rejected: OrderRejected,
+        meta: EventMeta,
+    ) -> impl Future<Output = Result<bool, sqlx::Error>> + Send;
+
+    /// Merges `candle` into the stored one with its key, see
+    /// [`Candle::merge`], and returns the result.
+    fn upsert_candle(
+        &mut self,
+        candle: &Candle,
+    ) -> impl Future<Output = Result<Candle, sqlx::Error>> + Send;
+
+    /// Returns how many candles there were.
+    fn delete_candles(&mut self) -> impl Future<Output = Result<u64, sqlx::Error>> + Send;
+
+    /// Up to `limit` trades with an id past `after_id` in id order, with
+    /// their id. Trades persisted before their price and timestamp were
+    /// published are left out.
+    fn trades_after(
+        &mut self,
+        after_id: i64,
+        limit: i64,
+    ) -> impl Future<Output = Result<Vec<(i64, NewTrade)>, sqlx::Error>> + Send;
+
+    /// Marks where [`EventTx::rollback_to_savepoint`] returns to, so one
+    /// failing event does not take the rest of the transaction with it.
+    fn savepoint(&mut self) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    fn release_savepoint(&mut self) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    fn rollback_to_savepoint(&mut self) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    fn commit(self) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+}
@@ -0,0 +1,465 @@
+//! Postgres storage, the schema is in `migrations/postgres/`.
+//!
+//! The queries are checked at runtime: the compile-time checked ones of
+//! the SQLite storage are checked against a single `DATABASE_URL`.
+use super::{EventTx, Storage};
+use crate::candles::Candle;
+use crate::messages::trading::{OrderCancelled, OrderRejected};
+use crate::persistence::{EventMeta, NewOrder, NewTrade};
+use crate::quarantine::{NewQuarantined, Quarantined};
+use crate::sequence::Position;
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use sqlx::{PgConnection, PgPool, Postgres, Row, Transaction};
+
+static MIGRATOR: Migrator = sqlx::migrate!("../migrations/postgres");
+
+#[derive(Clone)]
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    pub fn new(pool: PgPool) -> Self {
+        PostgresStorage { pool }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+}
+
+impl Storage for PostgresStorage {
+    type Tx = PostgresTx;
+
+    async fn begin(&self) -> Result<PostgresTx, sqlx::Error> {
+        Ok(PostgresTx(self.pool.begin().await?))
+    }
+
+    fn migrator(&self) -> &'static Migrator {
+        &MIGRATOR
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<i64>, MigrateError> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        let applied = conn.list_applied_migrations().await?;
+        Ok(applied
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect())
+    }
+
+    async fn migrate(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.pool).await
+    }
+
+    async fn ping(&self) -> bool {
+        sqlx::query("SELECT 1").execute(&self.pool).await.is_ok()
+    }
+
+    async fn checkpoint(&self) -> Result<Option<Position>, sqlx::Error> {
+        checkpoint(&mut *self.pool.acquire().await?).await
+    }
+
+    #[tracing::instrument(name = "quarantine message", skip_all, fields(reason = %message.reason))]
+    async fn quarantine(&self, message: NewQuarantined) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"INSERT INTO quarantine (payload, reason, retries, sequence, correlation_id)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id"#,
+        )
+        .bind(message.payload)
+        .bind(message.reason)
+        .bind(message.retries as i64)
+        .bind(message.sequence.map(|sequence| sequence as i64))
+        .bind(message.correlation_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn list_quarantined(
+        &self,
+        include_redriven: bool,
+    ) -> Result<Vec<Quarantined>, sqlx::Error> {
+        sqlx::query_as(
+            r#"SELECT id, payload, reason, retries, sequence, correlation_id, quarantined_at, redriven_at
+            FROM quarantine WHERE $1 OR redriven_at IS NULL ORDER BY id"#,
+        )
+        .bind(include_redriven)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn get_quarantined(&self, id: i64) -> Result<Option<Quarantined>, sqlx::Error> {
+        sqlx::query_as(
+            r#"SELECT id, payload, reason, retries, sequence, correlation_id, quarantined_at, redriven_at
+            FROM quarantine WHERE id = $1"#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn mark_redriven(&self, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"UPDATE quarantine SET redriven_at = EXTRACT(EPOCH FROM now())::BIGINT WHERE id = $1"#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_quarantined(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query(r#"DELETE FROM quarantine WHERE id = $1"#)
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(deleted > 0)
+    }
+}
+
+pub struct PostgresTx(Transaction<'static, Postgres>);
+
+impl EventTx for PostgresTx {
+    async fn checkpoint(&mut self) -> Result<Option<Position>, sqlx::Error> {
+        checkpoint(&mut self.0).await
+    }
+
+    async fn set_checkpoint(&mut self, position: Position) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"INSERT INTO persistor_checkpoint (id, epoch, last_sequence) VALUES (1, $1, $2)
+            ON CONFLICT (id) DO UPDATE SET epoch = excluded.epoch, last_sequence = excluded.last_sequence
+            WHERE (excluded.epoch, excluded.last_sequence)
+                > (persistor_checkpoint.epoch, persistor_checkpoint.last_sequence)"#,
+        )
+        .bind(position.epoch as i64)
+        .bind(position.sequence as i64)
+        .execute(&mut *self.0)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "insert order", skip_all, fields(order_id = new_order.order_id))]
+    async fn insert_order(&mut self, new_order: NewOrder) -> Result<bool, sqlx::Error> {
+        let meta = &new_order.meta;
+        let inserted = sqlx::query(
+            r#"INSERT INTO orders (order_id, user_id, client_order_id, base_currency, quote_currency, side, quantity, price, status, correlation_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'open', $9)
+            ON CONFLICT (order_id) DO NOTHING"#,
+        )
+        .bind(new_order.order_id)
+        .bind(new_order.user_id)
+        .bind(&new_order.client_order_id)
+        .bind(&new_order.base_currency)
+        .bind(&new_order.quote_currency)
+        .bind(new_order.side)
+        .bind(new_order.quantity)
+        .bind(new_order.price)
+        .bind(&meta.correlation_id)
+        .execute(&mut *self.0)
+        .await?
+        .rows_affected();
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            r#"INSERT INTO order_events (order_id, user_id, client_order_id, event, status, quantity, price, sequence, engine_timestamp_us, correlation_id, epoch)
+            VALUES ($1, $2, $3, 'accepted', 'open', $4, $5, $6, $7, $8, $9)"#,
+        )
+        .bind(new_order.order_id)
+        .bind(new_order.user_id)
+        .bind(&new_order.client_order_id)
+        .bind(new_order.quantity)
+        .bind(new_order.price)
+        .bind(meta.sequence)
+        .bind(meta.engine_timestamp_us)
+        .bind(&meta.correlation_id)
+        .bind(meta.epoch)
+        .execute(&mut *self.0)
+        .await?;
+        Ok(true)
+    }
+
+    #[tracing::instrument(
+        name = "insert trade",
+        skip_all,
+        fields(
+            maker_order_id = new_trade.maker_order_id,
+            taker_order_id = new_trade.taker_order_id
+        )
+    )]
+    async fn insert_trade(&mut self, new_trade: NewTrade) -> Result<bool, sqlx::Error> {
+        let meta = &new_trade.meta;
+        // Refused on receipt already, see `NewTrade::notional`
+        let notional = new_trade
+            .notional()
+            .ok_or_else(|| sqlx::Error::InvalidArgument("trade notional overflows".into()))?;
+        let inserted = sqlx::query(
+            r#"INSERT INTO trades (maker_order_id, taker_order_id, filled_qty, price, aggressor_side, base_currency, quote_currency, sequence, engine_timestamp_us, correlation_id, epoch)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (epoch, sequence) DO NOTHING"#,
+        )
+        .bind(new_trade.maker_order_id)
+        .bind(new_trade.taker_order_id)
+        .bind(new_trade.filled_qty)
+        .bind(new_trade.price)
+        .bind(new_trade.aggressor_side)
+        .bind(&new_trade.base_currency)
+        .bind(&new_trade.quote_currency)
+        .bind(meta.sequence)
+        .bind(meta.engine_timestamp_us)
+        .bind(&meta.correlation_id)
+        .bind(meta.epoch)
+        .execute(&mut *self.0)
+        .await?
+        .rows_affected();
+        // The fills went with the trade
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        for order_id in [new_trade.maker_order_id, new_trade.taker_order_id] {
+            fill_order(
+                &mut self.0,
+                order_id,
+                new_trade.filled_qty,
+                new_trade.price,
+                notional,
+                meta,
+            )
+            .await?;
+        }
+        Ok(true)
+    }
+
+    #[tracing::instrument(name = "cancel order", skip_all, fields(order_id = cancelled.order_id))]
+    async fn cancel_order(
+        &mut self,
+        cancelled: OrderCancelled,
+        meta: EventMeta,
+    ) -> Result<bool, sqlx::Error> {
+        let order_id = cancelled.order_id as i64;
+        let updated = sqlx::query(
+            r#"UPDATE orders SET status = 'cancelled' WHERE order_id = $1 AND status != 'cancelled'"#,
+        )
+        .bind(order_id)
+        .execute(&mut *self.0)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            tracing::warn!("cancelled order is not persisted or already cancelled");
+            return Ok(false);
+        }
+
+        sqlx::query(
+            r#"INSERT INTO order_events (order_id, user_id, client_order_id, event, status, sequence, engine_timestamp_us, correlation_id, epoch)
+            VALUES ($1, $2, $3, 'cancelled', 'cancelled', $4, $5, $6, $7)"#,
+        )
+        .bind(order_id)
+        .bind(cancelled.user_id as i64)
+        .bind(Some(cancelled.client_order_id).filter(|id| !id.is_empty()))
+        .bind(meta.sequence)
+        .bind(meta.engine_timestamp_us)
+        .bind(meta.correlation_id)
+        .bind(meta.epoch)
+        .execute(&mut *self.0)
+        .await?;
+        Ok(true)
+    }
+
+    #[tracing::instrument(name = "record rejection", skip_all, fields(user_id = rejected.user_id))]
+    async fn record_rejection(
+        &mut self,
+        rejected: OrderRejected,
+        meta: EventMeta,
+    ) -> Result<bool, sqlx::Error> {
+        let inserted = sqlx::query(
+            r#"INSERT INTO order_events (user_id, client_order_id, event, status, reason, sequence, engine_timestamp_us, correlation_id, epoch)
+            VALUES ($1, $2, 'rejected', 'rejected', $3, $4, $5, $6, $7)
+            ON CONFLICT (epoch, sequence) WHERE event = 'rejected' DO NOTHING"#,
+        )
+        .bind(rejected.user_id as i64)
+        .bind(Some(rejected.client_order_id).filter(|id| !id.is_empty()))
+        .bind(rejected.reason)
+        .bind(meta.sequence)
+        .bind(meta.engine_timestamp_us)
+        .bind(meta.correlation_id)
+        .bind(meta.epoch)
+        .execute(&mut *self.0)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    async fn upsert_candle(&mut self, candle: &Candle) -> Result<Candle, sqlx::Error> {
+        let row = sqlx::query(
+            r#"INSERT INTO candles (base_currency, quote_currency, interval_seconds, open_time_us, open, high, low, close, volume, notional, trade_count)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (base_currency, quote_currency, interval_seconds, open_time_us) DO UPDATE SET
+                high = GREATEST(candles.high, excluded.high),
+                low = LEAST(candles.low, excluded.low),
+                close = excluded.close,
+                volume = candles.volume + excluded.volume,
+                notional = candles.notional + excluded.notional,
+                trade_count = candles.trade_count + excluded.trade_count
+            RETURNING open, high, low, close, volume, notional, trade_count"#,
+        )
+        .bind(&candle.base_currency)
+        .bind(&candle.quote_currency)
+        .bind(candle.interval.seconds())
+        .bind(candle.open_time_us)
+        .bind(candle.open)
+        .bind(candle.high)
+        .bind(candle.low)
+        .bind(candle.close)
+        .bind(candle.volume)
+        .bind(candle.notional)
+        .bind(candle.trade_count)
+        .fetch_one(&mut *self.0)
+        .await?;
+        Ok(Candle {
+            open: row.try_get("open")?,
+            high: row.try_get("high")?,
+            low: row.try_get("low")?,
+            close: row.try_get("close")?,
+            volume: row.try_get("volume")?,
+            notional: row.try_get("notional")?,
+            trade_count: row.try_get("trade_count")?,
+            ..candle.clone()
+        })
+    }
+
+    async fn delete_candles(&mut self) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query(r#"DELETE FROM candles"#)
+            .execute(&mut *self.0)
+            .await?
+            .rows_affected();
+        Ok(deleted)
+    }
+
+    async fn trades_after(
+        &mut self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<(i64, NewTrade)>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"SELECT id, maker_order_id, taker_order_id, filled_qty, price, aggressor_side,
+                base_currency, quote_currency, sequence, epoch, engine_timestamp_us, correlation_id
+            FROM trades
+            WHERE id > $1 AND filled_qty IS NOT NULL AND price IS NOT NULL AND base_currency IS NOT NULL
+                AND quote_currency IS NOT NULL AND engine_timestamp_us IS NOT NULL
+            ORDER BY id LIMIT $2"#,
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&mut *self.0)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                let trade = NewTrade {
+                    maker_order_id: row.try_get("maker_order_id")?,
+                    taker_order_id: row.try_get("taker_order_id")?,
+                    filled_qty: row.try_get("filled_qty")?,
+                    price: row.try_get("price")?,
+                    aggressor_side: row
+                        .try_get::<Option<i32>, _>("aggressor_side")?
+                        .unwrap_or_default(),
+                    base_currency: row.try_get("base_currency")?,
+                    quote_currency: row.try_get("quote_currency")?,
+                    meta: EventMeta {
+                        sequence: row.try_get("sequence")?,
+                        epoch: row.try_get("epoch")?,
+                        engine_timestamp_us: row.try_get("engine_timestamp_us")?,
+                        correlation_id: row.try_get("correlation_id")?,
+                    },
+                };
+                Ok((row.try_get("id")?, trade))
+            })
+            .collect()
+    }
+
+    async fn savepoint(&mut self) -> Result<(), sqlx::Error> {
+        sqlx::query("SAVEPOINT event").execute(&mut *self.0).await?;
+        Ok(())
+    }
+
+    async fn release_savepoint(&mut self) -> Result<(), sqlx::Error> {
+        sqlx::query("RELEASE SAVEPOINT event")
+            .execute(&mut *self.0)
+            .await?;
+        Ok(())
+    }
+
+    async fn rollback_to_savepoint(&mut self) -> Result<(), sqlx::Error> {
+        sqlx::query("ROLLBACK TO SAVEPOINT event")
+            .execute(&mut *self.0)
+            .await?;
+        self.release_savepoint().await
+    }
+
+    async fn commit(self) -> Result<(), sqlx::Error> {
+        self.0.commit().await
+    }
+}
+
+async fn checkpoint(conn: &mut PgConnection) -> Result<Option<Position>, sqlx::Error> {
+    let last: Option<(i64, i64)> =
+        sqlx::query_as(r#"SELECT epoch, last_sequence FROM persistor_checkpoint WHERE id = 1"#)
+            .fetch_optional(&mut *conn)
+            .await?;
+    Ok(last.map(|(epoch, sequence)| Position {
+        epoch: epoch as u64,
+        sequence: sequence as u64,
+    }))
+}
+
+async fn fill_order(
+    conn: &mut PgConnection,
+    order_id: i64,
+    quantity: i64,
+    price: i64,
+    notional: i64,
+    meta: &EventMeta,
+) -> Result<(), sqlx::Error> {
+    // The right-hand sides all read the row as it was before the update.
+    // The notional sum stays within the bound the gateway puts on the price
+    // times quantity of each order, the average is taken in floating point
+    let filled = sqlx::query(
+        r#"UPDATE orders SET
+            filled_quantity = filled_quantity + $2,
+            filled_notional = filled_notional + $3,
+            average_price = (CAST(filled_notional AS DOUBLE PRECISION) + $3) / (filled_quantity + $2),
+            status = CASE WHEN filled_quantity + $2 >= quantity THEN 'filled' ELSE 'partially_filled' END
+        WHERE order_id = $1
+        RETURNING user_id, client_order_id, status"#,
+    )
+    .bind(order_id)
+    .bind(quantity)
+    .bind(notional)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(filled) = filled else {
+        tracing::warn!(order_id, "filled order is not persisted");
+        return Ok(());
+    };
+
+    sqlx::query(
+        r#"INSERT INTO order_events (order_id, user_id, client_order_id, event, status, quantity, price, sequence, engine_timestamp_us, correlation_id, epoch)
+        VALUES ($1, $2, $3, 'fill', $4, $5, $6, $7, $8, $9, $10)"#,
+    )
+    .bind(order_id)
+    .bind(filled.try_get::<Option<i64>, _>("user_id")?)
+    .bind(filled.try_get::<Option<String>, _>("client_order_id")?)
+    .bind(filled.try_get::<String, _>("status")?)
+    .bind(quantity)
+    .bind(price)
+    .bind(meta.sequence)
+    .bind(meta.engine_timestamp_us)
+    .bind(&meta.correlation_id)
+    .bind(meta.epoch)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
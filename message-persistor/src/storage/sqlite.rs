@@ -0,0 +1,556 @@
+//! SQLite storage, the schema is in `migrations/`.
+use super::{EventTx, Storage};
+use crate::candles::Candle;
+use crate::messages::trading::{OrderCancelled, OrderRejected};
+use crate::persistence::{EventMeta, NewOrder, NewTrade};
+use crate::quarantine::{NewQuarantined, Quarantined};
+use crate::sequence::Position;
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use sqlx::{Sqlite, SqliteConnection, SqlitePool, Transaction};
+
+static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+#[derive(Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: SqlitePool) -> Self {
+        SqliteStorage { pool }
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+}
+
+impl Storage for SqliteStorage {
+    type Tx = SqliteTx;
+
+    async fn begin(&self) -> Result<SqliteTx, sqlx::Error> {
+        Ok(SqliteTx(self.pool.begin().await?))
+    }
+
+    fn migrator(&self) -> &'static Migrator {
+        &MIGRATOR
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<i64>, MigrateError> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        let applied = conn.list_applied_migrations().await?;
+        Ok(applied
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect())
+    }
+
+    async fn migrate(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.pool).await
+    }
+
+    async fn ping(&self) -> bool {
+        sqlx::query("SELECT 1").execute(&self.pool).await.is_ok()
+    }
+
+    async fn checkpoint(&self) -> Result<Option<Position>, sqlx::Error> {
+        checkpoint(&mut *self.pool.acquire().await?).await
+    }
+
+    async fn quarantine(&self, message: NewQuarantined) -> Result<i64, sqlx::Error> {
+        quarantine(&self.pool, message).await
+    }
+
+    async fn list_quarantined(
+        &self,
+        include_redriven: bool,
+    ) -> Result<Vec<Quarantined>, sqlx::Error> {
+        list_quarantined(&self.pool, include_redriven).await
+    }
+
+    async fn get_quarantined(&self, id: i64) -> Result<Option<Quarantined>, sqlx::Error> {
+        get_quarantined(&self.pool, id).await
+    }
+
+    async fn mark_redriven(&self, id: i64) -> Result<(), sqlx::Error> {
+        mark_redriven(&self.pool, id).await
+    }
+
+    async fn delete_quarantined(&self, id: i64) -> Result<bool, sqlx::Error> {
+        delete_quarantined(&self.pool, id).await
+    }
+}
+
+pub struct SqliteTx(Transaction<'static, Sqlite>);
+
+impl EventTx for SqliteTx {
+    async fn checkpoint(&mut self) -> Result<Option<Position>, sqlx::Error> {
+        checkpoint(&mut self.0).await
+    }
+
+    async fn set_checkpoint(&mut self, position: Position) -> Result<(), sqlx::Error> {
+        set_checkpoint(&mut self.0, position).await
+    }
+
+    async fn insert_order(&mut self, new_order: NewOrder) -> Result<bool, sqlx::Error> {
+        insert_order(&mut self.0, new_order).await
+    }
+
+    async fn insert_trade(&mut self, new_trade: NewTrade) -> Result<bool, sqlx::Error> {
+        insert_trade(&mut self.0, new_trade).await
+    }
+
+    async fn cancel_order(
+        &mut self,
+        cancelled: OrderCancelled,
+        meta: EventMeta,
+    ) -> Result<bool, sqlx::Error> {
+        cancel_order(&mut self.0, cancelled, meta).await
+    }
+
+    async fn record_rejection(
+        &mut self,
+        rejected: OrderRejected,
+        meta: EventMeta,
+    ) -> Result<bool, sqlx::Error> {
+        record_rejection(&mut self.0, rejected, meta).await
+    }
+
+    async fn upsert_candle(&mut self, candle: &Candle) -> Result<Candle, sqlx::Error> {
+        upsert_candle(&mut self.0, candle).await
+    }
+
+    async fn delete_candles(&mut self) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(r#"DELETE FROM candles"#)
+            .execute(&mut *self.0)
+            .await?
+            .rows_affected();
+        Ok(deleted)
+    }
+
+    async fn trades_after(
+        &mut self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<(i64, NewTrade)>, sqlx::Error> {
+        trades_after(&mut self.0, after_id, limit).await
+    }
+
+    async fn savepoint(&mut self) -> Result<(), sqlx::Error> {
+        sqlx::query("SAVEPOINT event").execute(&mut *self.0).await?;
+        Ok(())
+    }
+
+    async fn release_savepoint(&mut self) -> Result<(), sqlx::Error> {
+        sqlx::query("RELEASE SAVEPOINT event")
+            .execute(&mut *self.0)
+            .await?;
+        Ok(())
+    }
+
+    async fn rollback_to_savepoint(&mut self) -> Result<(), sqlx::Error> {
+        sqlx::query("ROLLBACK TO SAVEPOINT event")
+            .execute(&mut *self.0)
+            .await?;
+        self.release_savepoint().await
+    }
+
+    async fn commit(self) -> Result<(), sqlx::Error> {
+        self.0.commit().await
+    }
+}
+
+/// Position of the last event applied, if any.
+async fn checkpoint(conn: &mut SqliteConnection) -> Result<Option<Position>, sqlx::Error> {
+    let last =
+        sqlx::query!(r#"SELECT epoch, last_sequence FROM persistor_checkpoint WHERE id = 1"#)
+            .fetch_optional(&mut *conn)
+            .await?;
+    Ok(last.map(|last| Position {
+        epoch: last.epoch as u64,
+        sequence: last.last_sequence as u64,
+    }))
+}
+
+/// Records `position` as the last applied, unless it is behind the
+/// checkpoint.
+async fn set_checkpoint(
+    conn: &mut SqliteConnection,
+    position: Position,
+) -> Result<(), sqlx::Error> {
+    let epoch = position.epoch as i64;
+    let sequence = position.sequence as i64;
+    sqlx::query!(
+        r#"INSERT INTO persistor_checkpoint (id, epoch, last_sequence) VALUES (1, $1, $2)
+        ON CONFLICT (id) DO UPDATE SET epoch = excluded.epoch, last_sequence = excluded.last_sequence
+        WHERE (excluded.epoch, excluded.last_sequence)
+            > (persistor_checkpoint.epoch, persistor_checkpoint.last_sequence)"#,
+        epoch,
+        sequence
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "insert order", skip_all, fields(order_id = new_order.order_id))]
+async fn insert_order(
+    conn: &mut SqliteConnection,
+    new_order: NewOrder,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"INSERT INTO orders (order_id, user_id, client_order_id, base_currency, quote_currency, side, quantity, price, status, correlation_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'open', $9)
+        ON CONFLICT (order_id) DO NOTHING"#,
+        new_order.order_id,
+        new_order.user_id,
+        new_order.client_order_id,
+        new_order.base_currency,
+        new_order.quote_currency,
+        new_order.side,
+        new_order.quantity,
+        new_order.price,
+        new_order.meta.correlation_id,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    if inserted == 0 {
+        return Ok(false);
+    }
+
+    let meta = &new_order.meta;
+    sqlx::query!(
+        r#"INSERT INTO order_events (order_id, user_id, client_order_id, event, status, quantity, price, sequence, engine_timestamp_us, correlation_id, epoch)
+        VALUES ($1, $2, $3, 'accepted', 'open', $4, $5, $6, $7, $8, $9)"#,
+        new_order.order_id,
+        new_order.user_id,
+        new_order.client_order_id,
+        new_order.quantity,
+        new_order.price,
+        meta.sequence,
+        meta.engine_timestamp_us,
+        meta.correlation_id,
+        meta.epoch,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(true)
+}
+
+/// Inserts the trade and fills both of its orders.
+#[tracing::instrument(
+    name = "insert trade",
+    skip_all,
+    fields(
+        maker_order_id = new_trade.maker_order_id,
+        taker_order_id = new_trade.taker_order_id
+    )
+)]
+async fn insert_trade(
+    conn: &mut SqliteConnection,
+    new_trade: NewTrade,
+) -> Result<bool, sqlx::Error> {
+    let meta = &new_trade.meta;
+    // Refused on receipt already, see `NewTrade::notional`
+    let notional = new_trade
+        .notional()
+        .ok_or_else(|| sqlx::Error::InvalidArgument("trade notional overflows".into()))?;
+    let inserted = sqlx::query!(
+        r#"INSERT INTO trades (maker_order_id, taker_order_id, filled_qty, price, aggressor_side, base_currency, quote_currency, sequence, engine_timestamp_us, correlation_id, epoch)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (epoch, sequence) DO NOTHING"#,
+        new_trade.maker_order_id,
+        new_trade.taker_order_id,
+        new_trade.filled_qty,
+        new_trade.price,
+        new_trade.aggressor_side,
+        new_trade.base_currency,
+        new_trade.quote_currency,
+        meta.sequence,
+        meta.engine_timestamp_us,
+        meta.correlation_id,
+        meta.epoch,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    // The fills went with the trade
+    if inserted == 0 {
+        return Ok(false);
+    }
+
+    for order_id in [new_trade.maker_order_id, new_trade.taker_order_id] {
+        fill_order(
+            conn,
+            order_id,
+            new_trade.filled_qty,
+            new_trade.price,
+            notional,
+            meta,
+        )
+        .await?;
+    }
+    Ok(true)
+}
+
+async fn fill_order(
+    conn: &mut SqliteConnection,
+    order_id: i64,
+    quantity: i64,
+    price: i64,
+    notional: i64,
+    meta: &EventMeta,
+) -> Result<(), sqlx::Error> {
+    // The right-hand sides all read the row as it was before the update.
+    // The notional sum stays within the bound the gateway puts on the price
+    // times quantity of each order, the average is taken in floating point
+    let filled = sqlx::query!(
+        r#"UPDATE orders SET
+            filled_quantity = filled_quantity + $2,
+            filled_notional = filled_notional + $3,
+            average_price = (CAST(filled_notional AS REAL) + $3) / (filled_quantity + $2),
+            status = CASE WHEN filled_quantity + $2 >= quantity THEN 'filled' ELSE 'partially_filled' END
+        WHERE order_id = $1
+        RETURNING user_id, client_order_id, status"#,
+        order_id,
+        quantity,
+        notional,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(filled) = filled else {
+        tracing::warn!(order_id, "filled order is not persisted");
+        return Ok(());
+    };
+
+    sqlx::query!(
+        r#"INSERT INTO order_events (order_id, user_id, client_order_id, event, status, quantity, price, sequence, engine_timestamp_us, correlation_id, epoch)
+        VALUES ($1, $2, $3, 'fill', $4, $5, $6, $7, $8, $9, $10)"#,
+        order_id,
+        filled.user_id,
+        filled.client_order_id,
+        filled.status,
+        quantity,
+        price,
+        meta.sequence,
+        meta.engine_timestamp_us,
+        meta.correlation_id,
+        meta.epoch,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Returns false when the order is cancelled already, or unknown.
+#[tracing::instrument(name = "cancel order", skip_all, fields(order_id = cancelled.order_id))]
+async fn cancel_order(
+    conn: &mut SqliteConnection,
+    cancelled: OrderCancelled,
+    meta: EventMeta,
+) -> Result<bool, sqlx::Error> {
+    let order_id = cancelled.order_id as i64;
+    let user_id = cancelled.user_id as i64;
+    let client_order_id = Some(cancelled.client_order_id).filter(|id| !id.is_empty());
+    let updated = sqlx::query!(
+        r#"UPDATE orders SET status = 'cancelled' WHERE order_id = $1 AND status != 'cancelled'"#,
+        order_id
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        tracing::warn!("cancelled order is not persisted or already cancelled");
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"INSERT INTO order_events (order_id, user_id, client_order_id, event, status, sequence, engine_timestamp_us, correlation_id, epoch)
+        VALUES ($1, $2, $3, 'cancelled', 'cancelled', $4, $5, $6, $7)"#,
+        order_id,
+        user_id,
+        client_order_id,
+        meta.sequence,
+        meta.engine_timestamp_us,
+        meta.correlation_id,
+        meta.epoch,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(true)
+}
+
+#[tracing::instrument(name = "record rejection", skip_all, fields(user_id = rejected.user_id))]
+async fn record_rejection(
+    conn: &mut SqliteConnection,
+    rejected: OrderRejected,
+    meta: EventMeta,
+) -> Result<bool, sqlx::Error> {
+    let user_id = rejected.user_id as i64;
+    let client_order_id = Some(rejected.client_order_id).filter(|id| !id.is_empty());
+    let inserted = sqlx::query!(
+        r#"INSERT INTO order_events (user_id, client_order_id, event, status, reason, sequence, engine_timestamp_us, correlation_id, epoch)
+        VALUES ($1, $2, 'rejected', 'rejected', $3, $4, $5, $6, $7)
+        ON CONFLICT (epoch, sequence) WHERE event = 'rejected' DO NOTHING"#,
+        user_id,
+        client_order_id,
+        rejected.reason,
+        meta.sequence,
+        meta.engine_timestamp_us,
+        meta.correlation_id,
+        meta.epoch,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    Ok(inserted > 0)
+}
+
+async fn upsert_candle(
+    conn: &mut SqliteConnection,
+    candle: &Candle,
+) -> Result<Candle, sqlx::Error> {
+    let interval_seconds = candle.interval.seconds();
+    let stored = sqlx::query!(
+        r#"INSERT INTO candles (base_currency, quote_currency, interval_seconds, open_time_us, open, high, low, close, volume, notional, trade_count)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (base_currency, quote_currency, interval_seconds, open_time_us) DO UPDATE SET
+            high = MAX(high, excluded.high),
+            low = MIN(low, excluded.low),
+            close = excluded.close,
+            volume = volume + excluded.volume,
+            notional = notional + excluded.notional,
+            trade_count = trade_count + excluded.trade_count
+        RETURNING open, high, low, close, volume, notional, trade_count"#,
+        candle.base_currency,
+        candle.quote_currency,
+        interval_seconds,
+        candle.open_time_us,
+        candle.open,
+        candle.high,
+        candle.low,
+        candle.close,
+        candle.volume,
+        candle.notional,
+        candle.trade_count,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(Candle {
+        open: stored.open,
+        high: stored.high,
+        low: stored.low,
+        close: stored.close,
+        volume: stored.volume,
+        notional: stored.notional,
+        trade_count: stored.trade_count,
+        ..candle.clone()
+    })
+}
+
+async fn trades_after(
+    conn: &mut SqliteConnection,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<(i64, NewTrade)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT id as "id!", maker_order_id, taker_order_id, filled_qty as "filled_qty!", price as "price!",
+            aggressor_side, base_currency as "base_currency!", quote_currency as "quote_currency!",
+            sequence, epoch, engine_timestamp_us, correlation_id
+        FROM trades
+        WHERE id > $1 AND filled_qty IS NOT NULL AND price IS NOT NULL AND base_currency IS NOT NULL
+            AND quote_currency IS NOT NULL AND engine_timestamp_us IS NOT NULL
+        ORDER BY id LIMIT $2"#,
+        after_id,
+        limit,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let trade = NewTrade {
+                maker_order_id: row.maker_order_id,
+                taker_order_id: row.taker_order_id,
+                filled_qty: row.filled_qty,
+                price: row.price,
+                aggressor_side: row.aggressor_side.unwrap_or_default() as i32,
+                base_currency: row.base_currency,
+                quote_currency: row.quote_currency,
+                meta: EventMeta {
+                    sequence: row.sequence,
+                    epoch: row.epoch,
+                    engine_timestamp_us: row.engine_timestamp_us,
+                    correlation_id: row.correlation_id,
+                },
+            };
+            (row.id, trade)
+        })
+        .collect())
+}
+
+#[tracing::instrument(name = "quarantine message", skip_all, fields(reason = %message.reason))]
+async fn quarantine(pool: &SqlitePool, message: NewQuarantined) -> Result<i64, sqlx::Error> {
+    let sequence = message.sequence.map(|sequence| sequence as i64);
+    let id = sqlx::query!(
+        r#"INSERT INTO quarantine (payload, reason, retries, sequence, correlation_id) VALUES ($1, $2, $3, $4, $5)"#,
+        message.payload,
+        message.reason,
+        message.retries,
+        sequence,
+        message.correlation_id,
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    Ok(id)
+}
+
+/// Quarantined messages, oldest first, optionally including re-driven ones.
+async fn list_quarantined(
+    pool: &SqlitePool,
+    include_redriven: bool,
+) -> Result<Vec<Quarantined>, sqlx::Error> {
+    sqlx::query_as!(
+        Quarantined,
+        r#"SELECT id as "id!", payload, reason, retries, sequence, correlation_id, quarantined_at, redriven_at
+        FROM quarantine WHERE $1 OR redriven_at IS NULL ORDER BY id"#,
+        include_redriven,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+async fn get_quarantined(pool: &SqlitePool, id: i64) -> Result<Option<Quarantined>, sqlx::Error> {
+    sqlx::query_as!(
+        Quarantined,
+        r#"SELECT id as "id!", payload, reason, retries, sequence, correlation_id, quarantined_at, redriven_at
+        FROM quarantine WHERE id = $1"#,
+        id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+async fn mark_redriven(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE quarantine SET redriven_at = strftime('%s', 'now') WHERE id = $1"#,
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Returns whether the message existed.
+async fn delete_quarantined(pool: &SqlitePool, id: i64) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query!(r#"DELETE FROM quarantine WHERE id = $1"#, id)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    Ok(deleted > 0)
+}
@@ -0,0 +1,65 @@
+//! The persistor's queues on top of the shared [`event_bus::topology`]:
+//!
+//! ```text
+//!              {channel} ◀──┐
+//!                  │        │ retry_delay_ms
+//!           failed └──▶ {retry_queue}
+//! ```
+//!
+//! and `{market_data_exchange}`, the topic exchange candles are published
+//! to. The engine declares neither.
+use crate::configuration::AmqpSettings;
+use event_bus::topology::{self, StreamTopology};
+use lapin::{
+    Channel, ExchangeKind,
+    options::{ExchangeDeclareOptions, QueueDeclareOptions},
+    types::{AMQPValue, FieldTable},
+};
+
+pub async fn declare(channel: &Channel, config: &AmqpSettings) -> lapin::Result<()> {
+    let stream = StreamTopology {
+        exchange: &config.exchange,
+        channel: &config.channel,
+        bindings: &config.bindings,
+        dead_letter_exchange: &config.dead_letter_exchange,
+        dead_letter_queue: &config.dead_letter_queue,
+    };
+    topology::declare(channel, &stream).await?;
+    channel
+        .exchange_declare(
+            &config.market_data_exchange,
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    // Expired messages go back to `channel` through the default exchange
+    let mut arguments = FieldTable::default();
+    arguments.insert(
+        "x-message-ttl".into(),
+        AMQPValue::LongUInt(config.retry_delay_ms),
+    );
+    arguments.insert(
+        "x-dead-letter-exchange".into(),
+        AMQPValue::LongString("".into()),
+    );
+    arguments.insert(
+        "x-dead-letter-routing-key".into(),
+        AMQPValue::LongString(config.channel.as_str().into()),
+    );
+    channel
+        .queue_declare(
+            &config.retry_queue,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            arguments,
+        )
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use crate::helpers::{Inspect, sqlite_storage, trade};
+use lapin::BasicProperties;
+use lapin::options::{BasicAckOptions, BasicNackOptions};
+use message_persistor::amqp_receiver::{Batch, Settle};
+use message_persistor::configuration::EngineSettings;
+use message_persistor::messages::trading::{
+    OrderAccepted, Side, WireMessage, wire_message::Payload,
+};
+use message_persistor::metrics::Metrics;
+use message_persistor::retransmit::RetransmitClient;
+use message_persistor::retry::Retry;
+use message_persistor::sequence::{Position, SequenceCheck, SequenceTracker};
+use message_persistor::storage::{SqliteStorage, Storage};
+use prost::Message;
+use std::sync::{Arc, Mutex};
+
+const EPOCH: u64 = 1_700_000_000_000_000;
+
+/// How a delivery was settled: its tag, and whether it was acked with
+/// `multiple`, acked alone or nacked.
+#[derive(Debug, PartialEq)]
+enum Settled {
+    Ack(u64),
+    AckMultiple(u64),
+    Nack(u64),
+}
+
+/// A delivery recording how it is settled in a log shared with the others.
+struct FakeDelivery {
+    tag: u64,
+    data: Vec<u8>,
+    properties: BasicProperties,
+    log: Arc<Mutex<Vec<Settled>>>,
+}
+
+impl Settle for FakeDelivery {
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn properties(&self) -> &BasicProperties {
+        &self.properties
+    }
+
+    fn delivery_tag(&self) -> u64 {
+        self.tag
+    }
+
+    async fn ack(&self, options: BasicAckOptions) -> lapin::Result<bool> {
+        let settled = if options.multiple {
+            Settled::AckMultiple(self.tag)
+        } else {
+            Settled::Ack(self.tag)
+        };
+        self.log.lock().unwrap().push(settled);
+        Ok(true)
+    }
+
+    async fn nack(&self, _: BasicNackOptions) -> lapin::Result<bool> {
+        self.log.lock().unwrap().push(Settled::Nack(self.tag));
+        Ok(true)
+    }
+}
+
+/// Accepts every retry, recording the payloads scheduled.
+#[derive(Default)]
+struct FakeRetrier {
+    scheduled: Mutex<Vec<Vec<u8>>>,
+}
+
+impl Retry for FakeRetrier {
+    fn can_retry(&self, _: &BasicProperties) -> bool {
+        true
+    }
+
+    async fn schedule(&self, payload: &[u8], _: &BasicProperties) -> lapin::Result<bool> {
+        self.scheduled.lock().unwrap().push(payload.to_vec());
+        Ok(true)
+    }
+}
+
+fn accepted(order_id: u64, side: Side) -> Payload {
+    Payload::OrderAccepted(OrderAccepted {
+        order_id,
+        user_id: order_id * 10,
+        side: side.into(),
+        price: 100,
+        quantity: 10,
+        base_currency: "BTC".into(),
+        quote_currency: "USD".into(),
+        client_order_id: format!("client-{}", order_id),
+    })
+}
+
+/// Drives a batch the way the receiver does, against an engine that is
+/// never asked for a retransmission.
+struct Harness {
+    storage: SqliteStorage,
+    metrics: Metrics,
+    sequence: SequenceTracker,
+    engine: RetransmitClient,
+    retrier: FakeRetrier,
+    log: Arc<Mutex<Vec<Settled>>>,
+    batch: Batch<SqliteStorage, FakeDelivery>,
+}
+
+impl Harness {
+    async fn new() -> Self {
+        let sequence = SequenceTracker::new();
+        Harness {
+            storage: sqlite_storage().await,
+            metrics: Metrics::new(),
+            sequence,
+            engine: RetransmitClient::new(&EngineSettings {
+                host: "127.0.0.1".into(),
+                port: 1,
+                retransmit_timeout_ms: 100,
+            }),
+            retrier: FakeRetrier::default(),
+            log: Arc::default(),
+            batch: Batch::new(sequence),
+        }
+    }
+
+    /// Stages the event at `sequence` as the delivery tagged `sequence`.
+    async fn stage(&mut self, sequence: u64, payload: Payload) {
+        let data = WireMessage {
+            payload: Some(payload),
+            sequence,
+            epoch: EPOCH,
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let delivery = FakeDelivery {
+            tag: sequence,
+            data,
+            properties: BasicProperties::default(),
+            log: self.log.clone(),
+        };
+        self.batch
+            .stage(
+                &self.storage,
+                &self.metrics,
+                &mut self.sequence,
+                &self.engine,
+                &self.retrier,
+                delivery,
+            )
+            .await;
+    }
+
+    async fn flush(&mut self) {
+        self.batch
+            .flush(
+                &self.storage,
+                &self.metrics,
+                &mut self.sequence,
+                &self.retrier,
+            )
+            .await;
+    }
+
+    fn settled(&self) -> Vec<Settled> {
+        std::mem::take(&mut self.log.lock().unwrap())
+    }
+}
+
+fn at(sequence: u64) -> Option<Position> {
+    Some(Position {
+        epoch: EPOCH,
+        sequence,
+    })
+}
+
+#[tokio::test]
+async fn staged_events_are_committed_then_acked_together() {
+    let mut harness = Harness::new().await;
+    harness.stage(1, accepted(1, Side::Sell)).await;
+    harness.stage(2, accepted(2, Side::Buy)).await;
+    harness
+        .stage(3, Payload::TradeOccurred(trade(1, 2, 4, 100)))
+        .await;
+    assert_eq!(harness.settled(), []);
+
+    harness.flush().await;
+
+    assert_eq!(harness.settled(), [Settled::AckMultiple(3)]);
+    assert_eq!(harness.sequence.last(), at(3));
+    assert_eq!(harness.storage.count("orders").await, 2);
+    assert_eq!(harness.storage.count("trades").await, 1);
+    assert_eq!(harness.storage.checkpoint().await.unwrap(), at(3));
+}
+
+#[tokio::test]
+async fn a_failing_event_only_rolls_back_its_savepoint() {
+    let mut harness = Harness::new().await;
+    harness.stage(1, accepted(1, Side::Sell)).await;
+    // Neither order exists
+    harness
+        .stage(2, Payload::TradeOccurred(trade(98, 99, 1, 100)))
+        .await;
+    harness.stage(3, accepted(3, Side::Buy)).await;
+
+    harness.flush().await;
+
+    assert_eq!(harness.storage.count("orders").await, 2);
+    assert_eq!(harness.storage.count("trades").await, 0);
+    assert_eq!(harness.storage.count("quarantine").await, 1);
+    // Quarantined, the stream moves on without it
+    assert_eq!(harness.sequence.last(), at(3));
+}
+
+#[tokio::test]
+async fn a_failed_delivery_is_settled_before_the_batch_is_acked() {
+    let mut harness = Harness::new().await;
+    harness.stage(1, accepted(1, Side::Sell)).await;
+    harness
+        .stage(2, Payload::TradeOccurred(trade(98, 99, 1, 100)))
+        .await;
+    harness.stage(3, accepted(3, Side::Buy)).await;
+
+    harness.flush().await;
+
+    // The multiple ack of 3 covers 1, 2 was quarantined and acked first
+    assert_eq!(
+        harness.settled(),
+        [Settled::Ack(2), Settled::AckMultiple(3)]
+    );
+}
+
+#[tokio::test]
+async fn a_failed_commit_resets_the_tracker_and_retries_the_batch() {
+    let mut harness = Harness::new().await;
+    harness.stage(1, accepted(1, Side::Sell)).await;
+    harness.flush().await;
+    harness.settled();
+    // Every order event breaks a foreign key only checked on commit
+    sqlx::raw_sql(
+        "CREATE TABLE commit_blocker (
+            order_id INTEGER REFERENCES orders (order_id) DEFERRABLE INITIALLY DEFERRED
+        );
+        CREATE TRIGGER block_commits AFTER INSERT ON order_events
+        BEGIN
+            INSERT INTO commit_blocker VALUES (-1);
+        END;",
+    )
+    .execute(harness.storage.pool())
+    .await
+    .unwrap();
+
+    harness.stage(2, accepted(2, Side::Buy)).await;
+    harness.stage(3, accepted(3, Side::Buy)).await;
+    assert_eq!(harness.sequence.last(), at(3));
+    harness.flush().await;
+
+    assert_eq!(harness.sequence.last(), at(1));
+    assert_eq!(harness.retrier.scheduled.lock().unwrap().len(), 2);
+    // Scheduled for a retry, then acked
+    assert_eq!(harness.settled(), [Settled::Ack(2), Settled::Ack(3)]);
+    assert_eq!(harness.storage.count("orders").await, 1);
+    assert_eq!(harness.storage.checkpoint().await.unwrap(), at(1));
+    // Expecting the retried events again
+    assert_eq!(harness.sequence.check(EPOCH, 2), SequenceCheck::Next);
+}
+
+#[tokio::test]
+async fn a_trade_whose_notional_overflows_is_quarantined() {
+    let mut harness = Harness::new().await;
+    harness.stage(1, accepted(1, Side::Sell)).await;
+    harness.stage(2, accepted(2, Side::Buy)).await;
+    // 15000 BTC at 64250.15 USD
+    harness
+        .stage(
+            3,
+            Payload::TradeOccurred(trade(1, 2, 1_500_000_000_000, 6_425_015)),
+        )
+        .await;
+
+    harness.flush().await;
+
+    assert_eq!(harness.storage.count("trades").await, 0);
+    assert_eq!(harness.storage.count("candles").await, 0);
+    assert_eq!(harness.storage.count("quarantine").await, 1);
+    // Refused for good, never retried
+    assert!(harness.retrier.scheduled.lock().unwrap().is_empty());
+    assert_eq!(harness.sequence.last(), at(3));
+}
@@ -0,0 +1,141 @@
+use crate::helpers::{Inspect, order, trade};
+use message_persistor::candles::{self, Candle, Interval, Overflow};
+use message_persistor::messages::trading::Side;
+use message_persistor::persistence::{EventMeta, NewTrade};
+use message_persistor::storage::EventTx;
+
+storage_tests!(
+    trades_update_a_candle_per_interval,
+    the_backfill_rebuilds_candles_from_the_trades,
+);
+
+/// 2023-11-14T22:13:20.5Z, 20s into its minute and 200s into its 5 minutes.
+const START_US: i64 = 1_700_000_000_500_000;
+
+fn fill(sequence: i64, at_us: i64, quantity: u64, price: u64) -> NewTrade {
+    let meta = EventMeta {
+        sequence: Some(sequence),
+        engine_timestamp_us: Some(at_us),
+        ..Default::default()
+    };
+    NewTrade::new(trade(1, 2, quantity, price), meta)
+}
+
+/// Persists trades of orders 1 and 2 as the persistor does, returns the
+/// candles of the last one.
+async fn persist(storage: &impl Inspect, trades: Vec<NewTrade>) -> Vec<Candle> {
+    let mut tx = storage.begin().await.unwrap();
+    tx.insert_order(order(1, Side::Sell, 100)).await.unwrap();
+    tx.insert_order(order(2, Side::Buy, 100)).await.unwrap();
+    let mut updated = Vec::new();
+    for trade in trades {
+        assert!(tx.insert_trade(trade.clone()).await.unwrap());
+        updated = candles::record_trade(&mut tx, &trade).await.unwrap();
+    }
+    tx.commit().await.unwrap();
+    updated
+}
+
+fn trades() -> Vec<NewTrade> {
+    vec![
+        fill(1, START_US, 4, 100),
+        fill(2, START_US + 300_000, 2, 105),
+        // The next minute
+        fill(3, START_US + 61_000_000, 1, 95),
+    ]
+}
+
+async fn trades_update_a_candle_per_interval(storage: impl Inspect) {
+    let updated = persist(&storage, trades()).await;
+
+    let intervals: Vec<_> = updated.iter().map(|candle| candle.interval).collect();
+    assert_eq!(intervals, Interval::ALL);
+    let five_minutes = &updated[2];
+    assert_eq!(five_minutes.open_time_us, 1_699_999_800_000_000);
+    assert_eq!(
+        (
+            five_minutes.open,
+            five_minutes.high,
+            five_minutes.low,
+            five_minutes.close
+        ),
+        (100, 105, 95, 95)
+    );
+    assert_eq!(five_minutes.volume, 7);
+    assert_eq!(five_minutes.notional, 4 * 100 + 2 * 105 + 95);
+    assert_eq!(five_minutes.trade_count, 3);
+
+    // The first two trades share a second, the last one has its own
+    assert_eq!(
+        storage
+            .candle(Interval::Second, 1_700_000_000_000_000)
+            .await,
+        Some((100, 105, 100, 105, 6, 2))
+    );
+    assert_eq!(
+        storage
+            .candle(Interval::Minute, 1_700_000_040_000_000)
+            .await,
+        Some((95, 95, 95, 95, 1, 1))
+    );
+    assert_eq!(storage.count("candles").await, 7);
+}
+
+async fn the_backfill_rebuilds_candles_from_the_trades(storage: impl Inspect) {
+    persist(&storage, trades()).await;
+    let mut tx = storage.begin().await.unwrap();
+    // Unstamped trades have no candle
+    let unstamped = NewTrade::new(trade(1, 2, 1, 1), EventMeta::default());
+    tx.insert_trade(unstamped).await.unwrap();
+    // Left over from a trade no longer in the table
+    let stale = Candle::of_trade(&fill(9, 0, 1, 1), Interval::Day).unwrap();
+    tx.upsert_candle(&stale).await.unwrap();
+    tx.commit().await.unwrap();
+
+    assert_eq!(candles::backfill(&storage).await.unwrap(), 3);
+
+    assert_eq!(storage.count("candles").await, 7);
+    assert_eq!(storage.candle(Interval::Day, 0).await, None);
+    assert_eq!(
+        storage.candle(Interval::Hour, 1_699_999_200_000_000).await,
+        Some((100, 105, 95, 95, 7, 3))
+    );
+}
+
+#[test]
+fn candles_open_on_multiples_of_their_interval() {
+    assert_eq!(
+        Interval::Second.open_time_us(START_US),
+        1_700_000_000_000_000
+    );
+    assert_eq!(
+        Interval::Minute.open_time_us(START_US),
+        1_699_999_980_000_000
+    );
+    assert_eq!(Interval::Day.open_time_us(START_US), 1_699_920_000_000_000);
+    assert_eq!(Interval::Minute.open_time_us(-1), -60_000_000);
+}
+
+/// 15000 BTC at 64250.15 USD, in satoshis and cents.
+const LARGE_QUANTITY: u64 = 1_500_000_000_000;
+const LARGE_PRICE: u64 = 6_425_015;
+
+#[test]
+fn a_trade_whose_notional_overflows_has_no_candle() {
+    let large = fill(1, START_US, LARGE_QUANTITY, LARGE_PRICE);
+
+    assert_eq!(large.notional(), None);
+    assert_eq!(Candle::of_trade(&large, Interval::Day), None);
+}
+
+#[test]
+fn candles_are_not_merged_past_their_notional() {
+    // Each fits, not both
+    let half = fill(1, START_US, LARGE_QUANTITY / 2, LARGE_PRICE);
+    let mut candle = Candle::of_trade(&half, Interval::Day).unwrap();
+    let later = Candle::of_trade(&half, Interval::Day).unwrap();
+
+    assert_eq!(candle.merge(&later), Err(Overflow));
+    assert_eq!(candle.trade_count, 1);
+    assert_eq!(candle.notional, 6_425_015 * 750_000_000_000);
+}
@@ -0,0 +1,142 @@
+use crate::helpers::{Inspect, order, trade};
+use message_persistor::messages::trading::{OrderCancelled, OrderRejected, Side};
+use message_persistor::persistence::{EventMeta, NewTrade, is_applied};
+use message_persistor::retry::{is_redriven, redrive_properties, with_retry_count};
+use message_persistor::sequence::Position;
+use message_persistor::storage::EventTx;
+
+storage_tests!(
+    redelivered_events_are_applied_once,
+    the_checkpoint_marks_events_applied,
+    a_new_engine_run_persists_its_sequences_again,
+    a_failed_event_rolls_back_to_its_savepoint,
+);
+
+fn sequenced(sequence: i64) -> EventMeta {
+    EventMeta {
+        sequence: Some(sequence),
+        ..Default::default()
+    }
+}
+
+fn at(epoch: u64, sequence: u64) -> Position {
+    Position { epoch, sequence }
+}
+
+async fn redelivered_events_are_applied_once(storage: impl Inspect) {
+    let mut tx = storage.begin().await.unwrap();
+    assert!(tx.insert_order(order(1, Side::Sell, 10)).await.unwrap());
+    assert!(!tx.insert_order(order(1, Side::Sell, 10)).await.unwrap());
+    assert!(tx.insert_order(order(2, Side::Buy, 4)).await.unwrap());
+
+    let fill = || NewTrade::new(trade(1, 2, 4, 100), sequenced(3));
+    assert!(tx.insert_trade(fill()).await.unwrap());
+    assert!(!tx.insert_trade(fill()).await.unwrap());
+
+    let cancel = || OrderCancelled {
+        order_id: 1,
+        user_id: 10,
+        client_order_id: "client-1".into(),
+    };
+    assert!(tx.cancel_order(cancel(), sequenced(4)).await.unwrap());
+    assert!(!tx.cancel_order(cancel(), sequenced(4)).await.unwrap());
+
+    let reject = || OrderRejected {
+        user_id: 42,
+        client_order_id: "client-9".into(),
+        reason: "insufficient liquidity".into(),
+    };
+    assert!(tx.record_rejection(reject(), sequenced(5)).await.unwrap());
+    assert!(!tx.record_rejection(reject(), sequenced(5)).await.unwrap());
+    tx.commit().await.unwrap();
+
+    assert_eq!(storage.count("trades").await, 1);
+    assert_eq!(
+        storage.order_status(1).await,
+        ("cancelled".into(), 4, Some(100.0))
+    );
+    // Two accepted, two fills, a cancel and a rejection
+    assert_eq!(storage.count("order_events").await, 6);
+}
+
+async fn the_checkpoint_marks_events_applied(storage: impl Inspect) {
+    assert_eq!(storage.checkpoint().await.unwrap(), None);
+    let mut tx = storage.begin().await.unwrap();
+    assert!(!is_applied(&mut tx, 1, 1).await.unwrap());
+
+    tx.set_checkpoint(at(1, 5)).await.unwrap();
+    tx.set_checkpoint(at(1, 6)).await.unwrap();
+    // Never moves back, within a run or to an earlier one
+    tx.set_checkpoint(at(1, 4)).await.unwrap();
+    tx.set_checkpoint(at(0, 9)).await.unwrap();
+
+    assert_eq!(tx.checkpoint().await.unwrap(), Some(at(1, 6)));
+    assert!(is_applied(&mut tx, 1, 6).await.unwrap());
+    assert!(is_applied(&mut tx, 1, 1).await.unwrap());
+    assert!(!is_applied(&mut tx, 1, 7).await.unwrap());
+    // Unsequenced, numbered again by a restarted engine, or from an earlier
+    // run, which the inserts are keyed against
+    assert!(!is_applied(&mut tx, 1, 0).await.unwrap());
+    assert!(!is_applied(&mut tx, 2, 1).await.unwrap());
+    assert!(!is_applied(&mut tx, 0, 3).await.unwrap());
+
+    tx.set_checkpoint(at(2, 1)).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(storage.checkpoint().await.unwrap(), Some(at(2, 1)));
+}
+
+async fn a_new_engine_run_persists_its_sequences_again(storage: impl Inspect) {
+    let in_run = |epoch, sequence| EventMeta {
+        epoch,
+        ..sequenced(sequence)
+    };
+    let mut tx = storage.begin().await.unwrap();
+    tx.insert_order(order(1, Side::Sell, 10)).await.unwrap();
+    tx.insert_order(order(2, Side::Buy, 10)).await.unwrap();
+    let fill = |epoch| NewTrade::new(trade(1, 2, 4, 100), in_run(epoch, 1));
+    assert!(tx.insert_trade(fill(1)).await.unwrap());
+    assert!(tx.insert_trade(fill(2)).await.unwrap());
+    assert!(!tx.insert_trade(fill(2)).await.unwrap());
+
+    let reject = || OrderRejected {
+        user_id: 42,
+        client_order_id: "client-9".into(),
+        reason: "insufficient liquidity".into(),
+    };
+    assert!(tx.record_rejection(reject(), in_run(1, 2)).await.unwrap());
+    assert!(tx.record_rejection(reject(), in_run(2, 2)).await.unwrap());
+    assert!(!tx.record_rejection(reject(), in_run(2, 2)).await.unwrap());
+    tx.commit().await.unwrap();
+
+    assert_eq!(storage.count("trades").await, 2);
+    assert_eq!(
+        storage.order_status(1).await,
+        ("partially_filled".into(), 8, Some(100.0))
+    );
+}
+
+async fn a_failed_event_rolls_back_to_its_savepoint(storage: impl Inspect) {
+    let mut tx = storage.begin().await.unwrap();
+    tx.insert_order(order(1, Side::Sell, 10)).await.unwrap();
+
+    tx.savepoint().await.unwrap();
+    // Neither order exists
+    let orphan = NewTrade::new(trade(98, 99, 1, 100), sequenced(2));
+    assert!(tx.insert_trade(orphan).await.is_err());
+    tx.rollback_to_savepoint().await.unwrap();
+
+    tx.savepoint().await.unwrap();
+    tx.insert_order(order(2, Side::Buy, 10)).await.unwrap();
+    tx.release_savepoint().await.unwrap();
+    tx.commit().await.unwrap();
+
+    assert_eq!(storage.count("orders").await, 2);
+    assert_eq!(storage.count("trades").await, 0);
+}
+
+#[test]
+fn redriven_messages_stay_marked_across_retries() {
+    assert!(!is_redriven(&Default::default()));
+    assert!(is_redriven(&redrive_properties()));
+    assert!(is_redriven(&with_retry_count(redrive_properties(), 2)));
+}
@@ -0,0 +1,10 @@
+#[macro_use]
+mod helpers;
+mod batch;
+mod candles;
+mod idempotency;
+mod orders;
+mod quarantine;
+mod schema;
+mod sequence;
+mod trades;
@@ -0,0 +1,115 @@
+use crate::helpers::{Inspect, order, trade};
+use message_persistor::messages::trading::{OrderCancelled, OrderRejected, Side};
+use message_persistor::persistence::{EventMeta, NewTrade};
+use message_persistor::storage::EventTx;
+
+storage_tests!(
+    fills_then_cancel_update_the_order_and_its_history,
+    rejections_are_recorded_without_an_order,
+    an_order_filled_up_to_the_largest_notional_keeps_its_average_price,
+);
+
+async fn fills_then_cancel_update_the_order_and_its_history(storage: impl Inspect) {
+    let mut tx = storage.begin().await.unwrap();
+    tx.insert_order(order(1, Side::Sell, 10)).await.unwrap();
+    tx.insert_order(order(2, Side::Buy, 4)).await.unwrap();
+    tx.insert_order(order(3, Side::Buy, 2)).await.unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(storage.order_status(1).await, ("open".into(), 0, None));
+
+    let fill = |taker, quantity, price| {
+        NewTrade::new(trade(1, taker, quantity, price), EventMeta::default())
+    };
+    let mut tx = storage.begin().await.unwrap();
+    tx.insert_trade(fill(2, 4, 100)).await.unwrap();
+    tx.insert_trade(fill(3, 2, 103)).await.unwrap();
+    tx.commit().await.unwrap();
+
+    assert_eq!(
+        storage.order_status(1).await,
+        ("partially_filled".into(), 6, Some(101.0))
+    );
+    assert_eq!(
+        storage.order_status(2).await,
+        ("filled".into(), 4, Some(100.0))
+    );
+    assert_eq!(
+        storage.order_status(3).await,
+        ("filled".into(), 2, Some(103.0))
+    );
+
+    let cancelled = OrderCancelled {
+        order_id: 1,
+        user_id: 10,
+        client_order_id: "client-1".into(),
+    };
+    let mut tx = storage.begin().await.unwrap();
+    tx.cancel_order(cancelled, EventMeta::default())
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(
+        storage.order_status(1).await,
+        ("cancelled".into(), 6, Some(101.0))
+    );
+
+    assert_eq!(
+        storage.order_history(1).await,
+        vec![
+            ("accepted".into(), "open".into(), Some(10)),
+            ("fill".into(), "partially_filled".into(), Some(4)),
+            ("fill".into(), "partially_filled".into(), Some(2)),
+            ("cancelled".into(), "cancelled".into(), None),
+        ]
+    );
+}
+
+async fn rejections_are_recorded_without_an_order(storage: impl Inspect) {
+    let rejected = OrderRejected {
+        user_id: 42,
+        client_order_id: "client-9".into(),
+        reason: "insufficient liquidity".into(),
+    };
+
+    let mut tx = storage.begin().await.unwrap();
+    assert!(
+        tx.record_rejection(rejected, EventMeta::default())
+            .await
+            .unwrap()
+    );
+    tx.commit().await.unwrap();
+
+    assert_eq!(
+        storage.rejections().await,
+        vec![(
+            None,
+            Some(42),
+            Some("client-9".into()),
+            Some("insufficient liquidity".into())
+        )]
+    );
+    assert_eq!(storage.count("orders").await, 0);
+}
+
+async fn an_order_filled_up_to_the_largest_notional_keeps_its_average_price(storage: impl Inspect) {
+    // 14000 BTC at 64250.15 USD, just under i64::MAX in satoshis times cents
+    let mut tx = storage.begin().await.unwrap();
+    tx.insert_order(order(1, Side::Sell, 1_400_000_000_000))
+        .await
+        .unwrap();
+    tx.insert_order(order(2, Side::Buy, 1_400_000_000_000))
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        let half = trade(1, 2, 700_000_000_000, 6_425_015);
+        tx.insert_trade(NewTrade::new(half, EventMeta::default()))
+            .await
+            .unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    assert_eq!(
+        storage.order_status(1).await,
+        ("filled".into(), 1_400_000_000_000, Some(6_425_015.0))
+    );
+}
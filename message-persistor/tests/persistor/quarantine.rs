@@ -0,0 +1,45 @@
+use lapin::BasicProperties;
+use message_persistor::quarantine::NewQuarantined;
+use message_persistor::retry::{redrive_properties, retry_count, with_retry_count};
+use message_persistor::storage::Storage;
+
+storage_tests!(redriven_messages_leave_the_pending_list);
+
+fn poison(reason: &str) -> NewQuarantined {
+    NewQuarantined {
+        payload: vec![0xff, 0x00],
+        reason: reason.into(),
+        retries: 5,
+        sequence: Some(12),
+        correlation_id: None,
+    }
+}
+
+async fn redriven_messages_leave_the_pending_list(storage: impl Storage) {
+    let first = storage.quarantine(poison("first")).await.unwrap();
+    let second = storage.quarantine(poison("second")).await.unwrap();
+
+    storage.mark_redriven(first).await.unwrap();
+
+    let pending = storage.list_quarantined(false).await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, second);
+    assert_eq!(pending[0].sequence, Some(12));
+    assert_eq!(pending[0].payload, [0xff, 0x00]);
+    let all = storage.list_quarantined(true).await.unwrap();
+    assert_eq!(all.len(), 2);
+    assert!(all[0].redriven_at.is_some());
+
+    assert!(storage.delete_quarantined(second).await.unwrap());
+    assert!(storage.get_quarantined(second).await.unwrap().is_none());
+}
+
+#[test]
+fn retry_count_is_carried_in_the_headers() {
+    let properties = BasicProperties::default();
+    assert_eq!(retry_count(&properties), 0);
+
+    let retried = with_retry_count(properties, 3);
+    assert_eq!(retry_count(&retried), 3);
+    assert_eq!(retry_count(&redrive_properties()), 0);
+}
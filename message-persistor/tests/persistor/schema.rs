@@ -0,0 +1,32 @@
+use crate::helpers::Inspect;
+use message_persistor::schema::{self, SchemaError, supported_version};
+
+storage_tests!(
+    the_database_is_migrated_to_the_supported_version,
+    a_newer_database_is_refused,
+);
+
+async fn the_database_is_migrated_to_the_supported_version(storage: impl Inspect) {
+    let supported = supported_version(storage.migrator());
+    assert_eq!(schema::version(&storage).await.unwrap(), Some(supported));
+
+    // Nothing pending the second time
+    schema::migrate(&storage).await.unwrap();
+    assert_eq!(schema::version(&storage).await.unwrap(), Some(supported));
+}
+
+async fn a_newer_database_is_refused(storage: impl Inspect) {
+    let supported = supported_version(storage.migrator());
+    storage.record_migration(supported + 1).await;
+
+    match schema::migrate(&storage).await {
+        Err(SchemaError::Newer {
+            database,
+            supported: refused_at,
+        }) => {
+            assert_eq!(database, supported + 1);
+            assert_eq!(refused_at, supported);
+        }
+        other => panic!("expected the newer database to be refused, got {:?}", other),
+    }
+}
@@ -0,0 +1,243 @@
+use crate::helpers::{Inspect, sqlite_storage};
+use message_persistor::amqp_receiver::recover_gap;
+use message_persistor::configuration::EngineSettings;
+use message_persistor::messages::trading::{
+    BatchExecuted, RetransmitComplete, WireMessage, wire_message::Payload,
+};
+use message_persistor::metrics::Metrics;
+use message_persistor::retransmit::RetransmitClient;
+use message_persistor::sequence::{Position, SequenceCheck, SequenceTracker};
+use message_persistor::storage::Storage;
+use prost::Message;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const EPOCH: u64 = 1_700_000_000_000_000;
+
+fn at(sequence: u64) -> Option<Position> {
+    Some(Position {
+        epoch: EPOCH,
+        sequence,
+    })
+}
+
+#[test]
+fn the_first_event_sets_the_baseline() {
+    let mut tracker = SequenceTracker::new();
+
+    assert_eq!(tracker.check(EPOCH, 7), SequenceCheck::Next);
+    tracker.advance(EPOCH, 7);
+    assert_eq!(tracker.check(EPOCH, 8), SequenceCheck::Next);
+    assert_eq!(tracker.check(EPOCH, 7), SequenceCheck::Duplicate);
+    assert_eq!(tracker.check(EPOCH, 0), SequenceCheck::Untracked);
+}
+
+#[test]
+fn skipped_events_are_reported_as_a_gap() {
+    let mut tracker = SequenceTracker::new();
+    tracker.advance(EPOCH, 3);
+
+    assert_eq!(
+        tracker.check(EPOCH, 6),
+        SequenceCheck::Gap { from: 4, to: 5 }
+    );
+    assert_eq!(tracker.check(EPOCH, 1), SequenceCheck::Duplicate);
+}
+
+#[test]
+fn a_restarted_engine_numbers_its_events_from_1_again() {
+    let mut tracker = SequenceTracker::new();
+    tracker.advance(EPOCH, 3);
+
+    assert_eq!(tracker.check(EPOCH + 1, 1), SequenceCheck::Next);
+    assert_eq!(
+        tracker.check(EPOCH + 1, 3),
+        SequenceCheck::Gap { from: 1, to: 2 }
+    );
+    tracker.advance(EPOCH + 1, 1);
+    // Late events of the previous run
+    assert_eq!(tracker.check(EPOCH, 4), SequenceCheck::Untracked);
+    tracker.advance(EPOCH, 4);
+    assert_eq!(
+        tracker.last(),
+        Some(Position {
+            epoch: EPOCH + 1,
+            sequence: 1
+        })
+    );
+}
+
+#[tokio::test]
+async fn fetches_a_range_from_the_engine() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let len = stream.read_u32().await.unwrap();
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await.unwrap();
+        let Some(Payload::RetransmitRequest(request)) =
+            WireMessage::decode(buf.as_slice()).unwrap().payload
+        else {
+            panic!("expected a retransmit request");
+        };
+
+        let mut replies: Vec<WireMessage> = (request.from_sequence..=request.to_sequence)
+            .map(|sequence| WireMessage {
+                sequence,
+                ..Default::default()
+            })
+            .collect();
+        replies.push(WireMessage {
+            payload: Some(Payload::RetransmitComplete(RetransmitComplete {
+                from_sequence: request.from_sequence,
+                to_sequence: request.to_sequence,
+                first_available: 1,
+            })),
+            ..Default::default()
+        });
+        for reply in replies {
+            let buf = reply.encode_to_vec();
+            stream.write_u32(buf.len() as u32).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+        }
+    });
+    let client = RetransmitClient::new(&EngineSettings {
+        host: "127.0.0.1".into(),
+        port,
+        retransmit_timeout_ms: 1000,
+    });
+
+    let retransmission = client.fetch(4, 6).await.unwrap();
+
+    let sequences: Vec<u64> = retransmission.events.iter().map(|e| e.sequence).collect();
+    assert_eq!(sequences, [4, 5, 6]);
+    assert_eq!(retransmission.first_available, 1);
+}
+
+/// A client of an engine in run `epoch` that answers one retransmission
+/// with `events` and `first_available`.
+async fn engine_with(epoch: u64, events: Vec<u64>, first_available: u64) -> RetransmitClient {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let len = stream.read_u32().await.unwrap();
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await.unwrap();
+
+        let mut replies: Vec<WireMessage> = events
+            .into_iter()
+            .map(|sequence| WireMessage {
+                payload: Some(Payload::BatchExecuted(BatchExecuted::default())),
+                sequence,
+                epoch,
+                ..Default::default()
+            })
+            .collect();
+        replies.push(WireMessage {
+            payload: Some(Payload::RetransmitComplete(RetransmitComplete {
+                first_available,
+                ..Default::default()
+            })),
+            epoch,
+            ..Default::default()
+        });
+        for reply in replies {
+            let buf = reply.encode_to_vec();
+            stream.write_u32(buf.len() as u32).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+        }
+    });
+    client_of(port)
+}
+
+fn client_of(port: u16) -> RetransmitClient {
+    RetransmitClient::new(&EngineSettings {
+        host: "127.0.0.1".into(),
+        port,
+        retransmit_timeout_ms: 1000,
+    })
+}
+
+/// Recovers the gap from 4 to 6 after event 3, returns whether it was
+/// recovered and the last position tracked.
+async fn recover_after_3(engine: RetransmitClient) -> (bool, Option<Position>) {
+    let storage = sqlite_storage().await;
+    let mut tracker = SequenceTracker::new();
+    tracker.advance(EPOCH, 3);
+    let recovered = recover_gap(
+        &storage,
+        &Metrics::new(),
+        &mut tracker,
+        &engine,
+        EPOCH,
+        4,
+        6,
+        &mut Vec::new(),
+    )
+    .await;
+    (recovered, tracker.last())
+}
+
+#[tokio::test]
+async fn a_retransmitted_gap_is_persisted() {
+    let storage = sqlite_storage().await;
+    let engine = engine_with(EPOCH, vec![4, 5, 6], 1).await;
+    let mut tracker = SequenceTracker::new();
+    tracker.advance(EPOCH, 3);
+
+    let recovered = recover_gap(
+        &storage,
+        &Metrics::new(),
+        &mut tracker,
+        &engine,
+        EPOCH,
+        4,
+        6,
+        &mut Vec::new(),
+    )
+    .await;
+
+    assert!(recovered);
+    assert_eq!(tracker.last(), at(6));
+    assert_eq!(storage.checkpoint().await.unwrap(), at(6));
+    assert_eq!(storage.count("quarantine").await, 0);
+}
+
+#[tokio::test]
+async fn evicted_events_are_skipped_as_lost() {
+    let engine = engine_with(EPOCH, vec![5, 6], 5).await;
+
+    assert_eq!(recover_after_3(engine).await, (true, at(6)));
+}
+
+#[tokio::test]
+async fn a_gap_is_lost_once_the_engine_restarted() {
+    let engine = engine_with(EPOCH + 1, vec![1, 2], 1).await;
+
+    assert_eq!(recover_after_3(engine).await, (true, at(6)));
+}
+
+#[tokio::test]
+async fn a_gap_is_held_while_the_engine_log_is_empty() {
+    let engine = engine_with(0, Vec::new(), 0).await;
+
+    assert_eq!(recover_after_3(engine).await, (false, at(3)));
+}
+
+#[tokio::test]
+async fn a_gap_is_held_past_the_end_of_a_short_log() {
+    let engine = engine_with(EPOCH, vec![4], 1).await;
+
+    assert_eq!(recover_after_3(engine).await, (false, at(4)));
+}
+
+#[tokio::test]
+async fn a_gap_is_held_when_the_engine_cannot_be_reached() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+
+    assert_eq!(recover_after_3(client_of(port)).await, (false, at(3)));
+}
@@ -0,0 +1,40 @@
+use crate::helpers::{order, sqlite_storage, trade};
+use message_persistor::messages::trading::Side;
+use message_persistor::persistence::{EventMeta, NewTrade};
+use message_persistor::storage::{EventTx, Storage};
+
+#[tokio::test]
+async fn trades_keep_maker_taker_price_and_aggressor() {
+    let storage = sqlite_storage().await;
+    let mut tx = storage.begin().await.unwrap();
+    tx.insert_order(order(1, Side::Sell, 10)).await.unwrap();
+    tx.insert_order(order(2, Side::Buy, 10)).await.unwrap();
+    let meta = EventMeta {
+        sequence: Some(7),
+        epoch: 1_700_000_000_000_000,
+        engine_timestamp_us: Some(1_700_000_000_000_000),
+        correlation_id: Some("req-1".into()),
+    };
+
+    tx.insert_trade(NewTrade::new(trade(1, 2, 4, 100), meta))
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    let row = sqlx::query!(
+        r#"SELECT maker_order_id, taker_order_id, filled_qty, price, aggressor_side, base_currency, quote_currency, sequence, epoch, engine_timestamp_us FROM trades"#
+    )
+    .fetch_one(storage.pool())
+    .await
+    .unwrap();
+    assert_eq!(row.maker_order_id, 1);
+    assert_eq!(row.taker_order_id, 2);
+    assert_eq!(row.filled_qty, Some(4));
+    assert_eq!(row.price, Some(100));
+    assert_eq!(row.aggressor_side, Some(Side::Buy as i64));
+    assert_eq!(row.base_currency.as_deref(), Some("BTC"));
+    assert_eq!(row.quote_currency.as_deref(), Some("USD"));
+    assert_eq!(row.sequence, Some(7));
+    assert_eq!(row.epoch, 1_700_000_000_000_000);
+    assert_eq!(row.engine_timestamp_us, Some(1_700_000_000_000_000));
+}
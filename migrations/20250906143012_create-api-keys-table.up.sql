@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TABLE api_keys (
+    api_key      TEXT PRIMARY KEY,
+    secret_hash  TEXT NOT NULL,
+    user_id      INTEGER NOT NULL,
+    created_at   INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    revoked      INTEGER NOT NULL DEFAULT 0 CHECK (revoked IN (0, 1))
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
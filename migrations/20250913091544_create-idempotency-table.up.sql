@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE idempotency (
+    user_id               INTEGER NOT NULL,
+    idempotency_key       TEXT NOT NULL,
+    request_fingerprint   TEXT NOT NULL,
+    -- NULL until the first request has completed
+    response_status_code  INTEGER,
+    response_content_type TEXT,
+    response_body         BLOB,
+    created_at            INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    PRIMARY KEY (user_id, idempotency_key)
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE trades DROP COLUMN correlation_id;
+ALTER TABLE orders DROP COLUMN correlation_id;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE orders ADD COLUMN correlation_id TEXT;
+ALTER TABLE trades ADD COLUMN correlation_id TEXT;
@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE TABLE quarantine (
+    id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- The delivery as received, a WireMessage unless it failed to decode
+    payload        BLOB NOT NULL,
+    reason         TEXT NOT NULL,
+    retries        INTEGER NOT NULL,
+    -- Decoded from the payload when possible
+    sequence       INTEGER,
+    correlation_id TEXT,
+    quarantined_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    -- NULL until it is published again
+    redriven_at    INTEGER
+);
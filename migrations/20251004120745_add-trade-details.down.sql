@@ -0,0 +1,7 @@
+-- Add down migration script here
+ALTER TABLE trades DROP COLUMN engine_timestamp_us;
+ALTER TABLE trades DROP COLUMN sequence;
+ALTER TABLE trades DROP COLUMN quote_currency;
+ALTER TABLE trades DROP COLUMN base_currency;
+ALTER TABLE trades DROP COLUMN aggressor_side;
+ALTER TABLE trades DROP COLUMN price;
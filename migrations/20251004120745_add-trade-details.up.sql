@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- NULL on trades persisted before these were published
+ALTER TABLE trades ADD COLUMN price INTEGER;
+ALTER TABLE trades ADD COLUMN aggressor_side INTEGER CHECK (aggressor_side IN (1, 2));
+ALTER TABLE trades ADD COLUMN base_currency TEXT REFERENCES instruments(name);
+ALTER TABLE trades ADD COLUMN quote_currency TEXT REFERENCES instruments(name);
+ALTER TABLE trades ADD COLUMN sequence INTEGER;
+ALTER TABLE trades ADD COLUMN engine_timestamp_us INTEGER;
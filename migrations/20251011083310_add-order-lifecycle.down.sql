@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP TABLE order_events;
+ALTER TABLE orders DROP COLUMN average_price;
+ALTER TABLE orders DROP COLUMN filled_notional;
+ALTER TABLE orders DROP COLUMN filled_quantity;
+ALTER TABLE orders DROP COLUMN status;
+ALTER TABLE orders DROP COLUMN client_order_id;
+ALTER TABLE orders DROP COLUMN user_id;
@@ -0,0 +1,30 @@
+-- Add up migration script here
+-- Orders persisted before this migration are assumed open
+ALTER TABLE orders ADD COLUMN user_id INTEGER;
+ALTER TABLE orders ADD COLUMN client_order_id TEXT;
+ALTER TABLE orders ADD COLUMN status TEXT NOT NULL DEFAULT 'open'
+    CHECK (status IN ('open', 'partially_filled', 'filled', 'cancelled'));
+ALTER TABLE orders ADD COLUMN filled_quantity INTEGER NOT NULL DEFAULT 0;
+-- Sum of price * quantity over the fills, average_price is derived from it
+ALTER TABLE orders ADD COLUMN filled_notional INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE orders ADD COLUMN average_price REAL;
+
+CREATE TABLE order_events (
+    id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- NULL for rejections, the engine assigns no id to orders it rejects
+    order_id            INTEGER REFERENCES orders(order_id),
+    user_id             INTEGER,
+    client_order_id     TEXT,
+    event               TEXT NOT NULL CHECK (event IN ('accepted', 'fill', 'cancelled', 'rejected')),
+    -- Status of the order once the event applied
+    status              TEXT NOT NULL
+        CHECK (status IN ('open', 'partially_filled', 'filled', 'cancelled', 'rejected')),
+    -- Of the order when accepted, of the fill otherwise
+    quantity            INTEGER,
+    price               INTEGER,
+    reason              TEXT,
+    sequence            INTEGER,
+    engine_timestamp_us INTEGER,
+    correlation_id      TEXT
+);
+CREATE INDEX order_events_order_id ON order_events (order_id);
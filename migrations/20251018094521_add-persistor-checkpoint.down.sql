@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX order_events_rejected_sequence;
+DROP INDEX trades_sequence;
+DROP TABLE persistor_checkpoint;
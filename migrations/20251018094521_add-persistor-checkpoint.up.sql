@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- Last engine sequence the persistor applied, written in the same
+-- transaction as the event so a redelivered event is recognised
+CREATE TABLE persistor_checkpoint (
+    id            INTEGER PRIMARY KEY CHECK (id = 1),
+    last_sequence INTEGER NOT NULL
+);
+
+-- A redelivered event must not insert its rows twice, even when it bypasses
+-- the checkpoint. Unsequenced rows (NULL) are not constrained.
+CREATE UNIQUE INDEX trades_sequence ON trades (sequence);
+CREATE UNIQUE INDEX order_events_rejected_sequence ON order_events (sequence)
+    WHERE event = 'rejected';
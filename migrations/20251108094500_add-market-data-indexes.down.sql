@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX trades_engine_timestamp_us;
+DROP INDEX orders_open_book;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX trades_maker_order_id;
+DROP INDEX trades_taker_order_id;
+DROP INDEX orders_user_id;
+DROP INDEX trades_instrument_id;
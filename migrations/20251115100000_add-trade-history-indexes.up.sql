@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- Trades of an instrument, newest first
+CREATE INDEX trades_instrument_id ON trades (base_currency, quote_currency, id);
+-- Fills of a user, through their orders
+CREATE INDEX orders_user_id ON orders (user_id);
+CREATE INDEX trades_taker_order_id ON trades (taker_order_id);
+CREATE INDEX trades_maker_order_id ON trades (maker_order_id);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+-- Revoked keys stay revoked, their secrets are gone
+ALTER TABLE api_keys ADD COLUMN secret_hash TEXT NOT NULL DEFAULT '';
+UPDATE api_keys SET revoked = 1;
+ALTER TABLE api_keys DROP COLUMN encrypted_secret;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- The stored hashes were the signing keys themselves, anyone reading this
+-- table could sign requests. Keys issued before are revoked, the secrets of
+-- new ones are encrypted by the gateway.
+ALTER TABLE api_keys ADD COLUMN encrypted_secret TEXT;
+UPDATE api_keys SET revoked = 1;
+ALTER TABLE api_keys DROP COLUMN secret_hash;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- Engine sequences restart at 1 with every run of the engine, the epoch
+-- (its startup time) tells the runs apart. Rows from before epochs are
+-- all in epoch 0.
+ALTER TABLE trades ADD COLUMN epoch INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE order_events ADD COLUMN epoch INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE persistor_checkpoint ADD COLUMN epoch INTEGER NOT NULL DEFAULT 0;
+
+DROP INDEX trades_sequence;
+DROP INDEX order_events_rejected_sequence;
+CREATE UNIQUE INDEX trades_epoch_sequence ON trades (epoch, sequence);
+CREATE UNIQUE INDEX order_events_rejected_epoch_sequence ON order_events (epoch, sequence)
+    WHERE event = 'rejected';
@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP TABLE persistor_checkpoint;
+DROP TABLE quarantine;
+DROP TABLE order_events;
+DROP TABLE trades;
+DROP TABLE orders;
+DROP TABLE instruments;
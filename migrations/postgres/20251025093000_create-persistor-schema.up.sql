@@ -0,0 +1,85 @@
+-- Add up migration script here
+-- The persistor's tables as the SQLite migrations in the parent directory
+-- leave them, the gateway's are not needed here
+CREATE TABLE instruments (
+    name           TEXT PRIMARY KEY,
+    scaling_factor INTEGER NOT NULL
+);
+
+INSERT INTO instruments (name, scaling_factor) VALUES ('USD', 2);
+INSERT INTO instruments (name, scaling_factor) VALUES ('BTC', 8);
+INSERT INTO instruments (name, scaling_factor) VALUES ('ETH', 8);
+
+CREATE TABLE orders (
+    order_id        BIGINT PRIMARY KEY,
+    user_id         BIGINT,
+    client_order_id TEXT,
+    base_currency   TEXT NOT NULL REFERENCES instruments(name),
+    quote_currency  TEXT NOT NULL REFERENCES instruments(name),
+    side            INTEGER NOT NULL CHECK (side IN (1, 2)),
+    quantity        BIGINT NOT NULL,
+    price           BIGINT NOT NULL,
+    status          TEXT NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'partially_filled', 'filled', 'cancelled')),
+    filled_quantity BIGINT NOT NULL DEFAULT 0,
+    -- Sum of price * quantity over the fills, average_price is derived from it
+    filled_notional BIGINT NOT NULL DEFAULT 0,
+    average_price   DOUBLE PRECISION,
+    correlation_id  TEXT
+);
+
+CREATE TABLE trades (
+    id                  BIGSERIAL PRIMARY KEY,
+    taker_order_id      BIGINT NOT NULL REFERENCES orders(order_id),
+    maker_order_id      BIGINT NOT NULL REFERENCES orders(order_id),
+    filled_qty          BIGINT,
+    price               BIGINT,
+    aggressor_side      INTEGER CHECK (aggressor_side IN (1, 2)),
+    base_currency       TEXT REFERENCES instruments(name),
+    quote_currency      TEXT REFERENCES instruments(name),
+    sequence            BIGINT,
+    engine_timestamp_us BIGINT,
+    correlation_id      TEXT
+);
+CREATE UNIQUE INDEX trades_sequence ON trades (sequence);
+
+CREATE TABLE order_events (
+    id                  BIGSERIAL PRIMARY KEY,
+    -- NULL for rejections, the engine assigns no id to orders it rejects
+    order_id            BIGINT REFERENCES orders(order_id),
+    user_id             BIGINT,
+    client_order_id     TEXT,
+    event               TEXT NOT NULL CHECK (event IN ('accepted', 'fill', 'cancelled', 'rejected')),
+    -- Status of the order once the event applied
+    status              TEXT NOT NULL
+        CHECK (status IN ('open', 'partially_filled', 'filled', 'cancelled', 'rejected')),
+    -- Of the order when accepted, of the fill otherwise
+    quantity            BIGINT,
+    price               BIGINT,
+    reason              TEXT,
+    sequence            BIGINT,
+    engine_timestamp_us BIGINT,
+    correlation_id      TEXT
+);
+CREATE INDEX order_events_order_id ON order_events (order_id);
+CREATE UNIQUE INDEX order_events_rejected_sequence ON order_events (sequence)
+    WHERE event = 'rejected';
+
+CREATE TABLE quarantine (
+    id             BIGSERIAL PRIMARY KEY,
+    -- The delivery as received, a WireMessage unless it failed to decode
+    payload        BYTEA NOT NULL,
+    reason         TEXT NOT NULL,
+    retries        BIGINT NOT NULL,
+    -- Decoded from the payload when possible
+    sequence       BIGINT,
+    correlation_id TEXT,
+    quarantined_at BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM now())::BIGINT),
+    -- NULL until it is published again
+    redriven_at    BIGINT
+);
+
+CREATE TABLE persistor_checkpoint (
+    id            INTEGER PRIMARY KEY CHECK (id = 1),
+    last_sequence BIGINT NOT NULL
+);
@@ -0,0 +1,20 @@
+-- Add up migration script here
+-- OHLCV candles per instrument, kept up to date by the persistor from the
+-- trades it applies
+CREATE TABLE candles (
+    base_currency    TEXT NOT NULL REFERENCES instruments(name),
+    quote_currency   TEXT NOT NULL REFERENCES instruments(name),
+    -- 1s, 1m, 5m, 1h or 1d
+    interval_seconds BIGINT NOT NULL CHECK (interval_seconds IN (1, 60, 300, 3600, 86400)),
+    -- Unix time in microseconds, a multiple of the interval
+    open_time_us     BIGINT NOT NULL,
+    open             BIGINT NOT NULL,
+    high             BIGINT NOT NULL,
+    low              BIGINT NOT NULL,
+    close            BIGINT NOT NULL,
+    volume           BIGINT NOT NULL,
+    -- Sum of price * quantity, like orders.filled_notional
+    notional         BIGINT NOT NULL,
+    trade_count      BIGINT NOT NULL,
+    PRIMARY KEY (base_currency, quote_currency, interval_seconds, open_time_us)
+);
@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- Best bid and ask of an instrument, among its resting orders
+CREATE INDEX orders_open_book ON orders (base_currency, quote_currency, side, price)
+    WHERE status IN ('open', 'partially_filled');
+-- Trades of the last 24 hours, for the ticker
+CREATE INDEX trades_engine_timestamp_us ON trades (engine_timestamp_us);
@@ -0,0 +1,11 @@
+-- Add down migration script here
+-- Fails once two engine runs persisted the same sequence
+DROP INDEX order_events_rejected_epoch_sequence;
+DROP INDEX trades_epoch_sequence;
+CREATE UNIQUE INDEX trades_sequence ON trades (sequence);
+CREATE UNIQUE INDEX order_events_rejected_sequence ON order_events (sequence)
+    WHERE event = 'rejected';
+
+ALTER TABLE persistor_checkpoint DROP COLUMN epoch;
+ALTER TABLE order_events DROP COLUMN epoch;
+ALTER TABLE trades DROP COLUMN epoch;
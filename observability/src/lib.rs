@@ -0,0 +1,4 @@
+//! Logging shared by the gateway, the engine and the persistor, and the
+//! health probes of the last two.
+pub mod probes;
+pub mod telemetry;
@@ -0,0 +1,76 @@
+//! Liveness and readiness probes, served over plain HTTP on
+//! `GET /health/live` and `GET /health/ready` next to `GET /metrics`.
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// What `GET /health/ready` reports.
+pub trait Readiness: Send + Sync + 'static {
+    /// Whether the service is ready, along with the JSON body detailing its
+    /// dependencies.
+    fn readiness(&self) -> impl Future<Output = (bool, String)> + Send;
+}
+
+/// What `GET /metrics` reports, in the Prometheus text format.
+pub trait RenderMetrics: Send + Sync + 'static {
+    fn render(&self) -> String;
+}
+
+const JSON: &str = "application/json";
+const PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4";
+
+async fn respond(
+    stream: TcpStream,
+    health: &impl Readiness,
+    metrics: &impl RenderMetrics,
+) -> std::io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    // Skip the headers, probes don't send a body
+    let mut header = String::new();
+    while stream.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+
+    let (status, content_type, body) =
+        match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+            ["GET", "/health/live"] => ("200 OK", JSON, r#"{"status":"ok"}"#.to_string()),
+            ["GET", "/health/ready"] => match health.readiness().await {
+                (true, body) => ("200 OK", JSON, body),
+                (false, body) => ("503 Service Unavailable", JSON, body),
+            },
+            ["GET", "/metrics"] => ("200 OK", PROMETHEUS_TEXT, metrics.render()),
+            _ => (
+                "404 Not Found",
+                JSON,
+                r#"{"status":"not_found"}"#.to_string(),
+            ),
+        };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.get_mut().write_all(response.as_bytes()).await?;
+    stream.get_mut().shutdown().await
+}
+
+pub async fn serve_probes<H: Readiness, M: RenderMetrics>(
+    listener: TcpListener,
+    health: Arc<H>,
+    metrics: Arc<M>,
+) -> std::io::Result<()> {
+    loop {
+        let (stream, _addr) = listener.accept().await?;
+        let health = health.clone();
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &*health, &*metrics).await {
+                tracing::error!(error = %e, "failed to answer health probe");
+            }
+        });
+    }
+}